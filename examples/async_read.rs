@@ -0,0 +1,43 @@
+// Copyright 2017 LambdaStack All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Drives the aio futures with async-std instead of tokio.  smol::block_on
+// or futures::executor::block_on can be swapped in the same way.
+//
+// Run as: `cargo run --example async_read -- <pool>`
+
+extern crate async_std;
+extern crate ceph;
+
+use async_std::task;
+use ceph::Rados;
+use ceph::error::RadosResult;
+
+fn run(pool: &str) -> RadosResult<()> {
+    let cluster = Rados::connect("admin", "/etc/ceph/ceph.conf")?;
+    let ioctx = cluster.ioctx(pool)?;
+
+    task::block_on(ioctx.write_full_async("async_read", b"hello from async-std")?)?;
+    let data = task::block_on(ioctx.read_async("async_read", 64, 0)?)?;
+    println!("Read back: {}", String::from_utf8_lossy(&data));
+    task::block_on(ioctx.remove_async("async_read")?)?;
+    Ok(())
+}
+
+fn main() {
+    let pool = std::env::args().nth(1).unwrap_or_else(|| "rbd".to_string());
+    if let Err(e) = run(&pool) {
+        println!("Error: {}", e);
+    }
+}
@@ -52,7 +52,9 @@ fn main() {
     println!("Librados version: {:?}", rados_version);
 
     println!("Connecting to ceph");
-    let cluster = ceph_helpers::connect_to_ceph("admin", "/etc/ceph/ceph.conf").unwrap();
+    // The Rados handle shuts the connection down when it goes out of scope
+    let rados = ceph::Rados::connect("admin", "/etc/ceph/ceph.conf").unwrap();
+    let cluster = rados.as_raw();
     println!("Creating pool {}", pool_name);
     ceph_helpers::rados_create_pool(cluster, pool_name).unwrap();
 
@@ -160,10 +162,7 @@ fn main() {
         // will be called soon to replace the string parse.
         let ceph_ver = ceph_helpers::ceph_version("/var/run/ceph/ceph-mon.ceph-vm1.asok"); // Change to the real mon admin socket name
         println!("Ceph Version - {:?}", ceph_ver);
-
-        rados::rados_shutdown(cluster);
     }
 
-    ceph_helpers::disconnect_from_ceph(cluster);
     println!("RADOS Version - v{}.{}.{}", rados_version.major, rados_version.minor, rados_version.extra);
 }
@@ -4,5 +4,5 @@ use ceph::CephClient;
 
 pub fn main() {
     let ceph_client = CephClient::new("admin", "/etc/ceph/ceph.conf").unwrap();
-    println!("Status: {}", ceph_client.status().unwrap());
+    println!("Status: {:#?}", ceph_client.status().unwrap());
 }
@@ -0,0 +1,299 @@
+//! Object io as `std::future::Future`s.  A future registers the waker of
+//! the task polling it and librados wakes it from its completion callback,
+//! so nothing here depends on a particular runtime: the futures run on
+//! async-std, smol, tokio or a plain `block_on` alike.  See
+//! `examples/async_read.rs` for async-std.
+//!
+//! The futures borrow the io context and aren't `Send`, so they belong on a
+//! local executor or in `block_on` rather than in a spawned task.
+//! Dropping one before it finishes cancels the operation and blocks until
+//! librados lets go of its buffer.  The blocking `IoCtx::aio_read` and
+//! `IoCtx::aio_write` wait on these futures in place, woken by librados or
+//! by their `CancellationToken`.
+//!
+//! Cancelling needs `rados_aio_cancel` from the Luminous librados, so it
+//! only happens with the `luminous` feature on.  Without it a dropped or
+//! cancelled operation still runs to the end first.
+
+use std::ffi::CString;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use libc::{c_char, c_int, c_void, ECANCELED};
+
+use cancel::CancellationToken;
+use ceph::{get_error, IoCtx};
+use error::{RadosError, RadosResult};
+use rados::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn it_wakes_the_registered_task() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let state = Box::new(Mutex::new(AioState::default()));
+        lock(&state).waker = Some(Waker::from(counter.clone()));
+
+        aio_complete(ptr::null_mut(), &*state as *const _ as *mut c_void);
+        assert!(lock(&state).complete);
+        assert!(lock(&state).waker.is_none());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn it_completes_before_anyone_waits() {
+        let state = Box::new(Mutex::new(AioState::default()));
+        aio_complete(ptr::null_mut(), &*state as *const _ as *mut c_void);
+        assert!(lock(&state).complete);
+    }
+}
+
+// Shared with the librbd completions in `rbd`
+#[derive(Debug, Default)]
+pub(crate) struct AioState {
+    pub(crate) complete: bool,
+    pub(crate) waker: Option<Waker>,
+}
+
+// A panic must not unwind into librados, so a poisoned lock is used as is
+pub(crate) fn lock(state: &Mutex<AioState>) -> MutexGuard<AioState> {
+    match state.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+// Runs on a librados thread when the operation completes
+pub(crate) extern "C" fn aio_complete(_completion: rados_completion_t, arg: *mut c_void) {
+    let state = unsafe { &*(arg as *const Mutex<AioState>) };
+    let waker = {
+        let mut state = lock(state);
+        state.complete = true;
+        state.waker.take()
+    };
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+/// A pending librados operation.  It resolves once the cluster has
+/// answered, whichever executor polls it.
+pub struct AioFuture<'a, T> {
+    // Only needed to cancel
+    #[cfg_attr(not(feature = "luminous"), allow(dead_code))]
+    ioctx: rados_ioctx_t,
+    completion: rados_completion_t,
+    // Boxed so the callback's pointer to it stays put when the future moves
+    state: Box<Mutex<AioState>>,
+    // What librados reads into, taken when the future resolves
+    buffer: Option<Vec<u8>>,
+    finish: fn(c_int, Vec<u8>) -> T,
+    io: PhantomData<&'a ()>,
+}
+
+impl<'a, T> Future for AioFuture<'a, T> {
+    type Output = RadosResult<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<RadosResult<T>> {
+        let this = self.get_mut();
+        {
+            let mut state = lock(&this.state);
+            if !state.complete {
+                // Only the task that polled last is woken
+                state.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+        let buffer = this.buffer.take().expect("AioFuture polled after it resolved");
+        let ret_code = unsafe { rados_aio_get_return_value(this.completion) };
+        if ret_code == -ECANCELED {
+            return Poll::Ready(Err(RadosError::Cancelled));
+        } else if ret_code < 0 {
+            return Poll::Ready(match get_error(ret_code) {
+                Ok(e) => Err(RadosError::new(e)),
+                Err(e) => Err(e),
+            });
+        }
+        Poll::Ready(Ok((this.finish)(ret_code, buffer)))
+    }
+}
+
+impl<'a, T> AioFuture<'a, T> {
+    // Ask librados to drop the operation.  It still completes, with
+    // ECANCELED unless it got done first.
+    #[cfg(feature = "luminous")]
+    fn cancel(&self) {
+        unsafe {
+            rados_aio_cancel(self.ioctx, self.completion);
+        }
+    }
+
+    // librados before Luminous can't cancel, the operation just runs to the end
+    #[cfg(not(feature = "luminous"))]
+    fn cancel(&self) {}
+}
+
+impl<'a, T> Drop for AioFuture<'a, T> {
+    fn drop(&mut self) {
+        if !lock(&self.state).complete {
+            self.cancel();
+        }
+        unsafe {
+            // The buffer and the state have to outlive the callback
+            rados_aio_wait_for_complete_and_cb(self.completion);
+            rados_aio_release(self.completion);
+        }
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// Block the calling thread until the future resolves, cancelling the
+// operation once the token is cancelled
+pub(crate) fn wait<T>(mut future: AioFuture<T>, token: &CancellationToken) -> RadosResult<T> {
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let _registration = token.register(waker.clone());
+    let mut cancelled = false;
+    loop {
+        if !cancelled && token.is_cancelled() {
+            future.cancel();
+            cancelled = true;
+        }
+        if let Poll::Ready(result) = Pin::new(&mut future).poll(&mut cx) {
+            return result;
+        }
+        // Unparked by the completion callback or by the token
+        thread::park();
+    }
+}
+
+fn finish_read(read: c_int, mut buffer: Vec<u8>) -> Vec<u8> {
+    unsafe {
+        buffer.set_len(read as usize);
+    }
+    buffer
+}
+
+fn finish_unit(_: c_int, _: Vec<u8>) {}
+
+impl<'a> IoCtx<'a> {
+    /// Read up to `len` bytes of an object starting at `offset`
+    pub fn read_async(&self, object: &str, len: usize, offset: u64) -> RadosResult<AioFuture<Vec<u8>>> {
+        let object_str = CString::new(object)?;
+        self.start_aio(Vec::with_capacity(len), finish_read, |io, completion, buffer| unsafe {
+            rados_aio_read(
+                io,
+                object_str.as_ptr(),
+                completion,
+                buffer.as_mut_ptr() as *mut c_char,
+                len,
+                offset,
+            )
+        })
+    }
+
+    /// Write `data` to an object at `offset`.  librados copies the data, so
+    /// it need not outlive the call.
+    pub fn write_async(&self, object: &str, data: &[u8], offset: u64) -> RadosResult<AioFuture<()>> {
+        let object_str = CString::new(object)?;
+        self.start_aio(Vec::new(), finish_unit, |io, completion, _| unsafe {
+            rados_aio_write(
+                io,
+                object_str.as_ptr(),
+                completion,
+                data.as_ptr() as *const c_char,
+                data.len(),
+                offset,
+            )
+        })
+    }
+
+    /// Replace the whole object with `data`
+    pub fn write_full_async(&self, object: &str, data: &[u8]) -> RadosResult<AioFuture<()>> {
+        let object_str = CString::new(object)?;
+        self.start_aio(Vec::new(), finish_unit, |io, completion, _| unsafe {
+            rados_aio_write_full(io, object_str.as_ptr(), completion, data.as_ptr() as *const c_char, data.len())
+        })
+    }
+
+    pub fn append_async(&self, object: &str, data: &[u8]) -> RadosResult<AioFuture<()>> {
+        let object_str = CString::new(object)?;
+        self.start_aio(Vec::new(), finish_unit, |io, completion, _| unsafe {
+            rados_aio_append(io, object_str.as_ptr(), completion, data.as_ptr() as *const c_char, data.len())
+        })
+    }
+
+    pub fn remove_async(&self, object: &str) -> RadosResult<AioFuture<()>> {
+        let object_str = CString::new(object)?;
+        self.start_aio(Vec::new(), finish_unit, |io, completion, _| unsafe {
+            rados_aio_remove(io, object_str.as_ptr(), completion)
+        })
+    }
+
+    /// Resolves once every write started before it is safe on disk
+    pub fn flush_async(&self) -> RadosResult<AioFuture<()>> {
+        self.start_aio(Vec::new(), finish_unit, |io, completion, _| unsafe {
+            rados_aio_flush_async(io, completion)
+        })
+    }
+
+    // Create a completion wired to a fresh state and hand it to `submit`
+    fn start_aio<T, F>(&self, mut buffer: Vec<u8>, finish: fn(c_int, Vec<u8>) -> T, submit: F)
+        -> RadosResult<AioFuture<T>>
+    where
+        F: FnOnce(rados_ioctx_t, rados_completion_t, &mut Vec<u8>) -> c_int,
+    {
+        let state = Box::new(Mutex::new(AioState::default()));
+        let mut completion: rados_completion_t = ptr::null_mut();
+        unsafe {
+            let ret_code = rados_aio_create_completion(
+                &*state as *const Mutex<AioState> as *mut c_void,
+                Some(aio_complete),
+                None,
+                &mut completion,
+            );
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            let ret_code = submit(self.as_raw(), completion, &mut buffer);
+            if ret_code < 0 {
+                // Nothing will complete it, so it mustn't reach the waiting drop
+                rados_aio_release(completion);
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(AioFuture {
+            ioctx: self.as_raw(),
+            completion: completion,
+            state: state,
+            buffer: Some(buffer),
+            finish: finish,
+            io: PhantomData,
+        })
+    }
+}
@@ -0,0 +1,457 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+
+use buffer_pool;
+use ceph::{mgr_command_bytes, mon_command_bytes, osd_command_bytes, pg_command_bytes};
+use cmd::get_command_descriptions;
+use error::{RadosError, RadosResult};
+use log::LogLevel;
+use rados::rados_t;
+use serde_json::Value;
+use utils::log_snippet;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cmd;
+    use PoolOption;
+
+    #[test]
+    fn it_parses_canned_output() {
+        let backend = MockBackend::new()
+            .respond("osd lspools", r#"[{"poolnum":1,"poolname":"rbd"},{"poolnum":2,"poolname":"cephfs_data"}]"#);
+
+        let pools = cmd::osd_lspools(&backend).unwrap();
+        assert_eq!(pools, vec![(1, "rbd".to_string()), (2, "cephfs_data".to_string())]);
+        assert_eq!(backend.commands(), vec![json!({"prefix": "osd lspools", "format": "json"})]);
+    }
+
+    #[test]
+    #[cfg(feature = "nautilus")]
+    fn it_routes_mgr_commands() {
+        let backend = MockBackend::new()
+            .respond("fs subvolumegroup ls", "[\n  {\n    \"name\": \"csi\"\n  }\n]\n");
+
+        let groups = cmd::fs_subvolumegroup_ls(&backend, "cephfs").unwrap();
+        assert_eq!(groups, vec!["csi"]);
+        assert_eq!(backend.commands()[0]["vol_name"], json!("cephfs"));
+    }
+
+    #[test]
+    fn it_routes_pg_commands() {
+        let backend = MockBackend::new().respond("query", r#"{"state":"active+clean"}"#);
+
+        let (output, _) = backend.pg_command("1.0", &json!({"prefix": "query"}), &[]).unwrap();
+        assert_eq!(output.unwrap(), r#"{"state":"active+clean"}"#);
+        assert_eq!(backend.commands(), vec![json!({"prefix": "query"})]);
+    }
+
+    #[test]
+    fn it_records_commands_without_output() {
+        let backend = MockBackend::new();
+
+        cmd::osd_out(&backend, 3).unwrap();
+        assert_eq!(backend.commands(), vec![json!({"prefix": "osd out", "ids": ["3"]})]);
+    }
+
+    #[test]
+    fn it_wants_confirmation_before_losing_an_osd() {
+        let backend = MockBackend::new();
+
+        assert!(cmd::osd_lost(&backend, 3, false).is_err());
+        assert!(backend.commands().is_empty());
+        cmd::osd_lost(&backend, 3, true).unwrap();
+        assert_eq!(backend.commands()[0]["sure"], json!("--yes-i-really-mean-it"));
+    }
+
+    #[test]
+    fn it_fails_on_mon_errors() {
+        let backend = MockBackend::new().fail("osd pool get", "ENOENT: unrecognized pool 'nope'");
+
+        assert!(cmd::osd_pool_get(&backend, "nope", &PoolOption::Size).is_err());
+    }
+
+    #[test]
+    fn it_names_the_command_that_failed_to_parse() {
+        let backend = MockBackend::new().respond("osd find", "not json");
+
+        match cmd::osd_find(&backend, 7) {
+            Err(RadosError::CommandFailed { prefix, args, .. }) => {
+                assert_eq!(prefix, "osd find");
+                assert_eq!(args, vec!["format", "id"]);
+            },
+            other => panic!("expected CommandFailed, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn it_spots_output_with_secrets() {
+        assert!(holds_secrets("auth get-or-create"));
+        assert!(holds_secrets("auth export"));
+        assert!(holds_secrets("config-key get"));
+        assert!(!holds_secrets("osd tree"));
+    }
+
+    #[test]
+    fn it_keeps_argument_values_out_of_errors() {
+        let cmd = json!({"prefix": "config-key set", "key": "rgw/secret", "val": "hunter2"});
+        let e = RadosError::command_failed(&cmd, RadosError::new("EIO".to_string()));
+        assert_eq!(e.to_string(), "config-key set (key, val) failed: EIO");
+    }
+
+    #[test]
+    fn it_plans_mutating_commands() {
+        let backend = MockBackend::new()
+            .respond(
+                "get_command_descriptions",
+                r#"{"cmd000":{"sig":["osd","lspools"],"help":"list pools","module":"osd","perm":"r"},
+                    "cmd001":{"sig":["osd","pool","set",{"name":"pool","type":"CephPoolname"}],
+                              "help":"set pool parameter","module":"osd","perm":"rw"}}"#,
+            )
+            .respond("osd lspools", r#"[{"poolnum":1,"poolname":"rbd"}]"#);
+
+        let planned = dry_run(&backend, |cluster| {
+            for (_, pool) in cmd::osd_lspools(cluster)? {
+                cmd::osd_pool_set(cluster, &pool, &PoolOption::Size, "3")?;
+            }
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(
+            planned,
+            vec![json!({"prefix": "osd pool set", "pool": "rbd", "var": "size", "val": "3"})]
+        );
+        let sent: Vec<Value> = backend.commands().into_iter().map(|c| c["prefix"].clone()).collect();
+        assert_eq!(sent, vec![json!("get_command_descriptions"), json!("osd lspools")]);
+    }
+
+    #[test]
+    fn it_plans_commands_whose_reply_is_parsed() {
+        let backend = MockBackend::new().respond("get_command_descriptions", "{}");
+
+        let planned = dry_run(&backend, |cluster| {
+            let id = cmd::osd_create(cluster, None)?;
+            cmd::osd_crush_add(cluster, id, 1.0, "node1")?;
+            cmd::osd_reweight_by_utilization(cluster, None, None, None, false)?;
+            cmd::osd_setmaxosd(cluster, 16)
+        }).unwrap();
+
+        let prefixes: Vec<Value> = planned.into_iter().map(|c| c["prefix"].clone()).collect();
+        assert_eq!(
+            prefixes,
+            vec![json!("osd create"), json!("osd crush add"), json!("osd reweight-by-utilization")]
+        );
+    }
+
+    #[test]
+    fn it_keeps_dry_run_errors_from_reads() {
+        let backend = MockBackend::new()
+            .respond(
+                "get_command_descriptions",
+                r#"{"cmd000":{"sig":["osd","lspools"],"help":"list pools","perm":"r"}}"#,
+            )
+            .fail("osd lspools", "EIO");
+
+        assert!(dry_run(&backend, |cluster| cmd::osd_lspools(cluster)).is_err());
+    }
+}
+
+/// Where the commands built in `cmd` are sent.  Librados is the real
+/// implementation and `MockBackend` stands in for a cluster in tests.
+pub trait CephBackend {
+    /// Send a json command to the monitors, or to a single monitor when a
+    /// target is given
+    fn mon_command_bytes(&self, target: Option<&str>, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)>;
+
+    /// Send a json command to the active mgr
+    fn mgr_command_bytes(&self, cmd: &Value, input: &[u8]) -> RadosResult<(Option<Vec<u8>>, Option<String>)>;
+
+    /// Send a json command to a single osd
+    fn osd_command_bytes(&self, osd_id: i32, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)>;
+
+    /// Send a json command to the primary osd of a pg, the way
+    /// `ceph pg <pgid> query` does
+    fn pg_command_bytes(&self, pgid: &str, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)>;
+
+    /// Whether mutating commands are only recorded instead of run.  They get
+    /// no reply then, so the `cmd` functions that read one return a
+    /// placeholder instead.
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+
+    fn mon_command(&self, cmd: &Value, input: &[u8]) -> RadosResult<(Option<String>, Option<String>)> {
+        let (outbuf, outs) = self.mon_command_bytes(None, cmd, input)?;
+        Ok((outbuf.map(reply_string), outs))
+    }
+
+    fn mon_command_target(&self, mon_name: &str, cmd: &Value) -> RadosResult<(Option<String>, Option<String>)> {
+        let (outbuf, outs) = self.mon_command_bytes(Some(mon_name), cmd, &[])?;
+        Ok((outbuf.map(reply_string), outs))
+    }
+
+    fn mgr_command(&self, cmd: &Value, input: &[u8]) -> RadosResult<(Option<String>, Option<String>)> {
+        let (outbuf, outs) = self.mgr_command_bytes(cmd, input)?;
+        Ok((outbuf.map(reply_string), outs))
+    }
+
+    fn osd_command(&self, osd_id: i32, cmd: &Value, input: &[u8]) -> RadosResult<(Option<String>, Option<String>)> {
+        let (outbuf, outs) = self.osd_command_bytes(osd_id, cmd, input)?;
+        Ok((outbuf.map(reply_string), outs))
+    }
+
+    fn pg_command(&self, pgid: &str, cmd: &Value, input: &[u8]) -> RadosResult<(Option<String>, Option<String>)> {
+        let (outbuf, outs) = self.pg_command_bytes(pgid, cmd, input)?;
+        Ok((outbuf.map(reply_string), outs))
+    }
+}
+
+// The reply as text, handing its buffer back to the pool
+fn reply_string(buf: Vec<u8>) -> String {
+    let reply = String::from_utf8_lossy(&buf).into_owned();
+    buffer_pool::recycle(buf);
+    reply
+}
+
+impl CephBackend for rados_t {
+    fn mon_command_bytes(&self, target: Option<&str>, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        log_response(cmd, mon_command_bytes(*self, target, cmd, input))
+    }
+
+    fn mgr_command_bytes(&self, cmd: &Value, input: &[u8]) -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        log_response(cmd, mgr_command_bytes(*self, cmd, input))
+    }
+
+    fn osd_command_bytes(&self, osd_id: i32, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        log_response(cmd, osd_command_bytes(*self, osd_id, cmd, input))
+    }
+
+    fn pg_command_bytes(&self, pgid: &str, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        log_response(cmd, pg_command_bytes(*self, pgid, cmd, input))
+    }
+}
+
+// Record what a command sent back.  The output itself only goes to trace
+// since it can be large, and not at all when it can hold secrets.  The
+// status string and failures go to debug.
+// Failures are tagged with the command so callers can tell which one broke.
+fn log_response(cmd: &Value, result: RadosResult<(Option<Vec<u8>>, Option<String>)>)
+    -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+    let prefix = cmd["prefix"].as_str().unwrap_or_default();
+    match result {
+        Ok((ref outbuf, ref outs)) => {
+            if let Some(ref outs) = *outs {
+                debug!("{} status: {}", prefix, outs);
+            }
+            if log_enabled!(LogLevel::Trace) {
+                match *outbuf {
+                    Some(_) if holds_secrets(prefix) => trace!("{} output withheld", prefix),
+                    Some(ref buf) => trace!("{} output: {}", prefix, log_snippet(&String::from_utf8_lossy(buf))),
+                    None => trace!("{} returned no output", prefix),
+                }
+            }
+        },
+        Err(e) => {
+            debug!("{} failed: {}", prefix, e);
+            return Err(RadosError::command_failed(cmd, e));
+        },
+    }
+    result
+}
+
+// Whether a command's output can hold cephx keys or config-key values,
+// which must not end up in a log
+fn holds_secrets(prefix: &str) -> bool {
+    prefix.starts_with("auth") || prefix.starts_with("config-key")
+}
+
+/// A backend that never talks to a cluster.  Every command sent to it is
+/// recorded and answered with the output registered for its prefix.
+/// Commands without a registered output succeed with no output, the way
+/// most mutating commands do.
+///
+/// ```
+/// # use ceph::{cmd, MockBackend};
+/// let backend = MockBackend::new()
+///     .respond("osd lspools", r#"[{"poolnum":1,"poolname":"rbd"}]"#);
+/// let pools = cmd::osd_lspools(&backend).unwrap();
+/// assert_eq!(pools, vec![(1, "rbd".to_string())]);
+/// assert_eq!(backend.commands()[0]["prefix"], "osd lspools");
+/// ```
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    responses: HashMap<String, Result<Vec<u8>, String>>,
+    commands: RefCell<Vec<Value>>,
+}
+
+impl MockBackend {
+    pub fn new() -> MockBackend {
+        MockBackend::default()
+    }
+
+    /// Answer every command with this prefix with the given output
+    pub fn respond(self, prefix: &str, output: &str) -> MockBackend {
+        self.respond_bytes(prefix, output.as_bytes())
+    }
+
+    /// Answer every command with this prefix with binary output such as an
+    /// encoded map
+    pub fn respond_bytes(mut self, prefix: &str, output: &[u8]) -> MockBackend {
+        self.responses.insert(prefix.to_string(), Ok(output.to_vec()));
+        self
+    }
+
+    /// Fail every command with this prefix with the given error message
+    pub fn fail(mut self, prefix: &str, message: &str) -> MockBackend {
+        self.responses.insert(prefix.to_string(), Err(message.to_string()));
+        self
+    }
+
+    /// The commands sent so far, oldest first
+    pub fn commands(&self) -> Vec<Value> {
+        self.commands.borrow().clone()
+    }
+
+    fn handle(&self, cmd: &Value) -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        self.commands.borrow_mut().push(cmd.clone());
+        let prefix = cmd["prefix"].as_str().unwrap_or_default();
+        match self.responses.get(prefix) {
+            Some(&Ok(ref output)) => Ok((Some(output.clone()), None)),
+            Some(&Err(ref message)) => Err(RadosError::new(message.clone())),
+            None => Ok((None, None)),
+        }
+    }
+}
+
+impl CephBackend for MockBackend {
+    fn mon_command_bytes(&self, _target: Option<&str>, cmd: &Value, _input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        self.handle(cmd)
+    }
+
+    fn mgr_command_bytes(&self, cmd: &Value, _input: &[u8]) -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        self.handle(cmd)
+    }
+
+    fn osd_command_bytes(&self, _osd_id: i32, cmd: &Value, _input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        self.handle(cmd)
+    }
+
+    fn pg_command_bytes(&self, _pgid: &str, cmd: &Value, _input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        self.handle(cmd)
+    }
+}
+
+/// Sends the read only commands on to another backend and records the rest
+/// instead of running them.  Whether a command only reads is taken from the
+/// permissions the monitors list for it, so anything they don't know about
+/// is treated as a change.
+pub struct DryRunBackend<'a> {
+    inner: &'a dyn CephBackend,
+    read_only: HashSet<String>,
+    planned: RefCell<Vec<Value>>,
+    // Whether the last command was planned rather than sent
+    last_planned: Cell<bool>,
+}
+
+impl<'a> DryRunBackend<'a> {
+    pub fn new(inner: &'a dyn CephBackend) -> RadosResult<DryRunBackend<'a>> {
+        let read_only = get_command_descriptions(inner)?
+            .iter()
+            .filter(|desc| desc.perm == "r")
+            .map(|desc| desc.prefix())
+            .collect();
+        Ok(DryRunBackend {
+            inner: inner,
+            read_only: read_only,
+            planned: RefCell::new(Vec::new()),
+            last_planned: Cell::new(false),
+        })
+    }
+
+    /// The commands that would have changed the cluster, oldest first
+    pub fn planned(&self) -> Vec<Value> {
+        self.planned.borrow().clone()
+    }
+
+    // Every command is checked here before it goes anywhere, so this is also
+    // where the last one is noted
+    fn is_read_only(&self, cmd: &Value) -> bool {
+        let read_only = match cmd["prefix"].as_str() {
+            Some(prefix) => self.read_only.contains(prefix),
+            None => false,
+        };
+        self.last_planned.set(!read_only);
+        read_only
+    }
+
+    fn plan(&self, cmd: &Value) -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        self.planned.borrow_mut().push(cmd.clone());
+        Ok((None, None))
+    }
+}
+
+impl<'a> CephBackend for DryRunBackend<'a> {
+    fn mon_command_bytes(&self, target: Option<&str>, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        match self.is_read_only(cmd) {
+            true => self.inner.mon_command_bytes(target, cmd, input),
+            false => self.plan(cmd),
+        }
+    }
+
+    fn mgr_command_bytes(&self, cmd: &Value, input: &[u8]) -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        match self.is_read_only(cmd) {
+            true => self.inner.mgr_command_bytes(cmd, input),
+            false => self.plan(cmd),
+        }
+    }
+
+    fn osd_command_bytes(&self, osd_id: i32, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        match self.is_read_only(cmd) {
+            true => self.inner.osd_command_bytes(osd_id, cmd, input),
+            false => self.plan(cmd),
+        }
+    }
+
+    fn pg_command_bytes(&self, pgid: &str, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        match self.is_read_only(cmd) {
+            true => self.inner.pg_command_bytes(pgid, cmd, input),
+            false => self.plan(cmd),
+        }
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+/// Run the `cmd` calls in `f` as a dry run and return the json of every
+/// command that would have changed the cluster, like a plan to review
+/// before applying it.  Functions that return something from a mutating
+/// command, such as the id from `osd_create`, return a placeholder in here.
+/// Anything that fails on the missing reply of a planned command ends the
+/// plan there instead, since what `f` does next depends on that reply.
+pub fn dry_run<F, T>(cluster: &dyn CephBackend, f: F) -> RadosResult<Vec<Value>>
+where
+    F: FnOnce(&dyn CephBackend) -> RadosResult<T>,
+{
+    let backend = DryRunBackend::new(cluster)?;
+    if let Err(e) = f(&backend) {
+        if !backend.last_planned.get() {
+            return Err(e);
+        }
+        debug!("dry run stopped after a planned command: {}", e);
+    }
+    Ok(backend.planned())
+}
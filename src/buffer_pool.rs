@@ -0,0 +1,175 @@
+//! A pool of read buffers shared by the whole crate.  `IoCtx::read_full`,
+//! `rados_object_read` and the mon, mgr and osd command responses take
+//! their buffers from it, so a busy client stops allocating a fresh one for
+//! every call.  The pool is empty and keeps nothing until `set_limits` is
+//! called.
+//!
+//! Buffers inside the crate go back to the pool by themselves.  The ones
+//! handed out, like the data from `read_full`, only come back through
+//! `recycle`.
+//!
+//! Object listing doesn't use the pool.  librados keeps each name in its
+//! own memory and the listing copies it into the strings of the
+//! `CephObject` the caller keeps, so there is no buffer to hand back.
+//!
+//! ```rust,no_run
+//! # use ceph::Rados;
+//! # use ceph::buffer_pool;
+//! # use ceph::error::RadosError;
+//! # fn main() {
+//! #   let _ = run();
+//! # }
+//! # fn run() -> Result<(), RadosError> {
+//! // Keep up to 32 buffers of at most 4MiB each
+//! buffer_pool::set_limits(4 * 1024 * 1024, 32);
+//!
+//! let cluster = Rados::connect("admin", "/etc/ceph/ceph.conf")?;
+//! let ioctx = cluster.ioctx("rbd")?;
+//! for _ in 0..1000 {
+//!     let data = ioctx.read_full("config")?;
+//!     println!("{} bytes", data.len());
+//!     buffer_pool::recycle(data);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reuses_buffers() {
+        let mut pool = Pool::new();
+        pool.set_limits(1024, 2);
+        let mut buffer = pool.take(100);
+        buffer.extend_from_slice(b"stale");
+        let ptr = buffer.as_ptr();
+        pool.recycle(buffer);
+        assert_eq!(pool.buffers.len(), 1);
+
+        // Too big for the pooled buffer
+        let big = pool.take(500);
+        assert_ne!(big.as_ptr(), ptr);
+        let again = pool.take(50);
+        assert_eq!(again.as_ptr(), ptr);
+        assert!(again.is_empty());
+        assert!(pool.buffers.is_empty());
+    }
+
+    #[test]
+    fn it_respects_the_limits() {
+        let mut pool = Pool::new();
+        pool.recycle(Vec::with_capacity(10));
+        assert!(pool.buffers.is_empty());
+
+        pool.set_limits(100, 2);
+        pool.recycle(Vec::with_capacity(200));
+        assert!(pool.buffers.is_empty());
+        for _ in 0..3 {
+            pool.recycle(Vec::with_capacity(10));
+        }
+        assert_eq!(pool.buffers.len(), 2);
+
+        // Lowering the limits drops what no longer fits
+        pool.set_limits(100, 1);
+        assert_eq!(pool.buffers.len(), 1);
+        pool.set_limits(5, 1);
+        assert!(pool.buffers.is_empty());
+    }
+
+    #[test]
+    fn it_skips_the_lock_while_off() {
+        // The only test that touches the shared pool
+        set_limits(64, 1);
+        assert!(ENABLED.load(Ordering::Relaxed));
+        set_limits(0, 1);
+        assert!(!ENABLED.load(Ordering::Relaxed));
+        recycle(Vec::with_capacity(10));
+        assert_eq!(pooled(), 0);
+    }
+}
+
+struct Pool {
+    max_buffer_size: usize,
+    max_buffers: usize,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl Pool {
+    const fn new() -> Pool {
+        Pool {
+            max_buffer_size: 0,
+            max_buffers: 0,
+            buffers: Vec::new(),
+        }
+    }
+
+    fn set_limits(&mut self, max_buffer_size: usize, max_buffers: usize) {
+        self.max_buffer_size = max_buffer_size;
+        self.max_buffers = max_buffers;
+        self.buffers.retain(|b| b.capacity() <= max_buffer_size);
+        self.buffers.truncate(max_buffers);
+    }
+
+    fn take(&mut self, capacity: usize) -> Vec<u8> {
+        match self.buffers.iter().position(|b| b.capacity() >= capacity) {
+            Some(i) => self.buffers.swap_remove(i),
+            None => Vec::with_capacity(capacity),
+        }
+    }
+
+    fn recycle(&mut self, mut buffer: Vec<u8>) {
+        let capacity = buffer.capacity();
+        if capacity > 0 && capacity <= self.max_buffer_size && self.buffers.len() < self.max_buffers {
+            buffer.clear();
+            self.buffers.push(buffer);
+        }
+    }
+}
+
+static POOL: Mutex<Pool> = Mutex::new(Pool::new());
+// Whether the limits let the pool keep anything, so the common case of a
+// pool that's off doesn't take the lock on every command
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+fn pool() -> MutexGuard<'static, Pool> {
+    // The pool is consistent between calls, so a panic elsewhere doesn't
+    // matter to it
+    match POOL.lock() {
+        Ok(pool) => pool,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Keep up to `max_buffers` buffers, each with room for at most
+/// `max_buffer_size` bytes.  Bigger buffers are freed as usual.  Setting
+/// either to 0 turns the pool off again and frees what it held.
+pub fn set_limits(max_buffer_size: usize, max_buffers: usize) {
+    let mut pool = pool();
+    pool.set_limits(max_buffer_size, max_buffers);
+    ENABLED.store(max_buffer_size > 0 && max_buffers > 0, Ordering::Relaxed);
+}
+
+/// Hand a buffer back for reuse once done with it
+pub fn recycle(buffer: Vec<u8>) {
+    if ENABLED.load(Ordering::Relaxed) {
+        pool().recycle(buffer);
+    }
+}
+
+/// How many buffers the pool holds right now
+pub fn pooled() -> usize {
+    pool().buffers.len()
+}
+
+/// An empty buffer with room for at least `capacity` bytes
+pub(crate) fn take(capacity: usize) -> Vec<u8> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Vec::with_capacity(capacity);
+    }
+    pool().take(capacity)
+}
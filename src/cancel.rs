@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn it_shares_cancellation_between_clones() {
+        let token = CancellationToken::new();
+        let worker = token.clone();
+        assert!(!worker.is_cancelled());
+
+        token.cancel();
+        assert!(worker.is_cancelled());
+        assert!(!CancellationToken::new().is_cancelled());
+    }
+
+    #[test]
+    fn it_wakes_registered_tasks_on_cancel() {
+        let token = CancellationToken::new();
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let registration = token.register(Waker::from(counter.clone()));
+        drop(token.register(Waker::from(counter.clone())));
+
+        token.clone().cancel();
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        drop(registration);
+        assert!(lock(&token.inner.wakers).is_empty());
+    }
+}
+
+/// Tells in-flight librados work to stop.  Hand a clone to the
+/// `IoCtx` aio calls or object listings and call `cancel` from anywhere,
+/// e.g. a shutdown handler, to abort them.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    next_id: AtomicUsize,
+    // Woken by `cancel`, keyed by registration
+    wakers: Mutex<HashMap<usize, Waker>>,
+}
+
+// Nothing run under the lock can panic, but a poisoned one is used as is
+fn lock(wakers: &Mutex<HashMap<usize, Waker>>) -> MutexGuard<HashMap<usize, Waker>> {
+    match wakers.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel every operation watching this token or any of its clones
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        let wakers: Vec<Waker> = lock(&self.inner.wakers).drain().map(|(_, waker)| waker).collect();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    // Wake `waker` when the token is cancelled, for as long as the returned
+    // registration lives.  Check `is_cancelled` after registering so a
+    // cancel in between isn't missed.
+    pub(crate) fn register(&self, waker: Waker) -> Registration {
+        let id = self.inner.next_id.fetch_add(1, Ordering::SeqCst);
+        lock(&self.inner.wakers).insert(id, waker);
+        Registration {
+            token: self,
+            id: id,
+        }
+    }
+}
+
+pub(crate) struct Registration<'a> {
+    token: &'a CancellationToken,
+    id: usize,
+}
+
+impl<'a> Drop for Registration<'a> {
+    fn drop(&mut self) {
+        lock(&self.token.inner.wakers).remove(&self.id);
+    }
+}
@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_builds_client_caps() {
+        let caps = CapsBuilder::new()
+            .mon_profile("rbd")
+            .osd_profile("rbd", Some("volumes"))
+            .osd_pool_namespace("rw", "data", "tenant1");
+
+        assert_eq!(
+            caps.as_args(),
+            vec![
+                "mon",
+                "profile rbd",
+                "osd",
+                "profile rbd pool=volumes, allow rw pool=data namespace=tenant1",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_skips_empty_daemons() {
+        let caps = CapsBuilder::new().mgr("allow r");
+
+        assert_eq!(caps.as_args(), vec!["mgr", "allow r"]);
+    }
+}
+
+/// Builds the caps for a cephx entity.  Each daemon type can be given any
+/// number of grants and they are joined together in the `, ` separated form
+/// that `ceph auth` expects.
+///
+/// ```
+/// # use ceph::CapsBuilder;
+/// let caps = CapsBuilder::new()
+///     .mon("allow r")
+///     .osd_pool("rwx", "images");
+/// assert_eq!(caps.as_args(), vec!["mon", "allow r", "osd", "allow rwx pool=images"]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CapsBuilder {
+    mon: Vec<String>,
+    osd: Vec<String>,
+    mds: Vec<String>,
+    mgr: Vec<String>,
+}
+
+impl CapsBuilder {
+    pub fn new() -> CapsBuilder {
+        CapsBuilder::default()
+    }
+
+    /// Add a raw mon cap such as `allow r`
+    pub fn mon(mut self, cap: &str) -> CapsBuilder {
+        self.mon.push(cap.to_string());
+        self
+    }
+
+    /// Add a raw osd cap such as `allow rwx`
+    pub fn osd(mut self, cap: &str) -> CapsBuilder {
+        self.osd.push(cap.to_string());
+        self
+    }
+
+    /// Add a raw mds cap such as `allow rw path=/home`
+    pub fn mds(mut self, cap: &str) -> CapsBuilder {
+        self.mds.push(cap.to_string());
+        self
+    }
+
+    /// Add a raw mgr cap such as `allow r`
+    pub fn mgr(mut self, cap: &str) -> CapsBuilder {
+        self.mgr.push(cap.to_string());
+        self
+    }
+
+    /// Grant a mon profile such as `rbd` or `bootstrap-osd`
+    pub fn mon_profile(self, profile: &str) -> CapsBuilder {
+        self.mon(&format!("profile {}", profile))
+    }
+
+    /// Grant an osd profile, optionally restricted to a single pool
+    pub fn osd_profile(self, profile: &str, pool: Option<&str>) -> CapsBuilder {
+        match pool {
+            Some(pool) => self.osd(&format!("profile {} pool={}", profile, pool)),
+            None => self.osd(&format!("profile {}", profile)),
+        }
+    }
+
+    /// Grant access such as `r` or `rwx` to a single pool
+    pub fn osd_pool(self, access: &str, pool: &str) -> CapsBuilder {
+        self.osd(&format!("allow {} pool={}", access, pool))
+    }
+
+    /// Grant access to a single rados namespace inside of a pool
+    pub fn osd_pool_namespace(self, access: &str, pool: &str, namespace: &str) -> CapsBuilder {
+        self.osd(&format!("allow {} pool={} namespace={}", access, pool, namespace))
+    }
+
+    /// The caps as the alternating daemon type and cap string list that
+    /// the `caps` argument of the auth commands takes
+    pub fn as_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for &(daemon, caps) in &[("mon", &self.mon), ("osd", &self.osd), ("mds", &self.mds), ("mgr", &self.mgr)] {
+            if !caps.is_empty() {
+                args.push(daemon.to_string());
+                args.push(caps.join(", "));
+            }
+        }
+        args
+    }
+}
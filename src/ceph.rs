@@ -18,7 +18,12 @@ use JsonData;
 
 use JsonValue;
 use admin_sockets::*;
+use aio;
+use backend::CephBackend;
+use buffer_pool;
 use byteorder::{LittleEndian, WriteBytesExt};
+use cancel::CancellationToken;
+use cluster::Rados;
 use error::*;
 use json::*;
 use libc::*;
@@ -28,9 +33,11 @@ use serde_json;
 use rados::*;
 use status::*;
 use std::{ptr, str};
+use std::collections::BTreeMap;
 use std::ffi::{CStr, CString};
 
 use std::io::{BufRead, Cursor};
+use std::marker::PhantomData;
 use std::net::IpAddr;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use utils::*;
@@ -38,6 +45,18 @@ use utils::*;
 use uuid::Uuid;
 // use JsonError;
 
+#[cfg(all(test, feature = "luminous"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_empty_values_in_nul_lists() {
+        assert_eq!(split_nul_list(b"a\0\0c\0"), vec!["a", "", "c"]);
+        assert_eq!(split_nul_list(b"\0"), vec![""]);
+        assert!(split_nul_list(b"").is_empty());
+    }
+}
+
 const CEPH_OSD_TMAP_HDR: char = 'h';
 const CEPH_OSD_TMAP_SET: char = 's';
 const CEPH_OSD_TMAP_CREATE: char = 'c';
@@ -183,37 +202,88 @@ pub struct CephObject {
 impl Iterator for Pool {
     type Item = CephObject;
     fn next(&mut self) -> Option<CephObject> {
-        let mut entry_ptr: *mut *const ::libc::c_char = ptr::null_mut();
-        let mut key_ptr: *mut *const ::libc::c_char = ptr::null_mut();
-        let mut nspace_ptr: *mut *const ::libc::c_char = ptr::null_mut();
-
-        unsafe {
-            let ret_code = rados_nobjects_list_next(self.ctx, &mut entry_ptr, &mut key_ptr, &mut nspace_ptr);
-            if ret_code == -ENOENT {
+        match next_list_entry(self.ctx) {
+            Ok(Some(object)) => Some(object),
+            Ok(None) => {
                 // We're done
-                rados_nobjects_list_close(self.ctx);
+                unsafe {
+                    rados_nobjects_list_close(self.ctx);
+                }
                 None
-            } else if ret_code < 0 {
-                // Unknown error
+            },
+            // Unknown error
+            Err(_) => None,
+        }
+    }
+}
+
+/// The objects of a pool, listed through `IoCtx::objects`.  The listing
+/// stops early once its token is cancelled or after the first error, and is
+/// closed when dropped.
+#[derive(Debug)]
+pub struct Objects<'a> {
+    ctx: rados_list_ctx_t,
+    token: CancellationToken,
+    done: bool,
+    ioctx: PhantomData<&'a rados_ioctx_t>,
+}
+
+impl<'a> Drop for Objects<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            rados_nobjects_list_close(self.ctx);
+        }
+    }
+}
+
+impl<'a> Iterator for Objects<'a> {
+    type Item = RadosResult<CephObject>;
+    fn next(&mut self) -> Option<RadosResult<CephObject>> {
+        if self.done || self.token.is_cancelled() {
+            return None;
+        }
+        match next_list_entry(self.ctx) {
+            Ok(Some(object)) => Some(Ok(object)),
+            Ok(None) => {
+                self.done = true;
                 None
-            } else {
-                let object_name = CStr::from_ptr(entry_ptr as *const ::libc::c_char);
-                let mut object_locator = String::new();
-                let mut namespace = String::new();
-                if !key_ptr.is_null() {
-                    object_locator.push_str(&CStr::from_ptr(key_ptr as *const ::libc::c_char).to_string_lossy());
-                }
-                if !nspace_ptr.is_null() {
-                    namespace.push_str(&CStr::from_ptr(nspace_ptr as *const ::libc::c_char).to_string_lossy());
-                }
+            },
+            Err(ret_code) => {
+                self.done = true;
+                Some(get_error(ret_code).and_then(|e| Err(RadosError::new(e))))
+            },
+        }
+    }
+}
 
-                return Some(CephObject {
-                    name: object_name.to_string_lossy().into_owned(),
-                    entry_locator: object_locator,
-                    namespace: namespace,
-                });
-            }
+// Ok(None) once the listing is exhausted, otherwise the librados error code
+fn next_list_entry(ctx: rados_list_ctx_t) -> Result<Option<CephObject>, i32> {
+    let mut entry_ptr: *mut *const ::libc::c_char = ptr::null_mut();
+    let mut key_ptr: *mut *const ::libc::c_char = ptr::null_mut();
+    let mut nspace_ptr: *mut *const ::libc::c_char = ptr::null_mut();
+
+    unsafe {
+        let ret_code = rados_nobjects_list_next(ctx, &mut entry_ptr, &mut key_ptr, &mut nspace_ptr);
+        if ret_code == -ENOENT {
+            return Ok(None);
+        } else if ret_code < 0 {
+            return Err(ret_code);
         }
+        let object_name = CStr::from_ptr(entry_ptr as *const ::libc::c_char);
+        let mut object_locator = String::new();
+        let mut namespace = String::new();
+        if !key_ptr.is_null() {
+            object_locator.push_str(&CStr::from_ptr(key_ptr as *const ::libc::c_char).to_string_lossy());
+        }
+        if !nspace_ptr.is_null() {
+            namespace.push_str(&CStr::from_ptr(nspace_ptr as *const ::libc::c_char).to_string_lossy());
+        }
+
+        Ok(Some(CephObject {
+            name: object_name.to_string_lossy().into_owned(),
+            entry_locator: object_locator,
+            namespace: namespace,
+        }))
     }
 }
 
@@ -277,7 +347,280 @@ pub struct RadosVersion {
     pub extra: i32,
 }
 
+/// An io context for a single pool.  The context is destroyed when this is
+/// dropped.  It borrows the `Rados` handle it was opened from so it can't
+/// outlive the connection.
+#[derive(Debug)]
+pub struct IoCtx<'a> {
+    ioctx: rados_ioctx_t,
+    cluster: PhantomData<&'a Rados>,
+}
+
+impl<'a> Drop for IoCtx<'a> {
+    fn drop(&mut self) {
+        destroy_rados_ioctx(self.ioctx);
+    }
+}
+
+impl<'a> IoCtx<'a> {
+    /// Open an io context for the pool with this name
+    pub fn new(cluster: &'a Rados, pool_name: &str) -> RadosResult<IoCtx<'a>> {
+        Ok(IoCtx {
+            ioctx: get_rados_ioctx(cluster.as_raw(), pool_name)?,
+            cluster: PhantomData,
+        })
+    }
+
+    /// Open an io context for the pool with this id
+    pub fn from_pool_id(cluster: &'a Rados, pool_id: i64) -> RadosResult<IoCtx<'a>> {
+        Ok(IoCtx {
+            ioctx: get_rados_ioctx2(cluster.as_raw(), pool_id)?,
+            cluster: PhantomData,
+        })
+    }
+
+    /// The raw handle for use with the rados_* functions in this module
+    pub fn as_raw(&self) -> rados_ioctx_t {
+        self.ioctx
+    }
+
+    /// Get the object, byte and io counters of the pool straight from
+    /// librados.  Unlike `rados df` through the monitors this needs no mon
+    /// caps.
+    pub fn pool_stat(&self) -> RadosResult<PoolStat> {
+        let stat = rados_stat_pool(self.ioctx)?;
+        Ok(PoolStat {
+            num_bytes: stat.num_bytes,
+            num_kb: stat.num_kb,
+            num_objects: stat.num_objects,
+            num_object_clones: stat.num_object_clones,
+            num_object_copies: stat.num_object_copies,
+            num_objects_missing_on_primary: stat.num_objects_missing_on_primary,
+            num_objects_unfound: stat.num_objects_unfound,
+            num_objects_degraded: stat.num_objects_degraded,
+            num_rd: stat.num_rd,
+            num_rd_kb: stat.num_rd_kb,
+            num_wr: stat.num_wr,
+            num_wr_kb: stat.num_wr_kb,
+        })
+    }
+
+    /// Tag the pool as used by `app`, e.g. "rbd", "rgw" or "cephfs".  A
+    /// pool already tagged with another application needs `force`.
+    #[cfg(feature = "luminous")]
+    pub fn application_enable(&self, app: &str, force: bool) -> RadosResult<()> {
+        let app_str = CString::new(app)?;
+        unsafe {
+            let ret_code = rados_application_enable(self.ioctx, app_str.as_ptr(), force as c_int);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// The applications the pool is tagged with
+    #[cfg(feature = "luminous")]
+    pub fn application_list(&self) -> RadosResult<Vec<String>> {
+        let mut buffer = vec![0u8; 256];
+        loop {
+            // librados ends the list with one more nul past the length it
+            // reports, so keep a byte back for it
+            let mut len = buffer.len() - 1;
+            let ret_code = unsafe { rados_application_list(self.ioctx, buffer.as_mut_ptr() as *mut c_char, &mut len) };
+            if ret_code == -ERANGE {
+                buffer.resize(len + 1, 0);
+                continue;
+            }
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            return Ok(split_nul_list(&buffer[..len]));
+        }
+    }
+
+    /// One metadata value an application keeps on the pool
+    #[cfg(feature = "luminous")]
+    pub fn application_metadata_get(&self, app: &str, key: &str) -> RadosResult<String> {
+        let app_str = CString::new(app)?;
+        let key_str = CString::new(key)?;
+        let mut buffer = vec![0u8; 256];
+        loop {
+            let mut len = buffer.len();
+            unsafe {
+                let ret_code = rados_application_metadata_get(
+                    self.ioctx,
+                    app_str.as_ptr(),
+                    key_str.as_ptr(),
+                    buffer.as_mut_ptr() as *mut c_char,
+                    &mut len,
+                );
+                if ret_code == -ERANGE {
+                    buffer.resize(len, 0);
+                    continue;
+                }
+                if ret_code < 0 {
+                    return Err(RadosError::new(get_error(ret_code)?));
+                }
+                return Ok(CStr::from_ptr(buffer.as_ptr() as *const c_char).to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    #[cfg(feature = "luminous")]
+    pub fn application_metadata_set(&self, app: &str, key: &str, value: &str) -> RadosResult<()> {
+        let app_str = CString::new(app)?;
+        let key_str = CString::new(key)?;
+        let value_str = CString::new(value)?;
+        unsafe {
+            let ret_code =
+                rados_application_metadata_set(self.ioctx, app_str.as_ptr(), key_str.as_ptr(), value_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "luminous")]
+    pub fn application_metadata_remove(&self, app: &str, key: &str) -> RadosResult<()> {
+        let app_str = CString::new(app)?;
+        let key_str = CString::new(key)?;
+        unsafe {
+            let ret_code = rados_application_metadata_remove(self.ioctx, app_str.as_ptr(), key_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// All the metadata an application keeps on the pool
+    #[cfg(feature = "luminous")]
+    pub fn application_metadata_list(&self, app: &str) -> RadosResult<BTreeMap<String, String>> {
+        let app_str = CString::new(app)?;
+        let mut keys = vec![0u8; 256];
+        let mut values = vec![0u8; 1024];
+        loop {
+            // As with `application_list`, each list ends past its length
+            let mut keys_len = keys.len() - 1;
+            let mut values_len = values.len() - 1;
+            let ret_code = unsafe {
+                rados_application_metadata_list(
+                    self.ioctx,
+                    app_str.as_ptr(),
+                    keys.as_mut_ptr() as *mut c_char,
+                    &mut keys_len,
+                    values.as_mut_ptr() as *mut c_char,
+                    &mut values_len,
+                )
+            };
+            // Both lengths come back as what is needed
+            if ret_code == -ERANGE {
+                keys.resize(keys_len + 1, 0);
+                values.resize(values_len + 1, 0);
+                continue;
+            }
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            let keys = split_nul_list(&keys[..keys_len]);
+            let values = split_nul_list(&values[..values_len]);
+            return Ok(keys.into_iter().zip(values).collect());
+        }
+    }
+
+    /// List the objects in the pool.  Cancelling the token ends the listing
+    /// at the next object.
+    pub fn objects(&self, token: &CancellationToken) -> RadosResult<Objects> {
+        Ok(Objects {
+            ctx: rados_list_pool_objects(self.ioctx)?,
+            token: token.clone(),
+            done: false,
+            ioctx: PhantomData,
+        })
+    }
+
+    /// Read up to `len` bytes of an object starting at `offset`.  Fails with
+    /// `RadosError::Cancelled` if the token is cancelled before the read
+    /// finishes.  This is `read_async` for callers without an executor.
+    pub fn aio_read(&self, object_name: &str, len: usize, offset: u64, token: &CancellationToken)
+        -> RadosResult<Vec<u8>> {
+        aio::wait(self.read_async(object_name, len, offset)?, token)
+    }
+
+    /// Write `data` to an object at `offset`.  Fails with
+    /// `RadosError::Cancelled` if the token is cancelled before the write
+    /// finishes.  This is `write_async` for callers without an executor.
+    pub fn aio_write(&self, object_name: &str, data: &[u8], offset: u64, token: &CancellationToken)
+        -> RadosResult<()> {
+        aio::wait(self.write_async(object_name, data, offset)?, token)
+    }
+
+    /// Read a whole object into a buffer from `buffer_pool`.  Hand the
+    /// buffer to `buffer_pool::recycle` when done with it to have the next
+    /// read reuse it.
+    pub fn read_full(&self, object_name: &str) -> RadosResult<Vec<u8>> {
+        let object_name_str = CString::new(object_name)?;
+        let (size, _) = rados_object_stat(self.ioctx, object_name)?;
+        let mut buffer = buffer_pool::take(size as usize);
+        // The object may have grown since the stat, so read until a short read
+        loop {
+            let len = buffer.capacity() - buffer.len();
+            if len == 0 {
+                buffer.reserve(1024 * 64);
+                continue;
+            }
+            unsafe {
+                let ret_code = rados_read(
+                    self.ioctx,
+                    object_name_str.as_ptr(),
+                    buffer.as_mut_ptr().add(buffer.len()) as *mut c_char,
+                    len,
+                    buffer.len() as u64,
+                );
+                if ret_code < 0 {
+                    buffer_pool::recycle(buffer);
+                    return Err(RadosError::new(get_error(ret_code)?));
+                }
+                let read = buffer.len() + ret_code as usize;
+                buffer.set_len(read);
+                if (ret_code as usize) < len {
+                    return Ok(buffer);
+                }
+            }
+        }
+    }
+}
+
+// The strings of a list librados wrote as name\0name\0.  Values may be
+// empty, so only the nothing after the last nul is dropped.
+#[cfg(feature = "luminous")]
+fn split_nul_list(buffer: &[u8]) -> Vec<String> {
+    let mut list: Vec<String> = buffer.split(|b| *b == 0).map(|s| String::from_utf8_lossy(s).into_owned()).collect();
+    list.pop();
+    list
+}
+
+/// Usage counters of a pool
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PoolStat {
+    pub num_bytes: u64,
+    pub num_kb: u64,
+    pub num_objects: u64,
+    pub num_object_clones: u64,
+    pub num_object_copies: u64,
+    pub num_objects_missing_on_primary: u64,
+    pub num_objects_unfound: u64,
+    pub num_objects_degraded: u64,
+    pub num_rd: u64,
+    pub num_rd_kb: u64,
+    pub num_wr: u64,
+    pub num_wr_kb: u64,
+}
+
 /// Connect to a Ceph cluster and return a connection handle rados_t
+#[deprecated(note = "use Rados::connect, which shuts the connection down when it is dropped")]
 pub fn connect_to_ceph(user_id: &str, config_file: &str) -> RadosResult<rados_t> {
     let connect_id = try!(CString::new(user_id));
     let conf_file = try!(CString::new(config_file));
@@ -289,10 +632,12 @@ pub fn connect_to_ceph(user_id: &str, config_file: &str) -> RadosResult<rados_t>
         }
         let ret_code = rados_conf_read_file(cluster_handle, conf_file.as_ptr());
         if ret_code < 0 {
+            rados_shutdown(cluster_handle);
             return Err(RadosError::new(try!(get_error(ret_code))));
         }
         let ret_code = rados_connect(cluster_handle);
         if ret_code < 0 {
+            rados_shutdown(cluster_handle);
             return Err(RadosError::new(try!(get_error(ret_code))));
         }
         Ok(cluster_handle)
@@ -301,6 +646,7 @@ pub fn connect_to_ceph(user_id: &str, config_file: &str) -> RadosResult<rados_t>
 
 /// Disconnect from a Ceph cluster and destroy the connection handle rados_t
 /// For clean up, this is only necessary after connect_to_ceph() has succeeded.
+#[deprecated(note = "use Rados::connect, which shuts the connection down when it is dropped")]
 pub fn disconnect_from_ceph(cluster: rados_t) {
     if cluster.is_null() {
         // No need to do anything
@@ -854,8 +1200,9 @@ pub fn rados_object_read(ctx: rados_ioctx_t, object_name: &str, fill_buffer: &mu
     let object_name_str = try!(CString::new(object_name));
     let mut len = fill_buffer.capacity();
     if len == 0 {
-        fill_buffer.reserve_exact(1024 * 64);
-        len = fill_buffer.capacity();
+        // A pooled buffer may be bigger, but the default read stays at 64K
+        len = 1024 * 64;
+        *fill_buffer = buffer_pool::take(len);
     }
 
     unsafe {
@@ -1843,63 +2190,288 @@ pub fn ceph_mon_command(cluster: rados_t, name: &str, value: &str, format: Optio
     ceph_mon_command_with_data(cluster, name, value, format, data)
 }
 
+#[deprecated(note = "use CephBackend::mon_command with a Rados handle")]
 pub fn ceph_mon_command_without_data(cluster: rados_t, cmd: &serde_json::Value) -> RadosResult<(Option<String>, Option<String>)> {
+    cluster.mon_command(cmd, &[])
+}
+
+/// Mon command that passes an input buffer along with the json command.
+/// Commands like `osd new` read their payload from this buffer the same way
+/// the ceph cli reads it from `-i <file>`.
+#[deprecated(note = "use CephBackend::mon_command with a Rados handle")]
+pub fn ceph_mon_command_with_input(cluster: rados_t, cmd: &serde_json::Value, input: &[u8])
+    -> RadosResult<(Option<String>, Option<String>)> {
+    cluster.mon_command(cmd, input)
+}
+
+/// Mon command that hands back the output buffer untouched.  This is needed
+/// for commands like `osd getmap` that return binary encoded data.
+#[deprecated(note = "use CephBackend::mon_command_bytes with a Rados handle")]
+pub fn ceph_mon_command_bytes(cluster: rados_t, cmd: &serde_json::Value, input: &[u8])
+    -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+    mon_command_bytes(cluster, None, cmd, input)
+}
+
+/// Mon command that is sent to one monitor by name instead of whichever
+/// monitor librados happens to be talking to.  Commands like
+/// `quorum enter` only make sense when they reach a specific monitor.
+#[deprecated(note = "use CephBackend::mon_command_target with a Rados handle")]
+pub fn ceph_mon_command_target(cluster: rados_t, mon_name: &str, cmd: &serde_json::Value)
+    -> RadosResult<(Option<String>, Option<String>)> {
+    cluster.mon_command_target(mon_name, cmd)
+}
+
+// Copy the output and status librados allocated for a command into owned
+// buffers, the output into one from the pool, and free them with
+// rados_buffer_free rather than libc::free
+unsafe fn take_command_output(outbuf: *mut c_char, outbuf_len: size_t, outs: *mut c_char, outs_len: size_t)
+    -> (Option<Vec<u8>>, Option<String>) {
+    let mut output = None;
+    let mut status = None;
+    if !outbuf.is_null() {
+        if outbuf_len > 0 {
+            let reply = ::std::slice::from_raw_parts(outbuf as *const u8, outbuf_len);
+            let mut buffer = buffer_pool::take(reply.len());
+            buffer.extend_from_slice(reply);
+            output = Some(buffer);
+        }
+        rados_buffer_free(outbuf);
+    }
+    if !outs.is_null() {
+        if outs_len > 0 {
+            let slice = ::std::slice::from_raw_parts(outs as *const u8, outs_len);
+            status = Some(String::from_utf8_lossy(slice).into_owned());
+        }
+        rados_buffer_free(outs);
+    }
+    (output, status)
+}
+
+pub(crate) fn mon_command_bytes(cluster: rados_t, target: Option<&str>, cmd: &serde_json::Value, input: &[u8])
+    -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
     if cluster.is_null() {
         return Err(RadosError::new("Rados not connected.  Please initialize cluster".to_string()));
     }
     let cmd_string = cmd.to_string();
-    debug!("ceph_mon_command_without_data: {}", cmd_string);
-    let data: Vec<*mut c_char> = Vec::with_capacity(1);
-    let cmds = CString::new(cmd_string).unwrap();
+    debug!("mon_command_bytes: {}", cmd_string);
+    let cmds = CString::new(cmd_string)?;
+    let target = match target {
+        Some(name) => Some(CString::new(name)?),
+        None => None,
+    };
 
     let mut outbuf = ptr::null_mut();
     let mut outs = ptr::null_mut();
     let mut outbuf_len = 0;
     let mut outs_len = 0;
 
-    // Ceph librados allocates these buffers internally and the pointer that comes
-    // back must be
-    // freed by call `rados_buffer_free`
-    let mut str_outbuf: Option<String> = None;
-    let mut str_outs: Option<String> = None;
+    unsafe {
+        // cmd length is 1 because we only allow one command at a time.
+        let ret_code = match target {
+            Some(ref name) => {
+                rados_mon_command_target(
+                    cluster,
+                    name.as_ptr(),
+                    &mut cmds.as_ptr(),
+                    1,
+                    input.as_ptr() as *const c_char,
+                    input.len() as usize,
+                    &mut outbuf,
+                    &mut outbuf_len,
+                    &mut outs,
+                    &mut outs_len,
+                )
+            },
+            None => {
+                rados_mon_command(
+                    cluster,
+                    &mut cmds.as_ptr(),
+                    1,
+                    input.as_ptr() as *const c_char,
+                    input.len() as usize,
+                    &mut outbuf,
+                    &mut outbuf_len,
+                    &mut outs,
+                    &mut outs_len,
+                )
+            },
+        };
+        debug!("return code: {}", ret_code);
+        // Free the buffers even when the command failed
+        let output = take_command_output(outbuf, outbuf_len, outs, outs_len);
+        if ret_code < 0 {
+            return Err(RadosError::new(get_error(ret_code)?));
+        }
+        Ok(output)
+    }
+}
 
-    debug!("Calling rados_mon_command with {:?}", cmd);
+/// OSD command that takes a json command, the same way `ceph tell osd.N`
+/// does.  This is how commands like `bench` or `flush_pg_stats` reach a
+/// single osd.
+///
+/// This is the json form of `ceph_osd_command`, which only builds commands
+/// from a single name and value.
+#[deprecated(note = "use CephBackend::osd_command with a Rados handle")]
+pub fn ceph_osd_command_without_data(cluster: rados_t, osd_id: i32, cmd: &serde_json::Value)
+    -> RadosResult<(Option<String>, Option<String>)> {
+    cluster.osd_command(osd_id, cmd, &[])
+}
+
+/// OSD json command that passes an input buffer along with the command
+#[deprecated(note = "use CephBackend::osd_command with a Rados handle")]
+pub fn ceph_osd_command_with_input(cluster: rados_t, osd_id: i32, cmd: &serde_json::Value, input: &[u8])
+    -> RadosResult<(Option<String>, Option<String>)> {
+    cluster.osd_command(osd_id, cmd, input)
+}
+
+/// Send a json command to one osd and hand back the output buffer untouched
+pub(crate) fn osd_command_bytes(cluster: rados_t, osd_id: i32, cmd: &serde_json::Value, input: &[u8])
+    -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+    if cluster.is_null() {
+        return Err(RadosError::new("Rados not connected.  Please initialize cluster".to_string()));
+    }
+    let cmd_string = cmd.to_string();
+    debug!("osd_command_bytes osd.{}: {}", osd_id, cmd_string);
+    let cmds = CString::new(cmd_string)?;
+
+    let mut outbuf = ptr::null_mut();
+    let mut outs = ptr::null_mut();
+    let mut outbuf_len = 0;
+    let mut outs_len = 0;
 
     unsafe {
         // cmd length is 1 because we only allow one command at a time.
-        let ret_code = rados_mon_command(
+        let ret_code = rados_osd_command(
             cluster,
+            osd_id,
             &mut cmds.as_ptr(),
             1,
-            data.as_ptr() as *mut c_char,
-            data.len() as usize,
+            input.as_ptr() as *const c_char,
+            input.len() as usize,
             &mut outbuf,
             &mut outbuf_len,
             &mut outs,
             &mut outs_len,
         );
         debug!("return code: {}", ret_code);
+        // Free the buffers even when the command failed
+        let output = take_command_output(outbuf, outbuf_len, outs, outs_len);
         if ret_code < 0 {
-            return Err(RadosError::new(try!(get_error(ret_code))));
+            return Err(RadosError::new(get_error(ret_code)?));
         }
+        Ok(output)
+    }
+}
 
-        // Copy the data from outbuf and then  call rados_buffer_free instead libc::free
-        if outbuf_len > 0 && !outbuf.is_null() {
-            let slice = ::std::slice::from_raw_parts(outbuf as *const u8, outbuf_len as usize);
-            str_outbuf = Some(String::from_utf8_lossy(slice).into_owned());
+/// Mgr command that takes a json command.  Commands for mgr modules such as
+/// the balancer or the pg autoscaler are handled by the active mgr, so they
+/// go straight to it instead of being forwarded by a monitor.
+#[deprecated(note = "use CephBackend::mgr_command with a Rados handle")]
+pub fn ceph_mgr_command_without_data(cluster: rados_t, cmd: &serde_json::Value)
+    -> RadosResult<(Option<String>, Option<String>)> {
+    cluster.mgr_command(cmd, &[])
+}
 
-            rados_buffer_free(outbuf);
-        }
+/// Mgr json command that passes an input buffer along with the command
+#[deprecated(note = "use CephBackend::mgr_command with a Rados handle")]
+pub fn ceph_mgr_command_with_input(cluster: rados_t, cmd: &serde_json::Value, input: &[u8])
+    -> RadosResult<(Option<String>, Option<String>)> {
+    cluster.mgr_command(cmd, input)
+}
 
-        if outs_len > 0 && !outs.is_null() {
-            let slice = ::std::slice::from_raw_parts(outs as *const u8, outs_len as usize);
-            str_outs = Some(String::from_utf8_lossy(slice).into_owned());
+pub(crate) fn mgr_command_bytes(cluster: rados_t, cmd: &serde_json::Value, input: &[u8])
+    -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+    if cluster.is_null() {
+        return Err(RadosError::new("Rados not connected.  Please initialize cluster".to_string()));
+    }
+    let cmd_string = cmd.to_string();
+    debug!("ceph_mgr_command: {}", cmd_string);
+    let cmds = CString::new(cmd_string)?;
 
-            rados_buffer_free(outs);
+    let mut outbuf = ptr::null_mut();
+    let mut outs = ptr::null_mut();
+    let mut outbuf_len = 0;
+    let mut outs_len = 0;
+
+    unsafe {
+        // cmd length is 1 because we only allow one command at a time.
+        let ret_code = rados_mgr_command(
+            cluster,
+            &mut cmds.as_ptr(),
+            1,
+            input.as_ptr() as *const c_char,
+            input.len() as usize,
+            &mut outbuf,
+            &mut outbuf_len,
+            &mut outs,
+            &mut outs_len,
+        );
+        debug!("return code: {}", ret_code);
+        // Free the buffers even when the command failed
+        let output = take_command_output(outbuf, outbuf_len, outs, outs_len);
+        if ret_code < 0 {
+            return Err(RadosError::new(get_error(ret_code)?));
         }
+        Ok(output)
     }
+}
 
-    Ok((str_outbuf, str_outs))
+/// PG command that takes a json command.  The command is sent to the primary
+/// osd of the pg, which is how `ceph pg <pgid> query` and
+/// `ceph pg <pgid> list_unfound` work.
+#[deprecated(note = "use CephBackend::pg_command with a Rados handle")]
+pub fn ceph_pg_command_without_data(cluster: rados_t, pgid: &str, cmd: &serde_json::Value)
+    -> RadosResult<(Option<String>, Option<String>)> {
+    cluster.pg_command(pgid, cmd, &[])
+}
+
+/// PG json command that passes an input buffer along with the command
+#[deprecated(note = "use CephBackend::pg_command with a Rados handle")]
+pub fn ceph_pg_command_with_input(cluster: rados_t, pgid: &str, cmd: &serde_json::Value, input: &[u8])
+    -> RadosResult<(Option<String>, Option<String>)> {
+    cluster.pg_command(pgid, cmd, input)
+}
+
+/// Send a json command to the primary osd of a pg and hand back the output
+/// buffer untouched
+pub(crate) fn pg_command_bytes(cluster: rados_t, pgid: &str, cmd: &serde_json::Value, input: &[u8])
+    -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+    if cluster.is_null() {
+        return Err(RadosError::new("Rados not connected.  Please initialize cluster".to_string()));
+    }
+    let cmd_string = cmd.to_string();
+    debug!("pg_command_bytes {}: {}", pgid, cmd_string);
+    let cmds = CString::new(cmd_string)?;
+    let pg_str = CString::new(pgid)?;
+
+    let mut outbuf = ptr::null_mut();
+    let mut outs = ptr::null_mut();
+    let mut outbuf_len = 0;
+    let mut outs_len = 0;
+
+    unsafe {
+        // cmd length is 1 because we only allow one command at a time.
+        let ret_code = rados_pg_command(
+            cluster,
+            pg_str.as_ptr(),
+            &mut cmds.as_ptr(),
+            1,
+            input.as_ptr() as *const c_char,
+            input.len() as usize,
+            &mut outbuf,
+            &mut outbuf_len,
+            &mut outs,
+            &mut outs_len,
+        );
+        debug!("return code: {}", ret_code);
+        // Free the buffers even when the command failed
+        let output = take_command_output(outbuf, outbuf_len, outs, outs_len);
+        if ret_code < 0 {
+            return Err(RadosError::new(get_error(ret_code)?));
+        }
+        Ok(output)
+    }
 }
 
 /// Mon command that does pass in a data payload.
@@ -1975,7 +2547,9 @@ pub fn ceph_mon_command_with_data(cluster: rados_t, name: &str, value: &str, for
     Ok((str_outbuf, str_outs))
 }
 
-/// OSD command that does not pass in a data payload.
+/// OSD command that does not pass in a data payload.  The command is built
+/// from one name and value, so commands with several arguments need
+/// `CephBackend::osd_command`, which takes the whole json command.
 pub fn ceph_osd_command(cluster: rados_t, id: i32, name: &str, value: &str, format: Option<&str>)
     -> RadosResult<(Option<String>, Option<String>)> {
     let data: Vec<*mut c_char> = Vec::with_capacity(1);
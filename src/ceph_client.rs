@@ -1,15 +1,24 @@
 use std::collections::HashMap;
 
-use rados::{self, rados_t};
-use ceph::{self, connect_to_ceph, disconnect_from_ceph};
+use rados;
+use ceph::{self, IoCtx};
 use cmd;
 
 use libc::{c_char};
 use std::{ptr, str};
 use std::ffi::{CString};
-
-use error::RadosError;
-use {CephVersion, MonCommand, OsdOption, PoolOption};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use error::{RadosError, RadosResult};
+use serde::de::DeserializeOwned;
+use serde_json;
+#[cfg(feature = "luminous")]
+use uuid::Uuid;
+#[cfg(feature = "luminous")]
+use CephRelease;
+use {dry_run, CapsBuilder, CephBackend, CephVersion, CommandObserver, DryRunBackend, FsOption, MonCommand, OsdOption,
+     PoolOption, Rados};
 
 /// A CephClient is a struct that handles communicating with Ceph
 /// in a nicer, Rustier way
@@ -28,17 +37,21 @@ use {CephVersion, MonCommand, OsdOption, PoolOption};
 /// # }
 /// ```
 pub struct CephClient {
-    rados_t: rados_t,
+    rados: Rados,
     simulate: bool,
+    // Read by min_version!, which only the newer releases use
+    #[cfg_attr(not(feature = "luminous"), allow(dead_code))]
     version: CephVersion,
 }
 
+// Only the commands of newer releases check the version
+#[cfg(feature = "luminous")]
 macro_rules! min_version {
     ( $version:ident, $self:ident ) => {
         {
-            if $self.version < CephVersion::$version {
+            if $self.version < CephRelease::$version {
                 return Err(
-                    RadosError::MinVersion(CephVersion::$version, $self.version)
+                    RadosError::MinVersion(CephRelease::$version, $self.version)
                 );
             }
         }
@@ -47,62 +60,96 @@ macro_rules! min_version {
 
 
 
-impl Drop for CephClient {
-    fn drop(&mut self) {
-        disconnect_from_ceph(self.rados_t);
-    }
-}
-
 impl CephClient {
     pub fn new<T1: AsRef<str>, T2: AsRef<str>>(user_id: T1, config_file: T2) -> Result<CephClient, RadosError> {
-        let rados_t = match connect_to_ceph(&user_id.as_ref(), &config_file.as_ref()) {
-            Ok(rados_t) => rados_t,
-            Err(e) => return Err(e.into()),
-        };
-        let version: CephVersion = match cmd::version(rados_t)?.parse() {
+        CephClient::from_rados(Rados::connect(user_id.as_ref(), config_file.as_ref())?)
+    }
+
+    /// Connect with a limit on how long each command may take.  See
+    /// `Rados::set_command_timeout`.
+    pub fn with_command_timeout<T1: AsRef<str>, T2: AsRef<str>>(user_id: T1, config_file: T2, timeout: Duration)
+        -> Result<CephClient, RadosError> {
+        let mut rados = Rados::connect(user_id.as_ref(), config_file.as_ref())?;
+        rados.set_command_timeout(Some(timeout));
+        CephClient::from_rados(rados)
+    }
+
+    fn from_rados(rados: Rados) -> Result<CephClient, RadosError> {
+        let version: CephVersion = match cmd::version(&rados)?.parse() {
             Ok(v) => v,
             Err(e) => return Err(e.into()),
         };
 
         Ok(CephClient {
-            rados_t: rados_t,
+            rados: rados,
             simulate: false,
             version: version,
         })
     }
 
+    /// Only pretend to change the cluster.  The commands that would change
+    /// it go to a `DryRunBackend` instead of the cluster, while the ones that
+    /// only read still run.
     pub fn simulate(mut self) -> Self {
         self.simulate = true;
         self
     }
 
-    pub fn osd_out(&self, osd_id: u64) -> Result<(), RadosError> {
-        let osd_id = osd_id.to_string();
-        let cmd = MonCommand::new()
-            .with_prefix("osd out")
-            .with("ids", &osd_id);
+    // Send the commands `f` builds to the cluster, or through a dry run when
+    // simulating
+    fn mutate<T, F>(&self, f: F) -> RadosResult<T>
+    where
+        F: FnOnce(&dyn CephBackend) -> RadosResult<T>,
+    {
+        if self.simulate {
+            f(&DryRunBackend::new(&self.rados)?)
+        } else {
+            f(&self.rados)
+        }
+    }
 
+    // Run a command built here that changes the cluster, or only plan it
+    // when simulating
+    fn run_change(&self, command: MonCommand) -> Result<(), RadosError> {
         if !self.simulate {
-            self.run_command(cmd)?;
+            self.run_command(command)?;
+        } else if let Some(e) = command.error() {
+            return Err(RadosError::new(e.to_string()));
+        } else {
+            DryRunBackend::new(&self.rados)?.mon_command(&command.as_value(), &[])?;
         }
         Ok(())
     }
 
+    /// The connection this client owns.  Pass it to any of the `cmd`
+    /// functions that don't have a method here yet.
+    pub fn rados(&self) -> &Rados {
+        &self.rados
+    }
+
+    /// See `Rados::set_command_observer`
+    pub fn set_command_observer(&mut self, observer: CommandObserver) {
+        self.rados.set_command_observer(observer);
+    }
+
+    pub fn osd_out(&self, osd_id: u64) -> Result<(), RadosError> {
+        let osd_id = osd_id.to_string();
+        let cmd = MonCommand::new("osd out")
+            .with("ids", &osd_id);
+
+        self.run_change(cmd)
+    }
+
     pub fn osd_crush_remove(&self, osd_id: u64) -> Result<(), RadosError> {
         let osd_id = format!("osd.{}", osd_id);
-        let cmd = MonCommand::new()
-            .with_prefix("osd crush remove")
+        let cmd = MonCommand::new("osd crush remove")
             .with_name(&osd_id);
-        if !self.simulate {
-            self.run_command(cmd)?;
-        }
-        Ok(())
+        self.run_change(cmd)
     }
 
     /// Query a ceph pool.
     pub fn osd_pool_get(&self, pool: &str, choice: &PoolOption) -> Result<String, RadosError> {
-        let cmd = MonCommand::new()
-            .with_prefix("osd pool get")
+        let cmd = MonCommand::new("osd pool get")
             .with("pool", pool)
             .with("var", choice.as_ref());
         if let Ok(result) = self.run_command(cmd) {
@@ -122,15 +169,11 @@ impl CephClient {
     }
     /// Set a pool value
     pub fn osd_pool_set(&self, pool: &str, key: &str, value: &str) -> Result<(), RadosError> {
-        let cmd = MonCommand::new()
-            .with_prefix("osd pool set")
+        let cmd = MonCommand::new("osd pool set")
             .with("pool", pool)
             .with("var", key)
             .with("value", value);
-        if !self.simulate {
-            self.run_command(cmd)?;
-        }
-        Ok(())
+        self.run_change(cmd)
     }
 
     /// Can be used to set options on an OSD
@@ -150,18 +193,14 @@ impl CephClient {
     pub fn osd_set(&self, key: OsdOption, force: bool) -> Result<(), RadosError> {
         let key = key.to_string();
         let cmd = {
-            let mut c = MonCommand::new()
-                .with_prefix("osd set")
+            let mut c = MonCommand::new("osd set")
                 .with("key", &key);
             if force {
                 c = c.with("sure", "--yes-i-really-mean-it");
             }
             c
         };
-        if !self.simulate {
-            self.run_command(cmd)?;
-        }
-        Ok(())
+        self.run_change(cmd)
     }
 
     /// Can be used to unset options on an OSD
@@ -179,130 +218,696 @@ impl CephClient {
     /// # }
     /// ```
     pub fn osd_unset(&self, key: OsdOption) -> Result<(), RadosError> {
-        cmd::osd_unset(self.rados_t, &key, self.simulate).map_err(|a| a.into())
+        self.mutate(|cluster| cmd::osd_unset(cluster, &key)).map_err(|a| a.into())
     }
 
     pub fn osd_tree(&self) -> Result<cmd::CrushTree, RadosError> {
-        cmd::osd_tree(self.rados_t).map_err(|a| a.into())
+        cmd::osd_tree(&self.rados).map_err(|a| a.into())
     }
 
     /// Get cluster status
-    pub fn status(&self) -> Result<String, RadosError> {
-        let cmd = MonCommand::new()
-            .with_prefix("status")
-            .with_format("json");
-        let return_data = self.run_command(cmd)?;
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(res.into()),
-            None => {
-                Err(RadosError::Error("No response from ceph for status".into()))
-            },
-        }
+    pub fn status(&self) -> Result<cmd::ClusterStatus, RadosError> {
+        Ok(cmd::status(&self.rados)?)
     }
 
     /// List all the monitors in the cluster and their current rank
     pub fn mon_dump(&self) -> Result<cmd::MonDump, RadosError> {
-        Ok(cmd::mon_dump(self.rados_t)?)
+        Ok(cmd::mon_dump(&self.rados)?)
     }
 
     /// Get the mon quorum
     pub fn mon_quorum(&self) -> Result<String, RadosError> {
-        Ok(cmd::mon_quorum(self.rados_t)?)
+        Ok(cmd::mon_quorum(&self.rados)?)
     }
 
     /// Show mon daemon version
     pub fn version(&self) -> Result<CephVersion, RadosError> {
-        cmd::version(self.rados_t)?
+        cmd::version(&self.rados)?
             .parse()
     }
 
+    /// Add a new monitor to the monmap
+    pub fn mon_add(&self, name: &str, addr: &SocketAddr) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::mon_add(cluster, name, addr))?)
+    }
+
+    /// Remove a monitor from the monmap
+    pub fn mon_remove(&self, name: &str) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::mon_remove(cluster, name))?)
+    }
+
+    /// Fetch the metadata for a single monitor
+    pub fn mon_metadata(&self, mon_id: &str) -> Result<cmd::MonMetadata, RadosError> {
+        Ok(cmd::mon_metadata(&self.rados, mon_id)?)
+    }
+
+    /// Get the binary encoded monmap for an epoch or the latest one
+    pub fn mon_getmap(&self, epoch: Option<u64>) -> Result<Vec<u8>, RadosError> {
+        Ok(cmd::mon_getmap(&self.rados, epoch)?)
+    }
+
+    /// Ask a monitor that left the quorum to rejoin it
+    pub fn mon_quorum_enter(&self, mon_name: &str) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::mon_quorum_enter(cluster, mon_name))?)
+    }
+
+    /// Ask a monitor to drop out of the quorum
+    pub fn mon_quorum_exit(&self, mon_name: &str) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::mon_quorum_exit(cluster, mon_name))?)
+    }
+
     pub fn osd_pool_quota_get(&self, pool: &str) -> Result<u64, RadosError> {
-        Ok(cmd::osd_pool_quota_get(self.rados_t, pool)?)
+        Ok(cmd::osd_pool_quota_get(&self.rados, pool)?)
     }
 
     pub fn auth_del(&self, osd_id: u64) -> Result<(), RadosError> {
-        Ok(cmd::auth_del(self.rados_t, osd_id, self.simulate)?)
+        Ok(self.mutate(|cluster| cmd::auth_del(cluster, osd_id))?)
     }
 
     pub fn osd_rm(&self, osd_id: u64) -> Result<(), RadosError> {
-        Ok(cmd::osd_rm(self.rados_t, osd_id, self.simulate)?)
+        Ok(self.mutate(|cluster| cmd::osd_rm(cluster, osd_id))?)
     }
 
     pub fn osd_create(&self, id: Option<u64>) -> Result<u64, RadosError> {
-        Ok(cmd::osd_create(self.rados_t, id, self.simulate)?)
+        Ok(self.mutate(|cluster| cmd::osd_create(cluster, id))?)
     }
 
     // Add a new mgr to the cluster
+    #[cfg(feature = "luminous")]
     pub fn mgr_auth_add(&self, mgr_id: &str) -> Result<(), RadosError> {
-        Ok(cmd::mgr_auth_add(self.rados_t, mgr_id, self.simulate)?)
+        Ok(self.mutate(|cluster| cmd::mgr_auth_add(cluster, mgr_id))?)
     }
 
     // Add a new osd to the cluster
     pub fn osd_auth_add(&self, osd_id: u64) -> Result<(), RadosError> {
-        Ok(cmd::osd_auth_add(self.rados_t, osd_id, self.simulate)?)
+        Ok(self.mutate(|cluster| cmd::osd_auth_add(cluster, osd_id))?)
     }
 
     /// Get a ceph-x key.  The id parameter can be either a number or a string
     /// depending on the type of client so I went with string.
     pub fn auth_get_key(&self, client_type: &str, id: &str) -> Result<String, RadosError> {
-        Ok(cmd::auth_get_key(self.rados_t, client_type, id)?)
+        Ok(cmd::auth_get_key(&self.rados, client_type, id)?)
+    }
+
+    /// List every cephx entity along with its key and caps
+    pub fn auth_list(&self) -> Result<Vec<cmd::AuthEntity>, RadosError> {
+        Ok(cmd::auth_list(&self.rados)?)
+    }
+
+    /// Get the key for an entity, creating it with these caps if needed
+    pub fn auth_get_or_create(&self, entity: &str, caps: &CapsBuilder) -> Result<String, RadosError> {
+        Ok(self.mutate(|cluster| cmd::auth_get_or_create(cluster, entity, caps))?)
+    }
+
+    /// Export an entity and its caps in keyring format
+    pub fn auth_export(&self, entity: &str) -> Result<String, RadosError> {
+        Ok(cmd::auth_export(&self.rados, entity)?)
+    }
+
+    /// Import the entities in a keyring into the auth database
+    pub fn auth_import(&self, keyring: &str) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::auth_import(cluster, keyring))?)
+    }
+
+    /// Get the ceph-x key of any entity
+    pub fn auth_print_key(&self, entity: &cmd::CephEntity) -> Result<String, RadosError> {
+        Ok(cmd::auth_print_key(&self.rados, entity)?)
     }
 
     // ceph osd crush add {id-or-name} {weight}  [{bucket-type}={bucket-name} ...]
     /// add or update crushmap position and weight for an osd
     pub fn osd_crush_add(&self, osd_id: u64, weight: f64, host: &str) -> Result<(), RadosError> {
-        Ok(cmd::osd_crush_add(self.rados_t, osd_id, weight, host, self.simulate)?)
+        Ok(self.mutate(|cluster| cmd::osd_crush_add(cluster, osd_id, weight, host))?)
+    }
+
+    /// Show which osds are blocking peering and how many pgs each one blocks
+    pub fn osd_blocked_by(&self) -> Result<Vec<cmd::OsdBlockedBy>, RadosError> {
+        Ok(cmd::osd_blocked_by(&self.rados)?)
+    }
+
+    /// Find the ip address, host and crush location of an osd
+    pub fn osd_find(&self, osd_id: u64) -> Result<cmd::OsdFind, RadosError> {
+        Ok(cmd::osd_find(&self.rados, osd_id)?)
+    }
+
+    /// Fetch the metadata for a single osd
+    pub fn osd_metadata(&self, osd_id: u64) -> Result<cmd::OsdMetadata, RadosError> {
+        Ok(cmd::osd_metadata(&self.rados, osd_id)?)
+    }
+
+    /// Fetch the metadata for every osd in the cluster
+    pub fn osd_metadata_all(&self) -> Result<Vec<cmd::OsdMetadata>, RadosError> {
+        Ok(cmd::osd_metadata_all(&self.rados)?)
+    }
+
+    /// Get the commit and apply latency of every osd
+    pub fn osd_perf(&self) -> Result<cmd::OsdPerf, RadosError> {
+        Ok(cmd::osd_perf(&self.rados)?)
+    }
+
+    /// Mark an osd as permanently lost.  Any data that only existed on this osd
+    /// is gone for good, so this is only for disaster recovery.
+    pub fn osd_lost(&self, osd_id: u64, confirm: bool) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::osd_lost(cluster, osd_id, confirm))?)
+    }
+
+    /// Get a summary of how many osds are up and in
+    pub fn osd_stat(&self) -> Result<cmd::OsdStat, RadosError> {
+        Ok(cmd::osd_stat(&self.rados)?)
+    }
+
+    /// Dump the latest osd map
+    pub fn osd_dump(&self) -> Result<cmd::OsdMap, RadosError> {
+        Ok(cmd::osd_dump(&self.rados)?)
+    }
+
+    /// Get the binary encoded osd map for an epoch or the latest one
+    pub fn osd_getmap(&self, epoch: Option<u64>) -> Result<Vec<u8>, RadosError> {
+        Ok(cmd::osd_getmap(&self.rados, epoch)?)
+    }
+
+    /// Get the binary encoded CRUSH map
+    pub fn osd_getcrushmap(&self) -> Result<Vec<u8>, RadosError> {
+        Ok(cmd::osd_getcrushmap(&self.rados)?)
+    }
+
+    /// Pause all client io to the cluster and verify the pause took effect
+    pub fn cluster_pause(&self) -> Result<(), RadosError> {
+        Ok(self.mutate(cmd::cluster_pause)?)
+    }
+
+    /// Resume client io to the cluster and verify the pause was lifted
+    pub fn cluster_unpause(&self) -> Result<(), RadosError> {
+        Ok(self.mutate(cmd::cluster_unpause)?)
+    }
+
+    /// Reweight osds that are more than oload percent above the average
+    /// utilization.  When dry_run is set the planned reweights are only
+    /// reported.  Simulate mode always does a dry run.
+    pub fn osd_reweight_by_utilization(&self, oload: Option<u64>, max_change: Option<f64>, max_osds: Option<u64>,
+                                       dry_run: bool)
+        -> Result<cmd::ReweightByUtilization, RadosError> {
+        Ok(cmd::osd_reweight_by_utilization(
+            &self.rados,
+            oload,
+            max_change,
+            max_osds,
+            dry_run || self.simulate,
+        )?)
+    }
+
+    /// Get the average, min and max number of pgs per osd
+    pub fn osd_utilization(&self) -> Result<cmd::OsdUtilization, RadosError> {
+        Ok(cmd::osd_utilization(&self.rados)?)
+    }
+
+    /// Get the maximum number of osds the osd map currently has room for
+    pub fn osd_getmaxosd(&self) -> Result<cmd::MaxOsd, RadosError> {
+        Ok(cmd::osd_getmaxosd(&self.rados)?)
+    }
+
+    /// Set the maximum number of osds the osd map has room for
+    pub fn osd_setmaxosd(&self, max_osd: u64) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::osd_setmaxosd(cluster, max_osd))?)
+    }
+
+    /// Find the pg and the up and acting osd sets that an object maps to
+    pub fn osd_map(&self, pool: &str, object: &str) -> Result<cmd::ObjectPlacement, RadosError> {
+        Ok(cmd::osd_map(&self.rados, pool, object)?)
+    }
+
+    /// List the pool ids and names through the monitor
+    pub fn osd_lspools(&self) -> Result<Vec<(i64, String)>, RadosError> {
+        Ok(cmd::osd_lspools(&self.rados)?)
+    }
+
+    /// Get a value out of the mon config-key store
+    pub fn config_key_get(&self, key: &str) -> Result<String, RadosError> {
+        Ok(cmd::config_key_get(&self.rados, key)?)
+    }
+
+    /// Store a value in the mon config-key store
+    pub fn config_key_set(&self, key: &str, value: &str) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::config_key_set(cluster, key, value))?)
+    }
+
+    /// Dump every key and value in the mon config-key store
+    pub fn config_key_dump(&self) -> Result<HashMap<String, String>, RadosError> {
+        Ok(cmd::config_key_dump(&self.rados)?)
+    }
+
+    /// Change config options on running daemons without restarting them
+    pub fn tell_injectargs(&self, target: &cmd::DaemonTarget, args: &[&str]) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::tell_injectargs(cluster, target, args))?)
+    }
+
+    /// Benchmark the write throughput of a single osd
+    pub fn osd_bench(&self, osd_id: u64, bytes: u64, block_size: u64) -> Result<cmd::OsdBench, RadosError> {
+        Ok(cmd::osd_bench(&self.rados, osd_id, bytes, block_size)?)
+    }
+
+    /// Get the cluster report that support tickets ask for
+    pub fn report(&self) -> Result<cmd::CephReport, RadosError> {
+        Ok(cmd::report(&self.rados)?)
+    }
+
+    /// Get the whole cluster report as json
+    pub fn report_json(&self) -> Result<serde_json::Value, RadosError> {
+        Ok(cmd::report_json(&self.rados)?)
+    }
+
+    /// List the CephFS filesystems and the pools they use
+    pub fn fs_ls(&self) -> Result<Vec<cmd::CephFs>, RadosError> {
+        Ok(cmd::fs_ls(&self.rados)?)
+    }
+
+    /// Create a new CephFS filesystem
+    pub fn fs_new(&self, name: &str, metadata_pool: &str, data_pool: &str) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::fs_new(cluster, name, metadata_pool, data_pool))?)
+    }
+
+    /// Remove a CephFS filesystem, leaving its pools in place
+    pub fn fs_rm(&self, name: &str, confirm: bool) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::fs_rm(cluster, name, confirm))?)
+    }
+
+    /// Get the fsmap with the state of every mds
+    pub fn mds_stat(&self) -> Result<cmd::MdsStat, RadosError> {
+        Ok(cmd::mds_stat(&self.rados)?)
+    }
+
+    /// Fetch the metadata for a single mds
+    pub fn mds_metadata(&self, name: &str) -> Result<cmd::MdsMetadata, RadosError> {
+        Ok(cmd::mds_metadata(&self.rados, name)?)
+    }
+
+    /// Mark an mds as failed so a standby takes over its rank
+    pub fn mds_fail(&self, name_or_gid: &str) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::mds_fail(cluster, name_or_gid))?)
+    }
+
+    /// Clear the damaged flag of a repaired rank
+    pub fn mds_repaired(&self, rank: &str) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::mds_repaired(cluster, rank))?)
+    }
+
+    /// Set an option on a filesystem
+    pub fn fs_set(&self, fs: &str, key: FsOption, value: &str) -> Result<(), RadosError> {
+        Ok(self.mutate(|cluster| cmd::fs_set(cluster, fs, &key, value))?)
+    }
+
+    /// Open an io context for a pool.  It can't outlive this client.
+    pub fn ioctx(&self, pool_name: &str) -> Result<IoCtx, RadosError> {
+        self.rados.ioctx(pool_name)
+    }
+
+    /// List every command the cluster accepts
+    pub fn get_command_descriptions(&self) -> Result<Vec<cmd::CommandDescription>, RadosError> {
+        Ok(cmd::get_command_descriptions(&self.rados)?)
+    }
+
+    /// Check whether the cluster accepts a command prefix before running it
+    pub fn supports_command(&self, prefix: &str) -> Result<bool, RadosError> {
+        Ok(cmd::supports_command(&self.rados, prefix)?)
+    }
+
+    /// Get the json of the commands that the `cmd` calls in `f` would send to
+    /// change the cluster, without sending them
+    ///
+    /// ```rust,no_run
+    /// # use ceph::{cmd, CephClient, PoolOption};
+    /// # use ceph::error::RadosError;
+    /// # fn main() {
+    /// #   let _ = run();
+    /// # }
+    /// # fn run() -> Result<(), RadosError> {
+    /// let client = CephClient::new("admin", "/etc/ceph/ceph.conf")?;
+    /// let plan = client.dry_run(|cluster| cmd::osd_pool_set(cluster, "rbd", &PoolOption::Size, "3"))?;
+    /// for command in plan {
+    ///     println!("{}", command);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn dry_run<F, T>(&self, f: F) -> Result<Vec<serde_json::Value>, RadosError>
+    where
+        F: FnOnce(&dyn CephBackend) -> Result<T, RadosError>,
+    {
+        dry_run(&self.rados, f)
     }
 
     // Luminous + only
 
+    #[cfg(feature = "luminous")]
     pub fn mgr_dump(&self) -> Result<cmd::MgrDump, RadosError> {
         min_version!(Luminous, self);
-        Ok(cmd::mgr_dump(self.rados_t)?)
+        Ok(cmd::mgr_dump(&self.rados)?)
     }
 
+    #[cfg(feature = "luminous")]
     pub fn mgr_fail(&self, mgr_id: &str) -> Result<(), RadosError> {
         min_version!(Luminous, self);
-        Ok(cmd::mgr_fail(self.rados_t, mgr_id, self.simulate)?)
+        Ok(self.mutate(|cluster| cmd::mgr_fail(cluster, mgr_id))?)
     }
 
+    #[cfg(feature = "luminous")]
     pub fn mgr_list_modules(&self) -> Result<Vec<String>, RadosError> {
         min_version!(Luminous, self);
-        Ok(cmd::mgr_list_modules(self.rados_t)?)
+        Ok(cmd::mgr_list_modules(&self.rados)?)
     }
 
+    #[cfg(feature = "luminous")]
     pub fn mgr_list_services(&self) -> Result<Vec<String>, RadosError> {
         min_version!(Luminous, self);
-        Ok(cmd::mgr_list_services(self.rados_t)?)
+        Ok(cmd::mgr_list_services(&self.rados)?)
     }
 
+    #[cfg(feature = "luminous")]
     pub fn mgr_enable_module(&self, module: &str, force: bool) -> Result<(), RadosError> {
         min_version!(Luminous, self);
-        Ok(cmd::mgr_enable_module(self.rados_t, module, force, self.simulate)?)
+        Ok(self.mutate(|cluster| cmd::mgr_enable_module(cluster, module, force))?)
     }
 
+    #[cfg(feature = "luminous")]
     pub fn mgr_disable_module(&self, module: &str) -> Result<(), RadosError> {
         min_version!(Luminous, self);
-        Ok(cmd::mgr_disable_module(self.rados_t, module, self.simulate)?)
+        Ok(self.mutate(|cluster| cmd::mgr_disable_module(cluster, module))?)
     }
 
+    #[cfg(feature = "luminous")]
     pub fn mgr_metadata(&self) -> Result<cmd::MgrMetadata, RadosError> {
         min_version!(Luminous, self);
-        Ok(cmd::mgr_metadata(self.rados_t)?)
+        Ok(cmd::mgr_metadata(&self.rados)?)
     }
 
+    #[cfg(feature = "luminous")]
     pub fn mgr_count_metadata(&self, property: &str) -> Result<HashMap<String, u64>, RadosError> {
         min_version!(Luminous, self);
-        Ok(cmd::mgr_count_metadata(self.rados_t, property)?)
+        Ok(cmd::mgr_count_metadata(&self.rados, property)?)
     }
 
+    #[cfg(feature = "luminous")]
     pub fn mgr_versions(&self) -> Result<HashMap<String, u64>, RadosError> {
         min_version!(Luminous, self);
-        Ok(cmd::mgr_versions(self.rados_t)?)
+        Ok(cmd::mgr_versions(&self.rados)?)
+    }
+
+    /// Mark an osd as destroyed.  The osd id is kept so a replacement osd can
+    /// reuse it.  Ceph refuses to do this unless force is set.
+    #[cfg(feature = "luminous")]
+    pub fn osd_destroy(&self, osd_id: u64, force: bool) -> Result<(), RadosError> {
+        min_version!(Luminous, self);
+        Ok(self.mutate(|cluster| cmd::osd_destroy(cluster, osd_id, force))?)
+    }
+
+    /// Remove an osd from the crush map, delete its auth key and remove it from
+    /// the osd map all in one step.  Ceph refuses to do this unless force is set.
+    #[cfg(feature = "luminous")]
+    pub fn osd_purge(&self, osd_id: u64, force: bool) -> Result<(), RadosError> {
+        min_version!(Luminous, self);
+        Ok(self.mutate(|cluster| cmd::osd_purge(cluster, osd_id, force))?)
+    }
+
+    /// Allocate a new osd id for the osd with this uuid and register its cephx
+    /// secrets in one atomic step.  Returns the osd id.
+    #[cfg(feature = "luminous")]
+    pub fn osd_new(&self, uuid: &Uuid, id: Option<u64>, secrets: Option<&serde_json::Value>)
+        -> Result<u64, RadosError> {
+        min_version!(Luminous, self);
+        Ok(self.mutate(|cluster| cmd::osd_new(cluster, uuid, id, secrets))?)
+    }
+
+    /// Set the oldest client release that is allowed to connect to the cluster
+    #[cfg(feature = "luminous")]
+    pub fn osd_set_require_min_compat_client(&self, release: CephRelease, confirm: bool) -> Result<(), RadosError> {
+        min_version!(Luminous, self);
+        Ok(self.mutate(|cluster| cmd::osd_set_require_min_compat_client(cluster, release, confirm))?)
+    }
+
+    /// Require every osd to be running at least this release
+    #[cfg(feature = "luminous")]
+    pub fn osd_require_osd_release(&self, release: CephRelease, confirm: bool) -> Result<(), RadosError> {
+        min_version!(Luminous, self);
+        Ok(self.mutate(|cluster| cmd::osd_require_osd_release(cluster, release, confirm))?)
+    }
+
+    #[cfg(feature = "luminous")]
+    pub fn osd_versions(&self) -> Result<HashMap<String, u64>, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::osd_versions(&self.rados)?)
+    }
+
+    #[cfg(feature = "luminous")]
+    pub fn osd_count_metadata(&self, property: &str) -> Result<HashMap<String, u64>, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::osd_count_metadata(&self.rados, property)?)
+    }
+
+    /// Explicitly remap a pg away from osds with (from, to) pairs of osd ids
+    #[cfg(feature = "luminous")]
+    pub fn osd_pg_upmap_items(&self, pgid: &str, mappings: &[(u64, u64)]) -> Result<(), RadosError> {
+        min_version!(Luminous, self);
+        Ok(self.mutate(|cluster| cmd::osd_pg_upmap_items(cluster, pgid, mappings))?)
+    }
+
+    /// Remove the explicit upmap overrides for a pg
+    #[cfg(feature = "luminous")]
+    pub fn osd_rm_pg_upmap_items(&self, pgid: &str) -> Result<(), RadosError> {
+        min_version!(Luminous, self);
+        Ok(self.mutate(|cluster| cmd::osd_rm_pg_upmap_items(cluster, pgid))?)
+    }
+
+    /// List the supported mon features and the ones persisted in the monmap
+    #[cfg(feature = "luminous")]
+    pub fn mon_feature_ls(&self) -> Result<cmd::MonFeatures, RadosError> {
+        min_version!(Kraken, self);
+        Ok(cmd::mon_feature_ls(&self.rados)?)
+    }
+
+    /// Persist a mon feature in the monmap.  This can't be undone.
+    #[cfg(feature = "luminous")]
+    pub fn mon_feature_set(&self, feature: &str, confirm: bool) -> Result<(), RadosError> {
+        min_version!(Kraken, self);
+        Ok(self.mutate(|cluster| cmd::mon_feature_set(cluster, feature, confirm))?)
+    }
+
+    #[cfg(feature = "luminous")]
+    pub fn mon_count_metadata(&self, property: &str) -> Result<HashMap<String, u64>, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::mon_count_metadata(&self.rados, property)?)
+    }
+
+    #[cfg(feature = "luminous")]
+    pub fn mon_versions(&self) -> Result<HashMap<String, u64>, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::mon_versions(&self.rados)?)
+    }
+
+    /// Remove a key from the mon config-key store
+    #[cfg(feature = "luminous")]
+    pub fn config_key_rm(&self, key: &str) -> Result<(), RadosError> {
+        min_version!(Luminous, self);
+        Ok(self.mutate(|cluster| cmd::config_key_rm(cluster, key))?)
+    }
+
+    /// List the keys in the mon config-key store
+    #[cfg(feature = "luminous")]
+    pub fn config_key_ls(&self) -> Result<Vec<String>, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::config_key_ls(&self.rados)?)
+    }
+
+    /// Dump every option that is set in the central config database
+    #[cfg(feature = "mimic")]
+    pub fn config_dump(&self) -> Result<Vec<cmd::ConfigEntry>, RadosError> {
+        min_version!(Mimic, self);
+        Ok(cmd::config_dump(&self.rados)?)
+    }
+
+    /// Set an option in the central config database
+    #[cfg(feature = "mimic")]
+    pub fn config_set(&self, who: &str, name: &str, value: &str) -> Result<(), RadosError> {
+        min_version!(Mimic, self);
+        Ok(self.mutate(|cluster| cmd::config_set(cluster, who, name, value))?)
+    }
+
+    /// Get the value of an option from the central config database
+    #[cfg(feature = "mimic")]
+    pub fn config_get(&self, who: &str, name: &str) -> Result<String, RadosError> {
+        min_version!(Mimic, self);
+        Ok(cmd::config_get(&self.rados, who, name)?)
+    }
+
+    /// Set an option of a mgr module
+    #[cfg(feature = "mimic")]
+    pub fn mgr_config_set(&self, module: &str, key: &str, value: &str) -> Result<(), RadosError> {
+        min_version!(Mimic, self);
+        Ok(self.mutate(|cluster| cmd::mgr_config_set(cluster, module, key, value))?)
+    }
+
+    /// Get an option of a mgr module
+    #[cfg(feature = "mimic")]
+    pub fn mgr_config_get(&self, module: &str, key: &str) -> Result<String, RadosError> {
+        min_version!(Mimic, self);
+        Ok(cmd::mgr_config_get(&self.rados, module, key)?)
+    }
+
+    /// Load a ceph.conf into the central config database and get back the
+    /// options that have to stay in the local conf
+    #[cfg(feature = "mimic")]
+    pub fn config_assimilate_conf(&self, conf: &str) -> Result<String, RadosError> {
+        min_version!(Mimic, self);
+        Ok(self.mutate(|cluster| cmd::config_assimilate_conf(cluster, conf))?)
+    }
+
+    /// Get the last n lines of the cluster log
+    #[cfg(feature = "luminous")]
+    pub fn log_last(&self, n: u64, level: cmd::LogLevel, channel: cmd::LogChannel)
+        -> Result<Vec<cmd::LogEntry>, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::log_last(&self.rados, n, level, channel)?)
+    }
+
+    /// Get the clock skew and latency of each monitor
+    #[cfg(feature = "luminous")]
+    pub fn time_sync_status(&self) -> Result<cmd::TimeSyncStatus, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::time_sync_status(&self.rados)?)
+    }
+
+    /// Show the features and releases of everything connected to the cluster
+    #[cfg(feature = "luminous")]
+    pub fn features(&self) -> Result<cmd::ClusterFeatures, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::features(&self.rados)?)
+    }
+
+    /// Count the running versions of every daemon type in the cluster
+    #[cfg(feature = "luminous")]
+    pub fn versions(&self) -> Result<cmd::CephVersions, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::versions(&self.rados)?)
+    }
+
+    /// List the hosts in the cluster and the daemons on each
+    #[cfg(feature = "luminous")]
+    pub fn node_ls(&self, kind: Option<cmd::NodeType>) -> Result<cmd::NodeList, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::node_ls(&self.rados, kind)?)
+    }
+
+    /// Dump the servicemap of registered service daemons
+    #[cfg(feature = "luminous")]
+    pub fn service_dump(&self) -> Result<cmd::ServiceMap, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::service_dump(&self.rados)?)
+    }
+
+    /// Get the last reported status of each registered service daemon
+    #[cfg(feature = "luminous")]
+    pub fn service_status(&self) -> Result<HashMap<String, HashMap<String, cmd::ServiceDaemonStatus>>, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::service_status(&self.rados)?)
+    }
+
+    /// Get the ongoing and completed events of the mgr progress module
+    #[cfg(feature = "nautilus")]
+    pub fn progress(&self) -> Result<cmd::Progress, RadosError> {
+        min_version!(Nautilus, self);
+        Ok(cmd::progress(&self.rados)?)
+    }
+
+    /// Get the events of the mgr progress module as json
+    #[cfg(feature = "nautilus")]
+    pub fn progress_json(&self) -> Result<serde_json::Value, RadosError> {
+        min_version!(Nautilus, self);
+        Ok(cmd::progress_json(&self.rados)?)
+    }
+
+    /// List the devices that the devicehealth module is tracking
+    #[cfg(feature = "nautilus")]
+    pub fn device_ls(&self) -> Result<Vec<cmd::Device>, RadosError> {
+        min_version!(Nautilus, self);
+        Ok(cmd::device_ls(&self.rados)?)
+    }
+
+    /// List the devices attached to one host
+    #[cfg(feature = "nautilus")]
+    pub fn device_ls_by_host(&self, host: &str) -> Result<Vec<cmd::Device>, RadosError> {
+        min_version!(Nautilus, self);
+        Ok(cmd::device_ls_by_host(&self.rados, host)?)
+    }
+
+    /// Get the scraped SMART data for a device
+    #[cfg(feature = "nautilus")]
+    pub fn device_health_metrics(&self, devid: &str) -> Result<HashMap<String, cmd::SmartSummary>, RadosError> {
+        min_version!(Nautilus, self);
+        Ok(cmd::device_health_metrics(&self.rados, devid)?)
+    }
+
+    /// Get the mds ranks, client counts and pool usage of the filesystems
+    #[cfg(feature = "luminous")]
+    pub fn fs_status(&self, fs: Option<&str>) -> Result<cmd::FsStatus, RadosError> {
+        min_version!(Luminous, self);
+        Ok(cmd::fs_status(&self.rados, fs)?)
+    }
+
+    /// Create a subvolume in a CephFS volume
+    #[cfg(feature = "nautilus")]
+    pub fn fs_subvolume_create(&self, vol_name: &str, sub_name: &str, group: Option<&str>, size: Option<u64>)
+        -> Result<(), RadosError> {
+        min_version!(Nautilus, self);
+        Ok(self.mutate(|cluster| cmd::fs_subvolume_create(cluster, vol_name, sub_name, group, size))?)
+    }
+
+    /// List the subvolumes in a volume or subvolume group
+    #[cfg(feature = "nautilus")]
+    pub fn fs_subvolume_ls(&self, vol_name: &str, group: Option<&str>) -> Result<Vec<String>, RadosError> {
+        min_version!(Nautilus, self);
+        Ok(cmd::fs_subvolume_ls(&self.rados, vol_name, group)?)
+    }
+
+    /// Remove a subvolume and its data
+    #[cfg(feature = "nautilus")]
+    pub fn fs_subvolume_rm(&self, vol_name: &str, sub_name: &str, group: Option<&str>, force: bool)
+        -> Result<(), RadosError> {
+        min_version!(Nautilus, self);
+        Ok(self.mutate(|cluster| cmd::fs_subvolume_rm(cluster, vol_name, sub_name, group, force))?)
+    }
+
+    /// Get the path of a subvolume inside the filesystem
+    #[cfg(feature = "nautilus")]
+    pub fn fs_subvolume_getpath(&self, vol_name: &str, sub_name: &str, group: Option<&str>)
+        -> Result<String, RadosError> {
+        min_version!(Nautilus, self);
+        Ok(cmd::fs_subvolume_getpath(&self.rados, vol_name, sub_name, group)?)
+    }
+
+    /// Create a subvolume group in a CephFS volume
+    #[cfg(feature = "nautilus")]
+    pub fn fs_subvolumegroup_create(&self, vol_name: &str, group: &str) -> Result<(), RadosError> {
+        min_version!(Nautilus, self);
+        Ok(self.mutate(|cluster| cmd::fs_subvolumegroup_create(cluster, vol_name, group))?)
+    }
+
+    /// List the subvolume groups in a CephFS volume
+    #[cfg(feature = "nautilus")]
+    pub fn fs_subvolumegroup_ls(&self, vol_name: &str) -> Result<Vec<String>, RadosError> {
+        min_version!(Nautilus, self);
+        Ok(cmd::fs_subvolumegroup_ls(&self.rados, vol_name)?)
+    }
+
+    /// Create a client key for a filesystem limited to a path
+    #[cfg(feature = "luminous")]
+    pub fn fs_authorize(&self, fs: &str, client_id: &str, path: &str, access: &str) -> Result<String, RadosError> {
+        min_version!(Luminous, self);
+        Ok(self.mutate(|cluster| cmd::fs_authorize(cluster, fs, client_id, path, access))?)
     }
 
     pub fn run_command(&self, command: MonCommand) -> Result<String, RadosError> {
+        if let Some(e) = command.error() {
+            return Err(RadosError::new(e.to_string()));
+        }
         let cmd = command.as_json();
         let data: Vec<*mut c_char> = Vec::with_capacity(1);
 
@@ -322,7 +927,7 @@ impl CephClient {
         let ret_code = unsafe {
             // cmd length is 1 because we only allow one command at a time.
             rados::rados_mon_command(
-                self.rados_t,
+                self.rados.as_raw(),
                 &mut cmds.as_ptr(),
                 1,
                 data.as_ptr() as *mut c_char,
@@ -369,4 +974,23 @@ impl CephClient {
         // Ok((str_outbuf, str_outs))
         Ok(str_outbuf)
     }
+
+    /// Run a command and deserialize its json output into any type
+    ///
+    /// ```rust,no_run
+    /// # use ceph::{CephClient, MonCommand};
+    /// # use ceph::error::RadosError;
+    /// # fn main() {
+    /// #   let _ = run();
+    /// # }
+    /// # fn run() -> Result<(), RadosError> {
+    /// let client = CephClient::new("admin", "/etc/ceph/ceph.conf")?;
+    /// let pools: Vec<String> = client.run_command_as(MonCommand::new("osd pool ls"))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run_command_as<T: DeserializeOwned>(&self, command: MonCommand) -> Result<T, RadosError> {
+        let output = self.run_command(command)?;
+        Ok(serde_json::from_str(&output)?)
+    }
 }
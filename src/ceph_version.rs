@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+use std::fmt;
 use std::str::FromStr;
 
 use error::RadosError;
@@ -8,8 +10,14 @@ mod tests {
 
     #[test]
     fn it_compares() {
-        assert!(CephVersion::Argonaut < CephVersion::Bobtail);
-        assert!(CephVersion::Luminous > CephVersion::Jewel);
+        assert!(CephRelease::Argonaut < CephRelease::Bobtail);
+        assert!(CephRelease::Luminous > CephRelease::Jewel);
+
+        let jewel: CephVersion = "ceph version 10.2.9 (2ee413f77150c0f375ff6f10edd6c8f9c7d060d0)".parse().unwrap();
+        let newer_jewel: CephVersion = "ceph version 10.2.11 (e4b061b47f07f583c92a050d9e84b1813a35671e)".parse().unwrap();
+        assert!(newer_jewel > jewel);
+        assert!(jewel >= CephRelease::Jewel);
+        assert!(jewel < CephRelease::Luminous);
     }
 
     #[test]
@@ -17,24 +25,170 @@ mod tests {
         let version: CephVersion = "ceph version 10.2.9 (2ee413f77150c0f375ff6f10edd6c8f9c7d060d0)"
             .parse()
             .unwrap();
-        assert_eq!(version, CephVersion::Jewel);
+        assert_eq!(version, CephRelease::Jewel);
+        assert_eq!((version.major, version.minor, version.patch), (10, 2, 9));
+    }
+
+    #[test]
+    fn it_parses_hammer() {
+        let version: CephVersion = "ceph version 0.94.10 (b1e0532418e4631af01acbc0cedd426f1905f4af)"
+            .parse()
+            .unwrap();
+        assert_eq!(version, CephRelease::Hammer);
+    }
+
+    #[test]
+    fn it_parses_mimic() {
+        let version: CephVersion = "ceph version 13.2.10 (564bdc4ae87418a232fc901524470e1a0f76d641) mimic (stable)"
+            .parse()
+            .unwrap();
+        assert_eq!(version, CephRelease::Mimic);
+        assert_eq!(version.to_string(), "13.2.10 mimic");
+    }
+
+    #[test]
+    fn it_names_releases() {
+        assert_eq!(CephRelease::Luminous.to_string(), "luminous");
+        assert_eq!(::serde_json::to_string(&CephRelease::Nautilus).unwrap(), "\"nautilus\"");
     }
 }
 
+/// A ceph version as reported by the `version` command.  Versions compare by
+/// their numbers and can also be compared against a release:
+///
+/// ```
+/// # use ceph::{CephRelease, CephVersion};
+/// let version: CephVersion = "ceph version 14.2.22 (ca74598065096e6fcbd8433c8779a2be0c889351) nautilus (stable)"
+///     .parse()
+///     .unwrap();
+/// assert!(version >= CephRelease::Luminous);
+/// ```
 #[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
-pub enum CephVersion {
+pub struct CephVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub release: CephRelease,
+}
+
+/// The named Ceph releases as the monitors spell them in commands like
+/// `osd set-require-min-compat-client` and `osd require-osd-release`
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum CephRelease {
+    #[serde(rename = "argonaut")]
     Argonaut,
+    #[serde(rename = "bobtail")]
     Bobtail,
+    #[serde(rename = "cuttlefish")]
     Cuttlefish,
+    #[serde(rename = "dumpling")]
     Dumpling,
+    #[serde(rename = "emperor")]
     Emperor,
+    #[serde(rename = "firefly")]
     Firefly,
+    #[serde(rename = "giant")]
     Giant,
+    #[serde(rename = "hammer")]
     Hammer,
+    #[serde(rename = "infernalis")]
     Infernalis,
+    #[serde(rename = "jewel")]
     Jewel,
+    #[serde(rename = "kraken")]
     Kraken,
+    #[serde(rename = "luminous")]
     Luminous,
+    #[serde(rename = "mimic")]
+    Mimic,
+    #[serde(rename = "nautilus")]
+    Nautilus,
+    #[serde(rename = "octopus")]
+    Octopus,
+    #[serde(rename = "pacific")]
+    Pacific,
+    #[serde(rename = "quincy")]
+    Quincy,
+    #[serde(rename = "reef")]
+    Reef,
+}
+
+impl fmt::Display for CephRelease {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+impl AsRef<str> for CephRelease {
+    fn as_ref(&self) -> &str {
+        match self {
+            &CephRelease::Argonaut => "argonaut",
+            &CephRelease::Bobtail => "bobtail",
+            &CephRelease::Cuttlefish => "cuttlefish",
+            &CephRelease::Dumpling => "dumpling",
+            &CephRelease::Emperor => "emperor",
+            &CephRelease::Firefly => "firefly",
+            &CephRelease::Giant => "giant",
+            &CephRelease::Hammer => "hammer",
+            &CephRelease::Infernalis => "infernalis",
+            &CephRelease::Jewel => "jewel",
+            &CephRelease::Kraken => "kraken",
+            &CephRelease::Luminous => "luminous",
+            &CephRelease::Mimic => "mimic",
+            &CephRelease::Nautilus => "nautilus",
+            &CephRelease::Octopus => "octopus",
+            &CephRelease::Pacific => "pacific",
+            &CephRelease::Quincy => "quincy",
+            &CephRelease::Reef => "reef",
+        }
+    }
+}
+
+impl CephRelease {
+    /// The release a version number belongs to.  Before Infernalis the
+    /// releases were numbered 0.x so the minor number decides.
+    pub fn from_version(major: u32, minor: u32) -> Option<CephRelease> {
+        use CephRelease::*;
+        match (major, minor) {
+            (0, 48) => Some(Argonaut),
+            (0, 56) => Some(Bobtail),
+            (0, 61) => Some(Cuttlefish),
+            (0, 67) => Some(Dumpling),
+            (0, 72) => Some(Emperor),
+            (0, 80) => Some(Firefly),
+            (0, 87) => Some(Giant),
+            (0, 94) => Some(Hammer),
+            (9, _) => Some(Infernalis),
+            (10, _) => Some(Jewel),
+            (11, _) => Some(Kraken),
+            (12, _) => Some(Luminous),
+            (13, _) => Some(Mimic),
+            (14, _) => Some(Nautilus),
+            (15, _) => Some(Octopus),
+            (16, _) => Some(Pacific),
+            (17, _) => Some(Quincy),
+            (18, _) => Some(Reef),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CephVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{} {}", self.major, self.minor, self.patch, self.release)
+    }
+}
+
+impl PartialEq<CephRelease> for CephVersion {
+    fn eq(&self, other: &CephRelease) -> bool {
+        self.release == *other
+    }
+}
+
+impl PartialOrd<CephRelease> for CephVersion {
+    fn partial_cmp(&self, other: &CephRelease) -> Option<Ordering> {
+        Some(self.release.cmp(other))
+    }
 }
 
 impl FromStr for CephVersion {
@@ -44,54 +198,26 @@ impl FromStr for CephVersion {
     /// rados version commands give them:
     /// `ceph version 10.2.9 (2ee413f77150c0f375ff6f10edd6c8f9c7d060d0)`
     fn from_str(s: &str) -> Result<Self, RadosError> {
-
-        use CephVersion::*;
-        let mut parts = s.split(" ");
-        if let (Some(_ceph), Some(_version), Some(version_str)) =
-            (parts.next(), parts.next(), parts.next())
-        {
-            let mut version_parts = version_str.split(".");
-            if let (Some(major), Some(minor), Some(_patch)) =
-                (
-                    version_parts.next(),
-                    version_parts.next(),
-                    version_parts.next(),
-                )
+        let mut parts = s.split(' ');
+        if let (Some(_ceph), Some(_version), Some(version_str)) = (parts.next(), parts.next(), parts.next()) {
+            let mut version_parts = version_str.split('.');
+            if let (Some(major), Some(minor), Some(patch)) =
+                (version_parts.next(), version_parts.next(), version_parts.next())
             {
-                match major {
-                    "12" => return Ok(Luminous),
-                    "11" => return Ok(Kraken),
-                    "10" => return Ok(Jewel),
-                    "9" => return Ok(Infernalis),
-                    "0" => {
-                        match minor {
-                            "94" => return Ok(Hammer),
-                            "97" => return Ok(Giant),
-                            "80" => return Ok(Firefly),
-                            "72" => return Ok(Emperor),
-                            "67" => return Ok(Dumpling),
-                            "61" => return Ok(Cuttlefish),
-                            "56" => return Ok(Bobtail),
-                            "48" => return Ok(Argonaut),
-                            _ => {}
-                        }
+                // Development builds look like 14.2.22-123-gdeadbeef
+                let patch = patch.split('-').next().unwrap_or(patch);
+                if let (Ok(major), Ok(minor), Ok(patch)) = (major.parse(), minor.parse(), patch.parse()) {
+                    if let Some(release) = CephRelease::from_version(major, minor) {
+                        return Ok(CephVersion {
+                            major: major,
+                            minor: minor,
+                            patch: patch,
+                            release: release,
+                        });
                     }
-                    _ => {}
                 }
             }
         }
         Err(RadosError::Parse(s.into()))
     }
 }
-
-// impl Ord for CephVersion {
-//     fn cmp(&self, other: &CephVersion) -> Ordering {
-//         self.height.cmp(&other.height)
-//     }
-// }
-
-// impl PartialOrd for CephVersion {
-//     fn partial_cmp(&self, other: &CephVersion) -> Option<Ordering> {
-//         Some(self.cmp(other))
-//     }
-// }
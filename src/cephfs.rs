@@ -0,0 +1,879 @@
+// Copyright 2017 LambdaStack All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Direct CephFS access through libcephfs, without a kernel or fuse mount.
+//! Only built with the `cephfs` feature, which links against libcephfs.
+//!
+//! ```rust,no_run
+//! # use ceph::cephfs::CephFs;
+//! # use ceph::libcephfs::{O_CREAT, O_TRUNC, O_WRONLY};
+//! # use ceph::error::RadosError;
+//! # fn main() {
+//! #   let _ = run();
+//! # }
+//! # fn run() -> Result<(), RadosError> {
+//! let fs = CephFs::mount("/etc/ceph/ceph.conf", "admin", "/")?;
+//! let mut file = fs.open("/hello", O_WRONLY | O_CREAT | O_TRUNC, 0o644)?;
+//! file.write(b"hello world")?;
+//! file.close()?;
+//! fs.unmount()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ffi::{CStr, CString};
+use std::io::SeekFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{mem, ptr};
+
+use ceph::get_error;
+use error::{RadosError, RadosResult};
+use libc::{c_char, c_int, c_void, dirent, statvfs, timespec, DT_DIR, ENODATA, ERANGE, SEEK_CUR, SEEK_END, SEEK_SET,
+           S_IFDIR, S_IFLNK, S_IFMT, S_IFREG};
+use libcephfs::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_strips_the_client_prefix() {
+        assert_eq!(client_id("client.admin"), "admin");
+        assert_eq!(client_id("admin"), "admin");
+    }
+
+    #[test]
+    fn it_converts_statx() {
+        let mut stx: ceph_statx = unsafe { mem::zeroed() };
+        stx.stx_mode = (S_IFDIR | 0o755) as u16;
+        stx.stx_mtime.tv_sec = 1500000000;
+        stx.stx_mtime.tv_nsec = 5;
+
+        let stat = Stat::from(&stx);
+        assert!(stat.is_dir());
+        assert!(!stat.is_file());
+        assert_eq!(stat.mode & 0o777, 0o755);
+        assert_eq!(stat.mtime, UNIX_EPOCH + Duration::new(1500000000, 5));
+    }
+
+    #[test]
+    fn it_parses_quotas() {
+        assert_eq!(parse_quota(Some("1073741824".to_string())).unwrap(), Some(1073741824));
+        assert_eq!(parse_quota(Some("0".to_string())).unwrap(), None);
+        assert_eq!(parse_quota(None).unwrap(), None);
+        assert!(parse_quota(Some("lots".to_string())).is_err());
+    }
+
+    #[test]
+    fn it_parses_rctime() {
+        assert_eq!(parse_rctime("1500000000.090000000\n").unwrap(), UNIX_EPOCH + Duration::new(1500000000, 90000000));
+        assert_eq!(parse_rctime("1500000000.5").unwrap(), UNIX_EPOCH + Duration::new(1500000000, 500000000));
+        assert_eq!(parse_rctime("1500000000").unwrap(), UNIX_EPOCH + Duration::new(1500000000, 0));
+        assert!(parse_rctime("soon").is_err());
+    }
+
+    #[test]
+    fn it_splits_xattr_names() {
+        assert_eq!(split_xattr_names(b"user.a\0user.b\0"), vec!["user.a", "user.b"]);
+    }
+
+    #[test]
+    fn it_maps_seeks() {
+        assert_eq!(seek_args(SeekFrom::Start(10)), (10, SEEK_SET));
+        assert_eq!(seek_args(SeekFrom::Current(-4)), (-4, SEEK_CUR));
+        assert_eq!(seek_args(SeekFrom::End(0)), (0, SEEK_END));
+    }
+}
+
+/// What `stat` and `lstat` report about a file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Stat {
+    /// The file type and permission bits
+    pub mode: u32,
+    pub ino: u64,
+    pub size: u64,
+    /// Allocated 512 byte blocks
+    pub blocks: u64,
+    pub blksize: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub dev: u64,
+    pub rdev: u64,
+    pub atime: SystemTime,
+    pub mtime: SystemTime,
+    pub ctime: SystemTime,
+    /// When the file was created
+    pub btime: SystemTime,
+    /// Bumped on every change to the file
+    pub version: u64,
+}
+
+impl Stat {
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+}
+
+impl<'a> From<&'a ceph_statx> for Stat {
+    fn from(stx: &ceph_statx) -> Stat {
+        Stat {
+            mode: u32::from(stx.stx_mode),
+            ino: stx.stx_ino,
+            size: stx.stx_size,
+            blocks: stx.stx_blocks,
+            blksize: stx.stx_blksize,
+            nlink: stx.stx_nlink,
+            uid: stx.stx_uid,
+            gid: stx.stx_gid,
+            dev: stx.stx_dev,
+            rdev: stx.stx_rdev,
+            atime: system_time(&stx.stx_atime),
+            mtime: system_time(&stx.stx_mtime),
+            ctime: system_time(&stx.stx_ctime),
+            btime: system_time(&stx.stx_btime),
+            version: stx.stx_version,
+        }
+    }
+}
+
+/// Usage of the filesystem, or of the directory tree under a quota
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StatFs {
+    pub block_size: u64,
+    /// Sizes are counted in these
+    pub fragment_size: u64,
+    pub blocks: u64,
+    pub blocks_free: u64,
+    pub blocks_available: u64,
+    pub files: u64,
+    pub files_free: u64,
+    pub fsid: u64,
+    pub name_max: u64,
+}
+
+/// The limits on a directory tree.  `None` is no limit.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Quota {
+    pub max_bytes: Option<u64>,
+    pub max_files: Option<u64>,
+}
+
+/// The statistics behind `ceph.dir.*`.  The r-prefixed ones cover the
+/// whole tree, the others only the directory's own entries.  The MDS
+/// updates the recursive ones lazily so they can lag behind a little.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirStats {
+    pub entries: u64,
+    pub files: u64,
+    pub subdirs: u64,
+    pub rentries: u64,
+    pub rfiles: u64,
+    pub rsubdirs: u64,
+    pub rbytes: u64,
+    /// The latest ctime of anything in the tree
+    pub rctime: SystemTime,
+}
+
+/// One entry of a directory listing
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirEntry {
+    pub name: String,
+    pub ino: u64,
+    /// One of the libc `DT_*` values
+    pub file_type: u8,
+}
+
+impl DirEntry {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == DT_DIR
+    }
+}
+
+/// The entries of a directory, from `CephFs::opendir`.  "." and ".." are
+/// skipped.  The directory is closed when this is dropped.
+///
+/// With the `cephfs-at` feature, which needs libcephfs from Reef on, the
+/// `*at` methods work on paths relative to this directory, so a caller can
+/// hand out a directory without handing out the whole mount.
+#[derive(Debug)]
+pub struct Dir<'a> {
+    fs: &'a CephFs,
+    dirp: *mut ceph_dir_result,
+    done: bool,
+}
+
+impl<'a> Drop for Dir<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            ceph_closedir(self.fs.cmount, self.dirp);
+        }
+    }
+}
+
+#[cfg(feature = "cephfs-at")]
+impl<'a> Dir<'a> {
+    /// Open a file relative to this directory, like `CephFs::open`
+    pub fn openat(&self, path: &str, flags: OpenFlags, mode: u32) -> RadosResult<File<'a>> {
+        let dirfd = self.fd()?;
+        let path_str = CString::new(path)?;
+        unsafe {
+            let fd = ceph_openat(self.fs.cmount, dirfd, path_str.as_ptr(), flags.bits(), mode);
+            if fd < 0 {
+                return Err(RadosError::new(get_error(fd)?));
+            }
+            Ok(File { fs: self.fs, fd: fd })
+        }
+    }
+
+    pub fn mkdirat(&self, path: &str, mode: u32) -> RadosResult<()> {
+        let dirfd = self.fd()?;
+        let path_str = CString::new(path)?;
+        unsafe {
+            let ret_code = ceph_mkdirat(self.fs.cmount, dirfd, path_str.as_ptr(), mode);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove a file or symlink relative to this directory
+    pub fn unlinkat(&self, path: &str) -> RadosResult<()> {
+        self.remove_at(path, 0)
+    }
+
+    /// Remove an empty directory relative to this directory
+    pub fn rmdirat(&self, path: &str) -> RadosResult<()> {
+        self.remove_at(path, AT_REMOVEDIR)
+    }
+
+    /// Stat a path relative to this directory without following a symlink
+    /// at the end of it
+    pub fn statat(&self, path: &str) -> RadosResult<Stat> {
+        let dirfd = self.fd()?;
+        let path_str = CString::new(path)?;
+        unsafe {
+            let mut stx: ceph_statx = mem::zeroed();
+            let ret_code = ceph_statxat(
+                self.fs.cmount,
+                dirfd,
+                path_str.as_ptr(),
+                &mut stx,
+                CEPH_STATX_ALL_STATS,
+                AT_SYMLINK_NOFOLLOW,
+            );
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            Ok(Stat::from(&stx))
+        }
+    }
+
+    fn remove_at(&self, path: &str, flags: c_int) -> RadosResult<()> {
+        let dirfd = self.fd()?;
+        let path_str = CString::new(path)?;
+        unsafe {
+            let ret_code = ceph_unlinkat(self.fs.cmount, dirfd, path_str.as_ptr(), flags);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    // The descriptor libcephfs keeps for the open directory.  It belongs to
+    // the directory handle and is closed along with it.
+    fn fd(&self) -> RadosResult<c_int> {
+        unsafe {
+            let fd = ceph_dirfd(self.fs.cmount, self.dirp);
+            if fd < 0 {
+                return Err(RadosError::new(get_error(fd)?));
+            }
+            Ok(fd)
+        }
+    }
+}
+
+impl<'a> Iterator for Dir<'a> {
+    type Item = RadosResult<DirEntry>;
+    fn next(&mut self) -> Option<RadosResult<DirEntry>> {
+        while !self.done {
+            unsafe {
+                let mut entry: dirent = mem::zeroed();
+                let ret_code = ceph_readdir_r(self.fs.cmount, self.dirp, &mut entry);
+                if ret_code == 0 {
+                    // We're done
+                    self.done = true;
+                    return None;
+                } else if ret_code < 0 {
+                    self.done = true;
+                    return Some(get_error(ret_code).and_then(|e| Err(RadosError::new(e))));
+                }
+                let name = CStr::from_ptr(entry.d_name.as_ptr()).to_string_lossy().into_owned();
+                if name == "." || name == ".." {
+                    continue;
+                }
+                return Some(Ok(DirEntry {
+                    name: name,
+                    ino: entry.d_ino,
+                    file_type: entry.d_type,
+                }));
+            }
+        }
+        None
+    }
+}
+
+/// A mounted CephFS filesystem.  It is unmounted when dropped.
+#[derive(Debug)]
+pub struct CephFs {
+    cmount: *mut ceph_mount_info,
+}
+
+impl Drop for CephFs {
+    fn drop(&mut self) {
+        unsafe {
+            if ceph_is_mounted(self.cmount) != 0 {
+                ceph_unmount(self.cmount);
+            }
+            ceph_release(self.cmount);
+        }
+    }
+}
+
+impl CephFs {
+    /// Mount the filesystem as `entity`, e.g. "admin" or "client.admin",
+    /// with the settings from a ceph.conf.  `root` is the directory of the
+    /// filesystem that becomes "/" for this mount.
+    pub fn mount(config_file: &str, entity: &str, root: &str) -> RadosResult<CephFs> {
+        CephFs::connect(config_file, entity, None, root)
+    }
+
+    /// Like `mount` but for a cluster with several filesystems.  `fs_name`
+    /// picks one of them instead of the default.
+    pub fn mount_fs(config_file: &str, entity: &str, fs_name: &str, root: &str) -> RadosResult<CephFs> {
+        CephFs::connect(config_file, entity, Some(fs_name), root)
+    }
+
+    fn connect(config_file: &str, entity: &str, fs_name: Option<&str>, root: &str) -> RadosResult<CephFs> {
+        let id_str = CString::new(client_id(entity))?;
+        let conf_str = CString::new(config_file)?;
+        let root_str = CString::new(root)?;
+        let fs_name_str = match fs_name {
+            Some(name) => Some(CString::new(name)?),
+            None => None,
+        };
+        let mut cmount: *mut ceph_mount_info = ptr::null_mut();
+        unsafe {
+            let ret_code = ceph_create(&mut cmount, id_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        // From here on dropping the handle releases it
+        let fs = CephFs { cmount: cmount };
+        unsafe {
+            let ret_code = ceph_conf_read_file(fs.cmount, conf_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            if let Some(ref name) = fs_name_str {
+                let ret_code = ceph_select_filesystem(fs.cmount, name.as_ptr());
+                if ret_code < 0 {
+                    return Err(RadosError::new(get_error(ret_code)?));
+                }
+            }
+            let ret_code = ceph_mount(fs.cmount, root_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(fs)
+    }
+
+    /// Unmount, reporting any error that dropping the mount would hide
+    pub fn unmount(self) -> RadosResult<()> {
+        unsafe {
+            let ret_code = ceph_unmount(self.cmount);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Open a file.  `mode` sets the permissions of a file that `O_CREAT`
+    /// creates, before the umask is applied.
+    pub fn open(&self, path: &str, flags: OpenFlags, mode: u32) -> RadosResult<File> {
+        let path_str = CString::new(path)?;
+        unsafe {
+            let fd = ceph_open(self.cmount, path_str.as_ptr(), flags.bits(), mode);
+            if fd < 0 {
+                return Err(RadosError::new(get_error(fd)?));
+            }
+            Ok(File { fs: self, fd: fd })
+        }
+    }
+
+    /// Create a directory.  Its parent must exist.
+    pub fn mkdir(&self, path: &str, mode: u32) -> RadosResult<()> {
+        let path_str = CString::new(path)?;
+        unsafe {
+            let ret_code = ceph_mkdir(self.cmount, path_str.as_ptr(), mode);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a directory and any missing parents, like `mkdir -p`
+    pub fn mkdirs(&self, path: &str, mode: u32) -> RadosResult<()> {
+        let path_str = CString::new(path)?;
+        unsafe {
+            let ret_code = ceph_mkdirs(self.cmount, path_str.as_ptr(), mode);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove an empty directory
+    pub fn rmdir(&self, path: &str) -> RadosResult<()> {
+        let path_str = CString::new(path)?;
+        unsafe {
+            let ret_code = ceph_rmdir(self.cmount, path_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove a file or symlink
+    pub fn unlink(&self, path: &str) -> RadosResult<()> {
+        let path_str = CString::new(path)?;
+        unsafe {
+            let ret_code = ceph_unlink(self.cmount, path_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Move a file or directory, replacing `to` if it exists
+    pub fn rename(&self, from: &str, to: &str) -> RadosResult<()> {
+        let from_str = CString::new(from)?;
+        let to_str = CString::new(to)?;
+        unsafe {
+            let ret_code = ceph_rename(self.cmount, from_str.as_ptr(), to_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn opendir(&self, path: &str) -> RadosResult<Dir> {
+        let path_str = CString::new(path)?;
+        let mut dirp: *mut ceph_dir_result = ptr::null_mut();
+        unsafe {
+            let ret_code = ceph_opendir(self.cmount, path_str.as_ptr(), &mut dirp);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(Dir {
+            fs: self,
+            dirp: dirp,
+            done: false,
+        })
+    }
+
+    /// Stat a path, following symlinks
+    pub fn stat(&self, path: &str) -> RadosResult<Stat> {
+        self.statx(path, 0)
+    }
+
+    /// Stat a path without following a symlink at the end of it
+    pub fn lstat(&self, path: &str) -> RadosResult<Stat> {
+        self.statx(path, AT_SYMLINK_NOFOLLOW)
+    }
+
+    fn statx(&self, path: &str, flags: u32) -> RadosResult<Stat> {
+        let path_str = CString::new(path)?;
+        unsafe {
+            let mut stx: ceph_statx = mem::zeroed();
+            let ret_code = ceph_statx(self.cmount, path_str.as_ptr(), &mut stx, CEPH_STATX_ALL_STATS, flags);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            Ok(Stat::from(&stx))
+        }
+    }
+
+    /// Usage of the filesystem.  Under a directory with a byte quota this
+    /// reports the quota as the size, like `df` on a mount of it.
+    pub fn statfs(&self, path: &str) -> RadosResult<StatFs> {
+        let path_str = CString::new(path)?;
+        unsafe {
+            let mut stat: statvfs = mem::zeroed();
+            let ret_code = ceph_statfs(self.cmount, path_str.as_ptr(), &mut stat);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            Ok(StatFs {
+                block_size: stat.f_bsize as u64,
+                fragment_size: stat.f_frsize as u64,
+                blocks: stat.f_blocks as u64,
+                blocks_free: stat.f_bfree as u64,
+                blocks_available: stat.f_bavail as u64,
+                files: stat.f_files as u64,
+                files_free: stat.f_ffree as u64,
+                fsid: stat.f_fsid as u64,
+                name_max: stat.f_namemax as u64,
+            })
+        }
+    }
+
+    /// The quota set on a directory itself.  Quotas on its parents still
+    /// apply but aren't reported.
+    pub fn quota_get(&self, path: &str) -> RadosResult<Quota> {
+        Ok(Quota {
+            max_bytes: parse_quota(self.vxattr(path, "ceph.quota.max_bytes")?)?,
+            max_files: parse_quota(self.vxattr(path, "ceph.quota.max_files")?)?,
+        })
+    }
+
+    /// Set both limits of a directory's quota, `None` removes a limit
+    pub fn quota_set(&self, path: &str, quota: &Quota) -> RadosResult<()> {
+        // Zero turns a limit off
+        self.set_vxattr(path, "ceph.quota.max_bytes", &quota.max_bytes.unwrap_or(0).to_string())?;
+        self.set_vxattr(path, "ceph.quota.max_files", &quota.max_files.unwrap_or(0).to_string())?;
+        Ok(())
+    }
+
+    /// Read an extended attribute, `None` when it isn't set.  This also
+    /// reads the ceph.* virtual xattrs, e.g. ceph.dir.layout.
+    pub fn getxattr(&self, path: &str, name: &str) -> RadosResult<Option<Vec<u8>>> {
+        let path_str = CString::new(path)?;
+        let name_str = CString::new(name)?;
+        read_xattr_buffer(|buf, size| unsafe {
+            ceph_getxattr(self.cmount, path_str.as_ptr(), name_str.as_ptr(), buf as *mut c_void, size)
+        })
+    }
+
+    pub fn setxattr(&self, path: &str, name: &str, value: &[u8]) -> RadosResult<()> {
+        let path_str = CString::new(path)?;
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code = ceph_setxattr(
+                self.cmount,
+                path_str.as_ptr(),
+                name_str.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+                0,
+            );
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// The names of the extended attributes set on a path
+    pub fn listxattr(&self, path: &str) -> RadosResult<Vec<String>> {
+        let path_str = CString::new(path)?;
+        let names =
+            read_xattr_buffer(|buf, size| unsafe { ceph_listxattr(self.cmount, path_str.as_ptr(), buf, size) })?;
+        Ok(split_xattr_names(&names.unwrap_or_default()))
+    }
+
+    pub fn removexattr(&self, path: &str, name: &str) -> RadosResult<()> {
+        let path_str = CString::new(path)?;
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code = ceph_removexattr(self.cmount, path_str.as_ptr(), name_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// The recursive statistics the MDS keeps for a directory tree
+    pub fn dir_stats(&self, path: &str) -> RadosResult<DirStats> {
+        let rctime = self.vxattr(path, "ceph.dir.rctime")?.unwrap_or_default();
+        Ok(DirStats {
+            entries: self.vxattr_u64(path, "ceph.dir.entries")?,
+            files: self.vxattr_u64(path, "ceph.dir.files")?,
+            subdirs: self.vxattr_u64(path, "ceph.dir.subdirs")?,
+            rentries: self.vxattr_u64(path, "ceph.dir.rentries")?,
+            rfiles: self.vxattr_u64(path, "ceph.dir.rfiles")?,
+            rsubdirs: self.vxattr_u64(path, "ceph.dir.rsubdirs")?,
+            rbytes: self.vxattr_u64(path, "ceph.dir.rbytes")?,
+            rctime: parse_rctime(&rctime)?,
+        })
+    }
+
+    // Read one of the ceph.* virtual xattrs, `None` when it isn't set
+    fn vxattr(&self, path: &str, name: &str) -> RadosResult<Option<String>> {
+        Ok(self.getxattr(path, name)?.map(|value| String::from_utf8_lossy(&value).into_owned()))
+    }
+
+    fn vxattr_u64(&self, path: &str, name: &str) -> RadosResult<u64> {
+        match self.vxattr(path, name)? {
+            Some(value) => Ok(value.trim().parse::<u64>()?),
+            None => Err(RadosError::new(format!("{} is not set on {}", name, path))),
+        }
+    }
+
+    fn set_vxattr(&self, path: &str, name: &str, value: &str) -> RadosResult<()> {
+        self.setxattr(path, name, value.as_bytes())
+    }
+
+    /// The raw handle for use with the ceph_* functions in `libcephfs`
+    pub fn as_raw(&self) -> *mut ceph_mount_info {
+        self.cmount
+    }
+}
+
+/// An open file on a `CephFs` mount.  It is closed when dropped.
+#[derive(Debug)]
+pub struct File<'a> {
+    fs: &'a CephFs,
+    fd: c_int,
+}
+
+impl<'a> Drop for File<'a> {
+    fn drop(&mut self) {
+        if self.fd >= 0 {
+            unsafe {
+                ceph_close(self.fs.cmount, self.fd);
+            }
+        }
+    }
+}
+
+impl<'a> File<'a> {
+    /// Read into `buf` from the current position, returning how much was
+    /// read.  Zero means the end of the file.
+    pub fn read(&mut self, buf: &mut [u8]) -> RadosResult<usize> {
+        unsafe {
+            // An offset of -1 reads from the current position and moves it
+            let ret_code = ceph_read(self.fs.cmount, self.fd, buf.as_mut_ptr() as *mut c_char, buf.len() as i64, -1);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            Ok(ret_code as usize)
+        }
+    }
+
+    /// Write `data` at the current position, returning how much was written
+    pub fn write(&mut self, data: &[u8]) -> RadosResult<usize> {
+        unsafe {
+            let ret_code = ceph_write(self.fs.cmount, self.fd, data.as_ptr() as *const c_char, data.len() as i64, -1);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            Ok(ret_code as usize)
+        }
+    }
+
+    /// Move the current position, returning the new offset from the start
+    pub fn lseek(&mut self, pos: SeekFrom) -> RadosResult<u64> {
+        let (offset, whence) = seek_args(pos);
+        unsafe {
+            let ret_code = ceph_lseek(self.fs.cmount, self.fd, offset, whence);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code as i32)?));
+            }
+            Ok(ret_code as u64)
+        }
+    }
+
+    /// Flush the file to the OSDs.  With `data_only` the metadata, e.g. the
+    /// mtime, is not synced unless it's needed to read the data back.
+    pub fn fsync(&self, data_only: bool) -> RadosResult<()> {
+        unsafe {
+            let ret_code = ceph_fsync(self.fs.cmount, self.fd, data_only as c_int);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Close the file, reporting any error that dropping it would hide
+    pub fn close(mut self) -> RadosResult<()> {
+        let fd = self.fd;
+        // Keep drop from closing it a second time
+        self.fd = -1;
+        unsafe {
+            let ret_code = ceph_close(self.fs.cmount, fd);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read an extended attribute of the open file, `None` when it isn't
+    /// set
+    pub fn getxattr(&self, name: &str) -> RadosResult<Option<Vec<u8>>> {
+        let name_str = CString::new(name)?;
+        read_xattr_buffer(|buf, size| unsafe {
+            ceph_fgetxattr(self.fs.cmount, self.fd, name_str.as_ptr(), buf as *mut c_void, size)
+        })
+    }
+
+    pub fn setxattr(&self, name: &str, value: &[u8]) -> RadosResult<()> {
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code = ceph_fsetxattr(
+                self.fs.cmount,
+                self.fd,
+                name_str.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+                0,
+            );
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn listxattr(&self) -> RadosResult<Vec<String>> {
+        let names = read_xattr_buffer(|buf, size| unsafe { ceph_flistxattr(self.fs.cmount, self.fd, buf, size) })?;
+        Ok(split_xattr_names(&names.unwrap_or_default()))
+    }
+
+    pub fn removexattr(&self, name: &str) -> RadosResult<()> {
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code = ceph_fremovexattr(self.fs.cmount, self.fd, name_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// The file descriptor for use with the ceph_* functions in `libcephfs`
+    pub fn as_raw(&self) -> c_int {
+        self.fd
+    }
+}
+
+// Run one of the xattr calls that fill a buffer.  Asking with a zero size
+// returns the size needed, which can grow before the real call so that
+// retries on ERANGE.
+fn read_xattr_buffer<F>(read: F) -> RadosResult<Option<Vec<u8>>>
+where
+    F: Fn(*mut c_char, usize) -> c_int,
+{
+    loop {
+        let size = read(ptr::null_mut(), 0);
+        if size == -ENODATA {
+            return Ok(None);
+        } else if size < 0 {
+            return Err(RadosError::new(get_error(size)?));
+        }
+        let mut buffer: Vec<u8> = Vec::with_capacity(size as usize);
+        let ret_code = read(buffer.as_mut_ptr() as *mut c_char, buffer.capacity());
+        if ret_code == -ERANGE {
+            continue;
+        } else if ret_code == -ENODATA {
+            return Ok(None);
+        } else if ret_code < 0 {
+            return Err(RadosError::new(get_error(ret_code)?));
+        }
+        unsafe {
+            buffer.set_len(ret_code as usize);
+        }
+        return Ok(Some(buffer));
+    }
+}
+
+// listxattr returns the names as a run of nul terminated strings
+fn split_xattr_names(buf: &[u8]) -> Vec<String> {
+    buf.split(|b| *b == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect()
+}
+
+// rctime reads as seconds and nanoseconds, e.g. "1500000000.090000000"
+fn parse_rctime(value: &str) -> RadosResult<SystemTime> {
+    let value = value.trim();
+    let mut parts = value.splitn(2, '.');
+    let secs = parts.next().unwrap_or_default().parse::<u64>()?;
+    let nanos = match parts.next() {
+        Some(fraction) => {
+            // Treat it as a decimal fraction in case it isn't padded to 9
+            let digits: String = fraction.chars().chain("000000000".chars()).take(9).collect();
+            digits.parse::<u32>()?
+        },
+        None => 0,
+    };
+    Ok(UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+// Quota vxattrs hold a decimal number with zero meaning no limit
+fn parse_quota(value: Option<String>) -> RadosResult<Option<u64>> {
+    match value {
+        Some(value) => {
+            let limit = value.trim().parse::<u64>()?;
+            Ok(if limit == 0 { None } else { Some(limit) })
+        },
+        None => Ok(None),
+    }
+}
+
+fn system_time(ts: &timespec) -> SystemTime {
+    let since_epoch = Duration::new(ts.tv_sec.unsigned_abs(), ts.tv_nsec as u32);
+    if ts.tv_sec < 0 {
+        UNIX_EPOCH - since_epoch
+    } else {
+        UNIX_EPOCH + since_epoch
+    }
+}
+
+// The offset and whence for ceph_lseek
+fn seek_args(pos: SeekFrom) -> (i64, c_int) {
+    match pos {
+        SeekFrom::Start(offset) => (offset as i64, SEEK_SET),
+        SeekFrom::Current(offset) => (offset, SEEK_CUR),
+        SeekFrom::End(offset) => (offset, SEEK_END),
+    }
+}
+
+// libcephfs wants the id without the "client." type
+fn client_id(entity: &str) -> &str {
+    entity.strip_prefix("client.").unwrap_or(entity)
+}
@@ -0,0 +1,363 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fmt;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc::{c_char, ETIMEDOUT};
+
+use backend::CephBackend;
+use ceph::{self, get_error, IoCtx};
+use cmd;
+use error::{RadosError, RadosResult};
+use log_watch::LogWatch;
+use monmap::MonMap;
+use rados::{rados_service_register, rados_service_update_status, rados_t};
+use serde_json::Value;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Sends through librados
+    #[cfg(not(ceph_without_librados))]
+    fn it_observes_commands() {
+        use std::cell::RefCell;
+        use std::ptr;
+        use std::rc::Rc;
+
+        let records = Rc::new(RefCell::new(Vec::new()));
+        let seen = records.clone();
+        // A handle that was never connected fails every command
+        let mut rados = unsafe { Rados::from_raw(ptr::null_mut()) };
+        rados.set_command_observer(Box::new(move |record: &CommandRecord| seen.borrow_mut().push(record.clone())));
+
+        assert!(cmd::osd_out(&rados, 5).is_err());
+
+        let records = records.borrow();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].target, CommandTarget::Mon(None));
+        assert_eq!(records[0].prefix, "osd out");
+        assert_eq!(records[0].command["ids"], json!(["5"]));
+        assert!(records[0].outcome.is_err());
+    }
+
+    #[test]
+    // Sends through librados
+    #[cfg(not(ceph_without_librados))]
+    fn it_runs_commands_with_a_timeout() {
+        use std::ptr;
+
+        let mut rados = unsafe { Rados::from_raw(ptr::null_mut()) };
+        rados.set_command_timeout(Some(Duration::from_secs(60)));
+        // The unconnected handle's error comes back rather than a timeout
+        match cmd::osd_out(&rados, 5) {
+            Err(e) => assert!(!e.to_string().contains("timed out"), "{}", e),
+            Ok(_) => panic!("an unconnected handle sent a command"),
+        }
+    }
+
+    #[test]
+    fn it_encodes_service_dicts() {
+        let mut dict = BTreeMap::new();
+        dict.insert("zone".to_string(), "us-east".to_string());
+        dict.insert("frontend".to_string(), "beast".to_string());
+        assert_eq!(encode_dict(&dict).unwrap(), b"frontend\0beast\0zone\0us-east\0\0");
+        assert_eq!(encode_dict(&BTreeMap::new()).unwrap(), b"\0");
+    }
+}
+
+/// Where a command was sent
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CommandTarget {
+    /// The monitors, or a single named monitor
+    Mon(Option<String>),
+    Mgr,
+    Osd(i32),
+    /// The primary osd of a pg
+    Pg(String),
+}
+
+/// A command that went out through a `Rados` handle, as handed to the
+/// command observer
+#[derive(Clone, Debug)]
+pub struct CommandRecord {
+    pub target: CommandTarget,
+    pub prefix: String,
+    /// The whole json command, prefix included
+    pub command: Value,
+    pub duration: Duration,
+    /// The error message when the command failed
+    pub outcome: Result<(), String>,
+}
+
+/// Called with every command that goes out through a `Rados` handle
+pub type CommandObserver = Box<dyn Fn(&CommandRecord)>;
+
+/// An owned connection to a cluster.  The connection is shut down when this
+/// is dropped.  Pass it to any of the `cmd` functions.
+pub struct Rados {
+    handle: Arc<Handle>,
+    observer: Option<CommandObserver>,
+    command_timeout: Option<Duration>,
+    // Whether a `LogWatch` holds the cluster log callback
+    log_watched: Cell<bool>,
+}
+
+// The connection.  A command that timed out holds on to it until librados
+// returns, so it's shut down once both are done with it.
+struct Handle(rados_t);
+
+// librados handles can be used from any thread
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Drop for Handle {
+    #[allow(deprecated)]
+    fn drop(&mut self) {
+        ceph::disconnect_from_ceph(self.0);
+    }
+}
+
+impl fmt::Debug for Rados {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rados")
+            .field("handle", &self.handle.0)
+            .field("observer", &self.observer.is_some())
+            .field("command_timeout", &self.command_timeout)
+            .finish()
+    }
+}
+
+impl Rados {
+    /// Connect as `user_id` with the settings from a ceph.conf
+    #[allow(deprecated)]
+    pub fn connect(user_id: &str, config_file: &str) -> RadosResult<Rados> {
+        Ok(Rados::wrap(ceph::connect_to_ceph(user_id, config_file)?))
+    }
+
+    /// Take ownership of a handle from `rados_create` and `rados_connect`.
+    ///
+    /// # Safety
+    ///
+    /// The handle is shut down when the returned `Rados` is dropped so it
+    /// must not be used or disconnected elsewhere afterwards.
+    pub unsafe fn from_raw(handle: rados_t) -> Rados {
+        Rados::wrap(handle)
+    }
+
+    fn wrap(handle: rados_t) -> Rados {
+        Rados {
+            handle: Arc::new(Handle(handle)),
+            observer: None,
+            command_timeout: None,
+            log_watched: Cell::new(false),
+        }
+    }
+
+    /// The raw handle for use with the rados_* functions in `ceph`
+    pub fn as_raw(&self) -> rados_t {
+        self.handle.0
+    }
+
+    /// Hand every mon, mgr, osd and pg command sent through this handle to the
+    /// observer once it finishes.  Useful for keeping an audit trail of the
+    /// changes made to a cluster.
+    pub fn set_command_observer(&mut self, observer: CommandObserver) {
+        self.observer = Some(observer);
+    }
+
+    /// Stop waiting for a mon, mgr, osd or pg command sent through this handle
+    /// once it has run for `timeout`, and fail it with ETIMEDOUT.  Each
+    /// command gets the whole timeout.  `None`, the default, waits for as
+    /// long as the cluster takes.  Object io through the `IoCtx`s of this
+    /// handle isn't limited.
+    ///
+    /// librados can't abandon a command, so one that times out carries on
+    /// in a background thread until the cluster answers and its reply is
+    /// thrown away.  Dropping the `Rados` shuts the connection down only
+    /// after that.
+    pub fn set_command_timeout(&mut self, timeout: Option<Duration>) {
+        self.command_timeout = timeout;
+    }
+
+    /// Open an io context for a pool
+    pub fn ioctx(&self, pool_name: &str) -> RadosResult<IoCtx> {
+        IoCtx::new(self, pool_name)
+    }
+
+    /// Subscribe to the cluster log, like `ceph -w`, getting the entries
+    /// at `level` and above.  Fails with EBUSY while another `LogWatch` of
+    /// this handle is alive.
+    pub fn watch_log(&self, level: cmd::LogLevel) -> RadosResult<LogWatch> {
+        LogWatch::new(self, level)
+    }
+
+    pub(crate) fn log_watched(&self) -> bool {
+        self.log_watched.get()
+    }
+
+    pub(crate) fn set_log_watched(&self, watched: bool) {
+        self.log_watched.set(watched);
+    }
+
+    /// The addresses this client's messenger is bound to, e.g.
+    /// `[v2:10.0.0.5:0/3391,v1:10.0.0.5:0/3391]`.  The monitors list the
+    /// session under these.
+    #[cfg(feature = "nautilus")]
+    pub fn addrs(&self) -> RadosResult<String> {
+        use libc::{c_void, free};
+        use rados::rados_getaddrs;
+        use std::ffi::CStr;
+        use std::ptr;
+
+        let mut addrs: *mut c_char = ptr::null_mut();
+        unsafe {
+            let ret_code = rados_getaddrs(self.as_raw(), &mut addrs);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            let s = CStr::from_ptr(addrs).to_string_lossy().into_owned();
+            free(addrs as *mut c_void);
+            Ok(s)
+        }
+    }
+
+    /// The current monmap, with the name and addresses of every monitor the
+    /// session knows about.  `MonInfo::public_addr` picks the address to
+    /// hand to service discovery.
+    pub fn monmap(&self) -> RadosResult<MonMap> {
+        MonMap::decode(&cmd::mon_getmap(self, None)?)
+    }
+
+    /// Show up as `daemon` of `service` in the services section of
+    /// `ceph status`, e.g. service "gateway" and daemon "gw1.host1".
+    /// `metadata` is static information about the daemon.  librados keeps
+    /// the registration alive for as long as this handle is connected.
+    pub fn service_register(&self, service: &str, daemon: &str, metadata: &BTreeMap<String, String>)
+                            -> RadosResult<()> {
+        let service_str = CString::new(service)?;
+        let daemon_str = CString::new(daemon)?;
+        let metadata = encode_dict(metadata)?;
+        unsafe {
+            let ret_code = rados_service_register(
+                self.as_raw(),
+                service_str.as_ptr(),
+                daemon_str.as_ptr(),
+                metadata.as_ptr() as *const c_char,
+            );
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the live status the registered daemon reports to the mgr
+    pub fn service_update_status(&self, status: &BTreeMap<String, String>) -> RadosResult<()> {
+        let status = encode_dict(status)?;
+        unsafe {
+            let ret_code = rados_service_update_status(self.as_raw(), status.as_ptr() as *const c_char);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    fn observe<T>(&self, target: CommandTarget, cmd: &Value, run: &dyn Fn() -> RadosResult<T>) -> RadosResult<T> {
+        let observer = match self.observer {
+            Some(ref observer) => observer,
+            None => return run(),
+        };
+        let start = Instant::now();
+        let result = run();
+        observer(&CommandRecord {
+            target: target,
+            prefix: cmd["prefix"].as_str().unwrap_or_default().to_string(),
+            command: cmd.clone(),
+            duration: start.elapsed(),
+            outcome: match result {
+                Ok(_) => Ok(()),
+                Err(ref e) => Err(e.to_string()),
+            },
+        });
+        result
+    }
+
+    // Run a command against the raw handle, giving up on it after the
+    // command timeout
+    fn send<F>(&self, command: F) -> RadosResult<(Option<Vec<u8>>, Option<String>)>
+    where
+        F: FnOnce(rados_t) -> RadosResult<(Option<Vec<u8>>, Option<String>)> + Send + 'static,
+    {
+        let timeout = match self.command_timeout {
+            Some(timeout) => timeout,
+            None => return command(self.as_raw()),
+        };
+        let handle = self.handle.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // Nobody is listening any more once the command timed out
+            let _ = sender.send(command(handle.0));
+        });
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(_) => Err(RadosError::new(get_error(-ETIMEDOUT)?)),
+        }
+    }
+}
+
+// The service calls take a map as key\0value\0 pairs ending in an extra
+// nul.  That can't be a CString, which has no nuls inside.
+fn encode_dict(dict: &BTreeMap<String, String>) -> RadosResult<Vec<u8>> {
+    let mut encoded = Vec::new();
+    for (key, value) in dict {
+        if key.contains('\0') || value.contains('\0') {
+            return Err(RadosError::new(format!("Service key or value {:?} contains a nul", key)));
+        }
+        encoded.extend_from_slice(key.as_bytes());
+        encoded.push(0);
+        encoded.extend_from_slice(value.as_bytes());
+        encoded.push(0);
+    }
+    encoded.push(0);
+    Ok(encoded)
+}
+
+impl CephBackend for Rados {
+    fn mon_command_bytes(&self, target: Option<&str>, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        let target = target.map(|t| t.to_string());
+        self.observe(CommandTarget::Mon(target.clone()), cmd, &|| {
+            let (target, cmd, input) = (target.clone(), cmd.clone(), input.to_vec());
+            self.send(move |handle| handle.mon_command_bytes(target.as_deref(), &cmd, &input))
+        })
+    }
+
+    fn mgr_command_bytes(&self, cmd: &Value, input: &[u8]) -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        self.observe(CommandTarget::Mgr, cmd, &|| {
+            let (cmd, input) = (cmd.clone(), input.to_vec());
+            self.send(move |handle| handle.mgr_command_bytes(&cmd, &input))
+        })
+    }
+
+    fn osd_command_bytes(&self, osd_id: i32, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        self.observe(CommandTarget::Osd(osd_id), cmd, &|| {
+            let (cmd, input) = (cmd.clone(), input.to_vec());
+            self.send(move |handle| handle.osd_command_bytes(osd_id, &cmd, &input))
+        })
+    }
+
+    fn pg_command_bytes(&self, pgid: &str, cmd: &Value, input: &[u8])
+        -> RadosResult<(Option<Vec<u8>>, Option<String>)> {
+        self.observe(CommandTarget::Pg(pgid.to_string()), cmd, &|| {
+            let (pgid, cmd, input) = (pgid.to_string(), cmd.clone(), input.to_vec());
+            self.send(move |handle| handle.pg_command_bytes(&pgid, &cmd, &input))
+        })
+    }
+}
@@ -3,16 +3,25 @@
 //! The cli commands mostly use this json based system.  This allows you to
 //! make the exact
 //! same calls without having to shell out with std::process::Command.
-//! Many of the commands defined in this file have a simulate parameter to
-//! allow you to test without actually calling Ceph.
+//! Every command takes a `CephBackend` to send it.  Pass a `Rados` handle
+//! to talk to a cluster or a `MockBackend` to check the commands and the
+//! parsing of canned output offline.  A raw `rados_t` from the deprecated
+//! `connect_to_ceph` is still accepted until it is removed.  Wrap the
+//! handle in a `DryRunBackend` to see what the mutating commands would do
+//! without running them.
 extern crate serde_json;
 
-use ceph::ceph_mon_command_without_data;
+use backend::CephBackend;
+use caps_builder::CapsBuilder;
+use ceph_version::CephRelease;
 use error::{RadosError, RadosResult};
-use rados::rados_t;
-use std::collections::HashMap;
+use serde::Deserialize;
+use serde::de::{self, DeserializeOwned, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::net::SocketAddr;
 use std::str::FromStr;
+use utils::log_snippet;
 use uuid::Uuid;
 
 #[derive(Deserialize, Debug)]
@@ -45,6 +54,7 @@ pub struct CrushTree {
 }
 
 #[derive(Deserialize, Debug)]
+#[cfg(feature = "luminous")]
 pub struct MgrMetadata {
     pub id: String,
     pub arch: String,
@@ -62,6 +72,7 @@ pub struct MgrMetadata {
 }
 
 #[derive(Deserialize, Debug)]
+#[cfg(feature = "luminous")]
 pub struct MgrStandby {
     pub gid: u64,
     pub name: String,
@@ -69,6 +80,7 @@ pub struct MgrStandby {
 }
 
 #[derive(Deserialize, Debug)]
+#[cfg(feature = "luminous")]
 pub struct MgrDump {
     pub epoch: u64,
     pub active_gid: u64,
@@ -80,6 +92,233 @@ pub struct MgrDump {
     pub available_modules: Vec<String>,
 }
 
+/// The servicemap kept by the mgr.  Daemons such as rgw, rbd-mirror and
+/// tcmu-runner register themselves here.
+#[derive(Deserialize, Debug)]
+pub struct ServiceMap {
+    pub epoch: u64,
+    pub modified: String,
+    pub services: HashMap<String, Service>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Service {
+    #[serde(deserialize_with = "service_daemons")]
+    pub daemons: HashMap<String, ServiceDaemon>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ServiceDaemon {
+    pub start_epoch: u64,
+    pub start_stamp: String,
+    pub gid: u64,
+    pub addr: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ServiceDaemonStatus {
+    pub status_stamp: Option<String>,
+    pub last_beacon: Option<String>,
+    #[serde(default)]
+    pub status: HashMap<String, String>,
+}
+
+// The daemons object carries a "summary" string next to the daemon entries
+fn service_daemons<'de, D>(deserializer: D) -> Result<HashMap<String, ServiceDaemon>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw: HashMap<String, serde_json::Value> = Deserialize::deserialize(deserializer)?;
+    let mut daemons = HashMap::new();
+    for (name, daemon) in raw.into_iter().filter(|&(_, ref d)| d.is_object()) {
+        daemons.insert(name, serde_json::from_value(daemon).map_err(de::Error::custom)?);
+    }
+    Ok(daemons)
+}
+
+/// The ongoing and recently completed events of the mgr progress module
+#[derive(Deserialize, Debug)]
+pub struct Progress {
+    pub events: Vec<ProgressEvent>,
+    pub completed: Vec<ProgressEvent>,
+}
+
+/// A long running operation such as a rebalance.  `progress` goes from 0.0
+/// to 1.0.
+#[derive(Deserialize, Debug)]
+pub struct ProgressEvent {
+    pub id: String,
+    pub message: String,
+    pub progress: f64,
+    pub started_at: Option<f64>,
+    pub finished_at: Option<f64>,
+}
+
+/// A physical device the mgr devicehealth module knows about
+#[derive(Deserialize, Debug)]
+#[cfg(feature = "nautilus")]
+pub struct Device {
+    pub devid: String,
+    #[serde(default)]
+    pub location: Vec<DeviceLocation>,
+    #[serde(default)]
+    pub daemons: Vec<String>,
+    pub life_expectancy_min: Option<String>,
+    pub life_expectancy_max: Option<String>,
+    pub life_expectancy_stamp: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg(feature = "nautilus")]
+pub struct DeviceLocation {
+    pub host: String,
+    pub dev: String,
+    pub path: Option<String>,
+}
+
+/// The parts of a smartctl json report that matter for predicting failures.
+/// Fields smartctl couldn't read from the device are left empty.
+#[derive(Deserialize, Debug)]
+#[cfg(feature = "nautilus")]
+pub struct SmartSummary {
+    pub model_name: Option<String>,
+    pub serial_number: Option<String>,
+    pub smart_status: Option<SmartStatus>,
+    pub temperature: Option<SmartTemperature>,
+    pub power_on_time: Option<SmartPowerOnTime>,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg(feature = "nautilus")]
+pub struct SmartStatus {
+    pub passed: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg(feature = "nautilus")]
+pub struct SmartTemperature {
+    pub current: i64,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg(feature = "nautilus")]
+pub struct SmartPowerOnTime {
+    pub hours: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CephFs {
+    pub name: String,
+    pub metadata_pool: String,
+    pub metadata_pool_id: i64,
+    pub data_pools: Vec<String>,
+    pub data_pool_ids: Vec<i64>,
+}
+
+/// The output of the mgr status module's `fs status`
+#[derive(Deserialize, Debug)]
+pub struct FsStatus {
+    pub clients: Vec<FsClients>,
+    pub mdsmap: Vec<FsMdsRank>,
+    pub pools: Vec<FsPoolUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FsClients {
+    pub fs: String,
+    pub clients: u64,
+}
+
+/// An mds in the mdsmap.  Standby daemons have no rank or activity counters.
+#[derive(Deserialize, Debug)]
+pub struct FsMdsRank {
+    pub rank: Option<u64>,
+    pub name: String,
+    pub state: String,
+    pub rate: Option<f64>,
+    pub dns: Option<u64>,
+    pub inos: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FsPoolUsage {
+    pub id: i64,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub pool_type: String,
+    pub used: u64,
+    pub avail: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MdsStat {
+    pub fsmap: FsMap,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FsMap {
+    pub epoch: u64,
+    pub standbys: Vec<MdsInfo>,
+    pub filesystems: Vec<FsMapFilesystem>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FsMapFilesystem {
+    pub id: i64,
+    pub mdsmap: MdsMap,
+}
+
+/// The mdsmap of one filesystem.  `up` is keyed by `mds_<rank>` and `info`
+/// by `gid_<gid>`.
+#[derive(Deserialize, Debug)]
+pub struct MdsMap {
+    pub fs_name: String,
+    pub epoch: u64,
+    pub max_mds: u64,
+    #[serde(rename = "in")]
+    pub in_ranks: Vec<u64>,
+    pub up: HashMap<String, u64>,
+    pub failed: Vec<u64>,
+    pub damaged: Vec<u64>,
+    pub stopped: Vec<u64>,
+    pub info: HashMap<String, MdsInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MdsInfo {
+    pub gid: u64,
+    pub name: String,
+    pub rank: i64,
+    pub incarnation: u64,
+    pub state: String,
+    pub state_seq: u64,
+    pub addr: String,
+    pub standby_for_rank: Option<i64>,
+    pub standby_for_name: Option<String>,
+    pub standby_replay: Option<bool>,
+}
+
+/// Ceph reports every mds metadata value as a string
+#[derive(Deserialize, Debug)]
+pub struct MdsMetadata {
+    pub name: Option<String>,
+    pub addr: Option<String>,
+    pub arch: String,
+    pub ceph_version: String,
+    pub cpu: String,
+    pub distro: String,
+    pub distro_description: String,
+    pub distro_version: String,
+    pub hostname: String,
+    pub kernel_description: String,
+    pub kernel_version: String,
+    pub mem_swap_kb: String,
+    pub mem_total_kb: String,
+    pub os: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct MonDump {
     pub epoch: i64,
@@ -119,6 +358,44 @@ pub struct Mon {
     pub addr: String,
 }
 
+/// The mon features known to this cluster.  `all` is what the monitors
+/// support and `monmap` is what has been recorded in the monmap.
+#[derive(Deserialize, Debug)]
+pub struct MonFeatures {
+    pub all: MonFeatureSet,
+    pub monmap: MonFeatureSet,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MonFeatureSet {
+    #[serde(default)]
+    pub supported: Vec<String>,
+    pub persistent: Vec<String>,
+    #[serde(default)]
+    pub optional: Vec<String>,
+    #[serde(default)]
+    pub required: Vec<String>,
+}
+
+/// Like the osd metadata, ceph reports every mon metadata value as a string
+#[derive(Deserialize, Debug)]
+pub struct MonMetadata {
+    pub name: Option<String>,
+    pub addr: Option<String>,
+    pub arch: String,
+    pub ceph_version: String,
+    pub cpu: String,
+    pub distro: String,
+    pub distro_description: String,
+    pub distro_version: String,
+    pub hostname: String,
+    pub kernel_description: String,
+    pub kernel_version: String,
+    pub mem_swap_kb: String,
+    pub mem_total_kb: String,
+    pub os: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub enum HealthStatus {
     #[serde(rename = "HEALTH_ERR")]
@@ -138,6 +415,144 @@ pub struct ClusterHealth {
     pub detail: Vec<String>,
 }
 
+/// Luminous replaced the health output with a map of named checks such as
+/// `OSD_DOWN`, each with its own severity
+#[derive(Deserialize, Debug)]
+pub struct HealthCheckMap {
+    pub status: HealthStatus,
+    pub checks: HashMap<String, HealthCheck>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HealthCheck {
+    pub severity: HealthStatus,
+    pub summary: HealthCheckMessage,
+    #[serde(default)]
+    pub detail: Vec<HealthCheckMessage>,
+    #[serde(default)]
+    pub muted: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HealthCheckMessage {
+    pub message: String,
+    pub count: Option<u64>,
+}
+
+/// The health of the cluster in whichever format the monitors answered with
+#[derive(Debug)]
+pub enum HealthReport {
+    /// Jewel and older
+    Legacy(ClusterHealth),
+    /// Luminous and newer
+    Checks(HealthCheckMap),
+}
+
+impl HealthReport {
+    pub fn status(&self) -> &HealthStatus {
+        match self {
+            &HealthReport::Legacy(ref health) => &health.overall_status,
+            &HealthReport::Checks(ref health) => &health.status,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for HealthReport {
+    fn deserialize<D>(deserializer: D) -> Result<HealthReport, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let health: serde_json::Value = Deserialize::deserialize(deserializer)?;
+        if health.get("checks").is_some() {
+            Ok(HealthReport::Checks(serde_json::from_value(health).map_err(de::Error::custom)?))
+        } else {
+            Ok(HealthReport::Legacy(serde_json::from_value(health).map_err(de::Error::custom)?))
+        }
+    }
+}
+
+/// The output of `ceph status`
+#[derive(Deserialize, Debug)]
+pub struct ClusterStatus {
+    pub fsid: String,
+    pub health: HealthReport,
+    pub election_epoch: u64,
+    pub quorum: Vec<u64>,
+    pub quorum_names: Vec<String>,
+    pub monmap: StatusMonMap,
+    #[serde(deserialize_with = "status_osd_map")]
+    pub osdmap: StatusOsdMap,
+    pub pgmap: StatusPgMap,
+    pub fsmap: StatusFsMap,
+}
+
+/// Octopus and newer only report the number of monitors, older releases
+/// list them
+#[derive(Deserialize, Debug)]
+pub struct StatusMonMap {
+    pub epoch: u64,
+    #[serde(default)]
+    pub mons: Vec<Mon>,
+    pub num_mons: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusOsdMap {
+    pub epoch: u64,
+    pub num_osds: u64,
+    pub num_up_osds: u64,
+    pub num_in_osds: u64,
+    pub full: Option<bool>,
+    pub nearfull: Option<bool>,
+    pub num_remapped_pgs: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusPgMap {
+    #[serde(default)]
+    pub pgs_by_state: Vec<PgStateCount>,
+    pub num_pgs: u64,
+    pub num_pools: Option<u64>,
+    pub num_objects: Option<u64>,
+    pub data_bytes: u64,
+    pub bytes_used: u64,
+    pub bytes_avail: u64,
+    pub bytes_total: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PgStateCount {
+    pub state_name: String,
+    pub count: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusFsMap {
+    pub epoch: u64,
+    #[serde(default)]
+    pub by_rank: Vec<StatusMdsRank>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusMdsRank {
+    pub filesystem_id: Option<i64>,
+    pub rank: i64,
+    pub name: String,
+    pub status: String,
+}
+
+// Up to Nautilus the osdmap summary is wrapped in a second osdmap object
+fn status_osd_map<'de, D>(deserializer: D) -> Result<StatusOsdMap, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut osd_map: serde_json::Value = Deserialize::deserialize(deserializer)?;
+    if let Some(inner) = osd_map.get_mut("osdmap") {
+        return serde_json::from_value(inner.take()).map_err(de::Error::custom);
+    }
+    serde_json::from_value(osd_map).map_err(de::Error::custom)
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Health {
     pub health_services: Vec<ServiceHealth>,
@@ -159,6 +574,30 @@ pub struct MonTimeChecks {
     pub health: HealthStatus,
 }
 
+/// Clock skew as reported by `time-sync-status`.  Luminous and newer no
+/// longer fill in the timechecks of `health`, so this is where the skew
+/// lives now.
+#[derive(Deserialize, Debug)]
+pub struct TimeSyncStatus {
+    pub time_skew_status: HashMap<String, MonSkew>,
+    pub timechecks: TimeCheckRound,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MonSkew {
+    pub skew: f64,
+    pub latency: f64,
+    pub health: HealthStatus,
+    pub details: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TimeCheckRound {
+    pub epoch: u64,
+    pub round: u64,
+    pub round_status: RoundStatus,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ServiceHealth {
     pub mons: Vec<MonHealth>,
@@ -209,77 +648,553 @@ pub enum MonState {
     Shutdown,
 }
 
-#[derive(Deserialize, Debug, Serialize)]
-pub enum OsdOption {
-    #[serde(rename = "full")]
-    Full,
-    #[serde(rename = "pause")]
-    Pause,
-    #[serde(rename = "noup")]
-    NoUp,
-    #[serde(rename = "nodown")]
-    NoDown,
-    #[serde(rename = "noout")]
-    NoOut,
-    #[serde(rename = "noin")]
-    NoIn,
-    #[serde(rename = "nobackfill")]
-    NoBackfill,
-    #[serde(rename = "norebalance")]
-    NoRebalance,
-    #[serde(rename = "norecover")]
-    NoRecover,
-    #[serde(rename = "noscrub")]
-    NoScrub,
-    #[serde(rename = "nodeep-scrub")]
-    NoDeepScrub,
-    #[serde(rename = "notieragent")]
-    NoTierAgent,
-    #[serde(rename = "sortbitwise")]
-    SortBitwise,
-    #[serde(rename = "recovery_deletes")]
-    RecoveryDeletes,
-    #[serde(rename = "require_jewel_osds")]
-    RequireJewelOsds,
-    #[serde(rename = "require_kraken_osds")]
-    RequireKrakenOsds,
+/// The output of `auth list`
+#[derive(Deserialize, Debug)]
+pub struct AuthDump {
+    pub auth_dump: Vec<AuthEntity>,
 }
 
-impl fmt::Display for OsdOption {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            &OsdOption::Full => write!(f, "full"),
-            &OsdOption::Pause => write!(f, "pause"),
-            &OsdOption::NoUp => write!(f, "noup"),
-            &OsdOption::NoDown => write!(f, "nodown"),
-            &OsdOption::NoOut => write!(f, "noout"),
-            &OsdOption::NoIn => write!(f, "noin"),
-            &OsdOption::NoBackfill => write!(f, "nobackfill"),
-            &OsdOption::NoRebalance => write!(f, "norebalance"),
-            &OsdOption::NoRecover => write!(f, "norecover"),
-            &OsdOption::NoScrub => write!(f, "noscrub"),
-            &OsdOption::NoDeepScrub => write!(f, "nodeep-scrub"),
-            &OsdOption::NoTierAgent => write!(f, "notieragent"),
-            &OsdOption::SortBitwise => write!(f, "sortbitwise"),
-            &OsdOption::RecoveryDeletes => write!(f, "recovery_deletes"),
-            &OsdOption::RequireJewelOsds => write!(f, "require_jewel_osds"),
-            &OsdOption::RequireKrakenOsds => write!(f, "require_kraken_osds"),
-        }
-    }
+/// A cephx entity such as `client.admin` along with its key and the caps
+/// it has for each daemon type
+#[derive(Deserialize, Debug)]
+pub struct AuthEntity {
+    pub entity: String,
+    pub key: String,
+    #[serde(default)]
+    pub caps: HashMap<String, String>,
 }
 
-impl AsRef<str> for OsdOption {
-    fn as_ref(&self) -> &str {
-        match self {
-            &OsdOption::Full => "full",
-            &OsdOption::Pause => "pause",
-            &OsdOption::NoUp => "noup",
-            &OsdOption::NoDown => "nodown",
-            &OsdOption::NoOut => "noout",
-            &OsdOption::NoIn => "noin",
-            &OsdOption::NoBackfill => "nobackfill",
-            &OsdOption::NoRebalance => "norebalance",
-            &OsdOption::NoRecover => "norecover",
+/// One option from the central config database
+#[derive(Deserialize, Debug)]
+#[cfg(feature = "mimic")]
+pub struct ConfigEntry {
+    pub section: String,
+    pub name: String,
+    pub value: String,
+    pub level: String,
+    pub can_update_at_runtime: bool,
+    #[serde(default)]
+    pub mask: String,
+    pub location_type: Option<String>,
+    pub location_value: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ObjectPlacement {
+    pub epoch: u64,
+    pub pool: String,
+    pub pool_id: i64,
+    pub objname: String,
+    pub raw_pgid: String,
+    pub pgid: String,
+    pub up: Vec<i64>,
+    pub up_primary: i64,
+    pub acting: Vec<i64>,
+    pub acting_primary: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MaxOsd {
+    pub epoch: u64,
+    pub max_osd: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OsdBlockedBy {
+    pub id: i64,
+    pub num_blocked: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OsdFind {
+    pub osd: u64,
+    pub ip: String,
+    pub osd_fsid: Option<Uuid>,
+    /// Only reported by Luminous and newer.  Older releases only have the host
+    /// inside of the crush_location map.
+    pub host: Option<String>,
+    pub crush_location: HashMap<String, String>,
+}
+
+/// Ceph reports all of the osd metadata values as strings.  The bluestore and
+/// numa fields are only present on osds that use them.
+#[derive(Deserialize, Debug)]
+pub struct OsdMetadata {
+    pub id: u64,
+    pub arch: String,
+    pub back_addr: String,
+    pub back_iface: Option<String>,
+    pub ceph_version: String,
+    pub cpu: String,
+    pub default_device_class: Option<String>,
+    pub distro: String,
+    pub distro_description: String,
+    pub distro_version: String,
+    pub front_addr: String,
+    pub front_iface: Option<String>,
+    pub hb_back_addr: String,
+    pub hb_front_addr: String,
+    pub hostname: String,
+    pub journal_rotational: Option<String>,
+    pub kernel_description: String,
+    pub kernel_version: String,
+    pub mem_swap_kb: String,
+    pub mem_total_kb: String,
+    pub os: String,
+    pub osd_data: String,
+    pub osd_objectstore: String,
+    pub rotational: Option<String>,
+    pub devices: Option<String>,
+    pub network_numa_node: Option<String>,
+    pub objectstore_numa_node: Option<String>,
+    pub bluefs: Option<String>,
+    pub bluefs_db_dev_node: Option<String>,
+    pub bluefs_db_partition_path: Option<String>,
+    pub bluefs_db_rotational: Option<String>,
+    pub bluefs_db_size: Option<String>,
+    pub bluefs_db_type: Option<String>,
+    pub bluefs_single_shared_device: Option<String>,
+    pub bluestore_bdev_dev_node: Option<String>,
+    pub bluestore_bdev_model: Option<String>,
+    pub bluestore_bdev_partition_path: Option<String>,
+    pub bluestore_bdev_rotational: Option<String>,
+    pub bluestore_bdev_size: Option<String>,
+    pub bluestore_bdev_type: Option<String>,
+}
+
+/// The decoded output of `osd dump`.  The release and ratio fields are only
+/// reported by Luminous and newer.
+#[derive(Deserialize, Debug)]
+pub struct OsdMap {
+    pub epoch: u64,
+    pub fsid: Uuid,
+    pub created: String,
+    pub modified: String,
+    /// Comma separated list of the cluster wide flags, ie: `noout,sortbitwise`
+    pub flags: String,
+    pub crush_version: Option<u64>,
+    pub full_ratio: Option<f64>,
+    pub backfillfull_ratio: Option<f64>,
+    pub nearfull_ratio: Option<f64>,
+    pub cluster_snapshot: String,
+    pub pool_max: i64,
+    pub max_osd: u64,
+    pub require_min_compat_client: Option<CephRelease>,
+    pub min_compat_client: Option<CephRelease>,
+    pub require_osd_release: Option<CephRelease>,
+    pub pools: Vec<OsdMapPool>,
+    pub osds: Vec<OsdMapOsd>,
+    #[serde(default)]
+    pub pg_upmap_items: Vec<PgUpmapItems>,
+    /// Blacklisted client addresses and when their entry expires
+    #[serde(default)]
+    pub blacklist: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OsdMapPool {
+    pub pool: i64,
+    pub pool_name: String,
+    pub flags: u64,
+    pub flags_names: String,
+    #[serde(rename = "type")]
+    pub pool_type: u64,
+    pub size: u64,
+    pub min_size: u64,
+    /// Called crush_ruleset before Luminous
+    pub crush_rule: Option<i64>,
+    pub crush_ruleset: Option<i64>,
+    pub pg_num: u64,
+    pub pg_placement_num: u64,
+    pub quota_max_bytes: u64,
+    pub quota_max_objects: u64,
+    pub tiers: Vec<i64>,
+    pub tier_of: i64,
+    pub erasure_code_profile: String,
+    #[serde(default)]
+    pub application_metadata: HashMap<String, HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OsdMapOsd {
+    pub osd: i64,
+    pub uuid: Uuid,
+    pub up: u8,
+    #[serde(rename = "in")]
+    pub in_cluster: u8,
+    pub weight: f64,
+    pub primary_affinity: f64,
+    pub last_clean_begin: u64,
+    pub last_clean_end: u64,
+    pub up_from: u64,
+    pub up_thru: u64,
+    pub down_at: u64,
+    pub lost_at: u64,
+    pub public_addr: String,
+    pub cluster_addr: String,
+    pub heartbeat_back_addr: String,
+    pub heartbeat_front_addr: String,
+    pub state: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PgUpmapItems {
+    pub pgid: String,
+    pub mappings: Vec<PgUpmapMapping>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PgUpmapMapping {
+    pub from: i64,
+    pub to: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OsdPerf {
+    pub osd_perf_infos: Vec<OsdPerfInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OsdPerfInfo {
+    pub id: u64,
+    pub perf_stats: OsdPerfStats,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OsdPerfStats {
+    pub commit_latency_ms: u64,
+    pub apply_latency_ms: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OsdStat {
+    pub epoch: u64,
+    pub num_osds: u64,
+    pub num_up_osds: u64,
+    pub num_in_osds: u64,
+    pub num_remapped_pgs: u64,
+}
+
+impl OsdStat {
+    pub fn num_down_osds(&self) -> u64 {
+        self.num_osds.saturating_sub(self.num_up_osds)
+    }
+
+    pub fn num_out_osds(&self) -> u64 {
+        self.num_osds.saturating_sub(self.num_in_osds)
+    }
+}
+
+/// Pg per osd balance.  The min and max osd entries are missing when there
+/// are no osds with pgs mapped to them.
+#[derive(Deserialize, Debug)]
+pub struct OsdUtilization {
+    pub avg_pgs: f64,
+    pub std_dev: f64,
+    pub expected_baseline_std_dev: f64,
+    pub min_osd: Option<i64>,
+    pub min_osd_pgs: Option<u64>,
+    pub min_osd_pct: Option<f64>,
+    pub max_osd: Option<i64>,
+    pub max_osd_pgs: Option<u64>,
+    pub max_osd_pct: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OsdReweight {
+    pub osd: u64,
+    pub weight: f64,
+    pub new_weight: f64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReweightByUtilization {
+    pub max_change: f64,
+    pub max_change_osds: u64,
+    pub average_utilization: f64,
+    pub overload_utilization: f64,
+    pub reweights: Vec<OsdReweight>,
+}
+
+/// A cephx entity.  Formats as the `type.id` name that ceph uses, for example
+/// `client.admin` or `osd.3`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CephEntity {
+    Client(String),
+    Osd(u64),
+    Mon(String),
+    Mds(String),
+    Mgr(String),
+}
+
+/// The daemons that a `tell` command can be sent to.  The `All` variants
+/// fan the command out to every running daemon of that type the same way
+/// `ceph tell osd.*` does.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DaemonTarget {
+    Osd(u64),
+    AllOsds,
+    Mon(String),
+    AllMons,
+}
+
+/// The result of an osd bench.  Jewel only reports the bytes written,
+/// block size and throughput.
+#[derive(Deserialize, Debug)]
+pub struct OsdBench {
+    pub bytes_written: u64,
+    pub blocksize: u64,
+    pub elapsed_sec: Option<f64>,
+    pub bytes_per_sec: f64,
+    pub iops: Option<f64>,
+}
+
+/// One line from the cluster log
+#[derive(Deserialize, Debug)]
+pub struct LogEntry {
+    pub name: String,
+    pub rank: String,
+    pub addr: Option<String>,
+    pub stamp: String,
+    pub seq: u64,
+    pub channel: String,
+    pub priority: String,
+    pub message: String,
+}
+
+/// How severe a cluster log entry is, least severe first
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Sec,
+    Warn,
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogChannel {
+    All,
+    Cluster,
+    Audit,
+}
+
+/// The parts of `ceph report` that are stable across releases.  The health
+/// and pgmap sections change shape between releases so they are left as
+/// json.
+#[derive(Deserialize, Debug)]
+pub struct CephReport {
+    pub cluster_fingerprint: String,
+    pub version: String,
+    pub commit: String,
+    pub timestamp: String,
+    pub tag: String,
+    pub health: serde_json::Value,
+    pub osdmap: OsdMap,
+    pub pgmap: serde_json::Value,
+}
+
+/// The hosts in the cluster and the ids of the daemons running on each
+#[derive(Deserialize, Debug)]
+pub struct NodeList {
+    #[serde(default)]
+    pub mon: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub osd: HashMap<String, Vec<u64>>,
+    #[serde(default)]
+    pub mds: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub mgr: HashMap<String, Vec<String>>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NodeType {
+    Mon,
+    Osd,
+    Mds,
+    Mgr,
+}
+
+/// How many daemons of each type are running each ceph version
+#[derive(Deserialize, Debug)]
+pub struct CephVersions {
+    #[serde(default)]
+    pub mon: HashMap<String, u64>,
+    #[serde(default)]
+    pub mgr: HashMap<String, u64>,
+    #[serde(default)]
+    pub osd: HashMap<String, u64>,
+    #[serde(default)]
+    pub mds: HashMap<String, u64>,
+    #[serde(default)]
+    pub overall: HashMap<String, u64>,
+}
+
+impl CephVersions {
+    /// True once every daemon in the cluster runs the same version, which
+    /// is how to tell an upgrade has finished
+    pub fn is_uniform(&self) -> bool {
+        self.overall.len() <= 1
+    }
+}
+
+/// One command the monitors will accept, from `get_command_descriptions`.
+/// The signature holds the literal words of the prefix as plain strings
+/// followed by an object describing each argument.
+#[derive(Deserialize, Debug)]
+pub struct CommandDescription {
+    pub sig: Vec<serde_json::Value>,
+    pub help: String,
+    #[serde(default)]
+    pub module: String,
+    #[serde(default)]
+    pub perm: String,
+    pub avail: Option<String>,
+    pub flags: Option<u64>,
+}
+
+impl CommandDescription {
+    /// The command prefix such as `osd pool create`
+    pub fn prefix(&self) -> String {
+        self.sig
+            .iter()
+            .take_while(|word| word.is_string())
+            .filter_map(|word| word.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
+
+/// The feature groups connected to the cluster for each type of daemon and
+/// for clients.  Each group is a distinct feature bitmask, the newest release
+/// that bitmask satisfies and how many connections have it.
+#[derive(Deserialize, Debug)]
+pub struct ClusterFeatures {
+    #[serde(default, deserialize_with = "feature_groups")]
+    pub mon: Vec<FeatureGroup>,
+    #[serde(default, deserialize_with = "feature_groups")]
+    pub mds: Vec<FeatureGroup>,
+    #[serde(default, deserialize_with = "feature_groups")]
+    pub osd: Vec<FeatureGroup>,
+    #[serde(default, deserialize_with = "feature_groups")]
+    pub client: Vec<FeatureGroup>,
+    #[serde(default, deserialize_with = "feature_groups")]
+    pub mgr: Vec<FeatureGroup>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FeatureGroup {
+    pub features: String,
+    pub release: String,
+    pub num: u64,
+}
+
+// Luminous prints each feature group as a "group" key of an object, repeating
+// the key when there is more than one group.  Mimic switched to an array.
+fn feature_groups<'de, D>(deserializer: D) -> Result<Vec<FeatureGroup>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct GroupsVisitor;
+
+    impl<'de> Visitor<'de> for GroupsVisitor {
+        type Value = Vec<FeatureGroup>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a list or map of feature groups")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut groups = Vec::new();
+            while let Some(group) = seq.next_element()? {
+                groups.push(group);
+            }
+            Ok(groups)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut groups = Vec::new();
+            while let Some((_, group)) = map.next_entry::<String, FeatureGroup>()? {
+                groups.push(group);
+            }
+            Ok(groups)
+        }
+    }
+
+    deserializer.deserialize_any(GroupsVisitor)
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+pub enum OsdOption {
+    #[serde(rename = "full")]
+    Full,
+    #[serde(rename = "pause")]
+    Pause,
+    #[serde(rename = "noup")]
+    NoUp,
+    #[serde(rename = "nodown")]
+    NoDown,
+    #[serde(rename = "noout")]
+    NoOut,
+    #[serde(rename = "noin")]
+    NoIn,
+    #[serde(rename = "nobackfill")]
+    NoBackfill,
+    #[serde(rename = "norebalance")]
+    NoRebalance,
+    #[serde(rename = "norecover")]
+    NoRecover,
+    #[serde(rename = "noscrub")]
+    NoScrub,
+    #[serde(rename = "nodeep-scrub")]
+    NoDeepScrub,
+    #[serde(rename = "notieragent")]
+    NoTierAgent,
+    #[serde(rename = "sortbitwise")]
+    SortBitwise,
+    #[serde(rename = "recovery_deletes")]
+    RecoveryDeletes,
+    #[serde(rename = "require_jewel_osds")]
+    RequireJewelOsds,
+    #[serde(rename = "require_kraken_osds")]
+    RequireKrakenOsds,
+}
+
+impl fmt::Display for OsdOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &OsdOption::Full => write!(f, "full"),
+            &OsdOption::Pause => write!(f, "pause"),
+            &OsdOption::NoUp => write!(f, "noup"),
+            &OsdOption::NoDown => write!(f, "nodown"),
+            &OsdOption::NoOut => write!(f, "noout"),
+            &OsdOption::NoIn => write!(f, "noin"),
+            &OsdOption::NoBackfill => write!(f, "nobackfill"),
+            &OsdOption::NoRebalance => write!(f, "norebalance"),
+            &OsdOption::NoRecover => write!(f, "norecover"),
+            &OsdOption::NoScrub => write!(f, "noscrub"),
+            &OsdOption::NoDeepScrub => write!(f, "nodeep-scrub"),
+            &OsdOption::NoTierAgent => write!(f, "notieragent"),
+            &OsdOption::SortBitwise => write!(f, "sortbitwise"),
+            &OsdOption::RecoveryDeletes => write!(f, "recovery_deletes"),
+            &OsdOption::RequireJewelOsds => write!(f, "require_jewel_osds"),
+            &OsdOption::RequireKrakenOsds => write!(f, "require_kraken_osds"),
+        }
+    }
+}
+
+impl AsRef<str> for OsdOption {
+    fn as_ref(&self) -> &str {
+        match self {
+            &OsdOption::Full => "full",
+            &OsdOption::Pause => "pause",
+            &OsdOption::NoUp => "noup",
+            &OsdOption::NoDown => "nodown",
+            &OsdOption::NoOut => "noout",
+            &OsdOption::NoIn => "noin",
+            &OsdOption::NoBackfill => "nobackfill",
+            &OsdOption::NoRebalance => "norebalance",
+            &OsdOption::NoRecover => "norecover",
             &OsdOption::NoScrub => "noscrub",
             &OsdOption::NoDeepScrub => "nodeep-scrub",
             &OsdOption::NoTierAgent => "notieragent",
@@ -493,6 +1408,38 @@ impl AsRef<str> for PoolOption {
     }
 }
 
+#[derive(Deserialize, Debug, Serialize)]
+pub enum FsOption {
+    #[serde(rename = "max_mds")]
+    MaxMds,
+    #[serde(rename = "standby_count_wanted")]
+    StandbyCountWanted,
+    #[serde(rename = "allow_standby_replay")]
+    AllowStandbyReplay,
+    #[serde(rename = "joinable")]
+    Joinable,
+    #[serde(rename = "down")]
+    Down,
+}
+
+impl fmt::Display for FsOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+impl AsRef<str> for FsOption {
+    fn as_ref(&self) -> &str {
+        match self {
+            &FsOption::MaxMds => "max_mds",
+            &FsOption::StandbyCountWanted => "standby_count_wanted",
+            &FsOption::AllowStandbyReplay => "allow_standby_replay",
+            &FsOption::Joinable => "joinable",
+            &FsOption::Down => "down",
+        }
+    }
+}
+
 impl fmt::Display for HealthStatus {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -531,59 +1478,141 @@ impl AsRef<str> for RoundStatus {
     }
 }
 
-pub fn cluster_health(cluster_handle: rados_t) -> RadosResult<ClusterHealth> {
-    let cmd = json!({
-        "prefix": "health",
-    });
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
-            None => {
-                return Err(RadosError::Error(format!(
-                "Unable to parse health output: {:?}",
-                return_data,
-            )))
-            },
+impl fmt::Display for CephEntity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &CephEntity::Client(ref id) => write!(f, "client.{}", id),
+            &CephEntity::Osd(id) => write!(f, "osd.{}", id),
+            &CephEntity::Mon(ref id) => write!(f, "mon.{}", id),
+            &CephEntity::Mds(ref id) => write!(f, "mds.{}", id),
+            &CephEntity::Mgr(ref id) => write!(f, "mgr.{}", id),
         }
     }
-    Err(RadosError::Error(result.1.unwrap_or(
-        "No response from ceph for health".into(),
-    )))
 }
 
-pub fn osd_out(cluster_handle: rados_t, osd_id: u64, simulate: bool) -> RadosResult<()> {
-    let cmd = json!({
-        "prefix": "osd out",
-        "ids": [osd_id.to_string()]
-    });
+impl fmt::Display for DaemonTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &DaemonTarget::Osd(id) => write!(f, "osd.{}", id),
+            &DaemonTarget::AllOsds => write!(f, "osd.*"),
+            &DaemonTarget::Mon(ref id) => write!(f, "mon.{}", id),
+            &DaemonTarget::AllMons => write!(f, "mon.*"),
+        }
+    }
+}
 
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
+impl fmt::Display for NodeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_ref())
     }
-    Ok(())
 }
 
-pub fn osd_crush_remove(cluster_handle: rados_t, osd_id: u64, simulate: bool) -> RadosResult<()> {
-    let cmd = json!({
-        "prefix": "osd crush remove",
-        "name": format!("osd.{}", osd_id),
-    });
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
+impl AsRef<str> for NodeType {
+    fn as_ref(&self) -> &str {
+        match self {
+            &NodeType::Mon => "mon",
+            &NodeType::Osd => "osd",
+            &NodeType::Mds => "mds",
+            &NodeType::Mgr => "mgr",
+        }
     }
-    Ok(())
 }
 
-/// Query a ceph pool.
-pub fn osd_pool_get(cluster_handle: rados_t, pool: &str, choice: &PoolOption) -> RadosResult<String> {
-    let cmd = json!({
-        "prefix": "osd pool get",
-        "pool": pool,
-        "var": choice,
-    });
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+impl AsRef<str> for LogLevel {
+    fn as_ref(&self) -> &str {
+        match self {
+            &LogLevel::Debug => "debug",
+            &LogLevel::Info => "info",
+            &LogLevel::Sec => "sec",
+            &LogLevel::Warn => "warn",
+            &LogLevel::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for LogChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+impl AsRef<str> for LogChannel {
+    fn as_ref(&self) -> &str {
+        match self {
+            &LogChannel::All => "*",
+            &LogChannel::Cluster => "cluster",
+            &LogChannel::Audit => "audit",
+        }
+    }
+}
+
+// Deserialize the whole output buffer of a command that was asked for json.
+// Some daemons pretty print their json so it can't be read line by line.
+fn json_output<T>(result: (Option<String>, Option<String>), cmd: &serde_json::Value) -> RadosResult<T>
+where
+    T: DeserializeOwned,
+{
+    let prefix = cmd["prefix"].as_str().unwrap_or_default();
+    let err = match result.0 {
+        Some(ref return_data) if !return_data.trim().is_empty() => {
+            match serde_json::from_str(return_data) {
+                Ok(parsed) => return Ok(parsed),
+                Err(e) => {
+                    debug!("Unable to parse {} output: {}: {}", prefix, e, log_snippet(return_data));
+                    e.into()
+                },
+            }
+        },
+        Some(ref return_data) => RadosError::Error(format!("Unable to parse {} output: {:?}", prefix, return_data)),
+        None => RadosError::Error(result.1.unwrap_or_else(|| format!("No response from ceph for {}", prefix))),
+    };
+    Err(RadosError::command_failed(cmd, err))
+}
+
+/// Get the cluster health.  Works with both the pre-Luminous health format
+/// and the health checks of Luminous and newer.
+pub fn cluster_health(cluster_handle: &dyn CephBackend) -> RadosResult<HealthReport> {
+    let cmd = json!({
+        "prefix": "health",
+        "format": "json",
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+pub fn osd_out(cluster_handle: &dyn CephBackend, osd_id: u64) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "osd out",
+        "ids": [osd_id.to_string()]
+    });
+
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+pub fn osd_crush_remove(cluster_handle: &dyn CephBackend, osd_id: u64) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "osd crush remove",
+        "name": format!("osd.{}", osd_id),
+    });
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Query a ceph pool.
+pub fn osd_pool_get(cluster_handle: &dyn CephBackend, pool: &str, choice: &PoolOption) -> RadosResult<String> {
+    let cmd = json!({
+        "prefix": "osd pool get",
+        "pool": pool,
+        "var": choice,
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
     if let Some(return_data) = result.0 {
         let mut l = return_data.lines();
         match l.next() {
@@ -602,7 +1631,7 @@ pub fn osd_pool_get(cluster_handle: rados_t, pool: &str, choice: &PoolOption) ->
 }
 
 /// Set a pool value
-pub fn osd_pool_set(cluster_handle: rados_t, pool: &str, key: &PoolOption, value: &str, simulate: bool)
+pub fn osd_pool_set(cluster_handle: &dyn CephBackend, pool: &str, key: &PoolOption, value: &str)
     -> RadosResult<()> {
     let cmd = json!({
         "prefix": "osd pool set",
@@ -610,13 +1639,11 @@ pub fn osd_pool_set(cluster_handle: rados_t, pool: &str, key: &PoolOption, value
         "var": key,
         "val": value,
     });
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    }
+    cluster_handle.mon_command(&cmd, &[])?;
     Ok(())
 }
 
-pub fn osd_set(cluster_handle: rados_t, key: &OsdOption, force: bool, simulate: bool) -> RadosResult<()> {
+pub fn osd_set(cluster_handle: &dyn CephBackend, key: &OsdOption, force: bool) -> RadosResult<()> {
     let cmd = match force {
         true => {
             json!({
@@ -632,137 +1659,74 @@ pub fn osd_set(cluster_handle: rados_t, key: &OsdOption, force: bool, simulate:
             })
         },
     };
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    }
+    cluster_handle.mon_command(&cmd, &[])?;
     Ok(())
 }
 
-pub fn osd_unset(cluster_handle: rados_t, key: &OsdOption, simulate: bool) -> RadosResult<()> {
+pub fn osd_unset(cluster_handle: &dyn CephBackend, key: &OsdOption) -> RadosResult<()> {
     let cmd = json!({
         "prefix": "osd unset",
         "key": key,
     });
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    }
+    cluster_handle.mon_command(&cmd, &[])?;
     Ok(())
 }
 
-pub fn osd_tree(cluster_handle: rados_t) -> RadosResult<CrushTree> {
+pub fn osd_tree(cluster_handle: &dyn CephBackend) -> RadosResult<CrushTree> {
     let cmd = json!({
         "prefix": "osd tree",
         "format": "json"
     });
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
-            None => {
-                return Err(RadosError::Error(format!(
-                "Unable to parse osd tree output: {:?}",
-                return_data,
-            )))
-            },
-        }
-    }
-    Err(RadosError::Error("No response from ceph for osd tree".into()))
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
 }
 
 // Get cluster status
-pub fn status(cluster_handle: rados_t) -> RadosResult<String> {
+pub fn status(cluster_handle: &dyn CephBackend) -> RadosResult<ClusterStatus> {
     let cmd = json!({
         "prefix": "status",
         "format": "json"
     });
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(res.into()),
-            None => {
-                return Err(RadosError::Error(format!(
-                "Unable to parse status output: {:?}",
-                return_data,
-            )))
-            },
-        }
-    }
-    Err(RadosError::Error("No response from ceph for status".into()))
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
 }
 
 /// List all the monitors in the cluster and their current rank
-pub fn mon_dump(cluster_handle: rados_t) -> RadosResult<MonDump> {
+pub fn mon_dump(cluster_handle: &dyn CephBackend) -> RadosResult<MonDump> {
     let cmd = json!({
         "prefix": "mon dump",
         "format": "json"
     });
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
-            None => {
-                return Err(RadosError::Error(format!(
-                "Unable to parse mon dump output: {:?}",
-                return_data,
-            )))
-            },
-        }
-    }
-    Err(RadosError::Error("No response from ceph for mon dump".into()))
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
 }
 
 /// Get the mon quorum
-pub fn mon_quorum(cluster_handle: rados_t) -> RadosResult<String> {
+pub fn mon_quorum(cluster_handle: &dyn CephBackend) -> RadosResult<String> {
     let cmd = json!({
         "prefix": "quorum_status",
         "format": "json"
     });
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
-            None => {
-                return Err(RadosError::Error(format!(
-                "Unable to parse quorum_status output: {:?}",
-                return_data,
-            )))
-            },
-        }
-    }
-    Err(RadosError::Error("No response from ceph for quorum_status".into()))
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
 }
 
 /// Get the mon status
-pub fn mon_status(cluster_handle: rados_t) -> RadosResult<MonStatus> {
+pub fn mon_status(cluster_handle: &dyn CephBackend) -> RadosResult<MonStatus> {
     let cmd = json!({
         "prefix": "mon_status",
+        "format": "json",
     });
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
-            None => {
-                return Err(RadosError::Error(format!(
-                "Unable to parse mon_status output: {:?}",
-                return_data,
-            )))
-            },
-        }
-    }
-    Err(RadosError::Error("No response from ceph for mon_status".into()))
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
 }
 
 /// Show mon daemon version
-pub fn version(cluster_handle: rados_t) -> RadosResult<String> {
+pub fn version(cluster_handle: &dyn CephBackend) -> RadosResult<String> {
     let cmd = json!({
         "prefix": "version",
     });
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
+    let result = cluster_handle.mon_command(&cmd, &[])?;
     if let Some(return_data) = result.0 {
         let mut l = return_data.lines();
         match l.next() {
@@ -779,12 +1743,232 @@ pub fn version(cluster_handle: rados_t) -> RadosResult<String> {
 }
 
 
-pub fn osd_pool_quota_get(cluster_handle: rados_t, pool: &str) -> RadosResult<u64> {
+/// Get the clock skew and latency of each monitor relative to the leader
+#[cfg(feature = "luminous")]
+pub fn time_sync_status(cluster_handle: &dyn CephBackend) -> RadosResult<TimeSyncStatus> {
+    let cmd = json!({
+        "prefix": "time-sync-status",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// List the hosts in the cluster and the daemons on each.  When a kind is
+/// given only that type of daemon is filled in.
+#[cfg(feature = "luminous")]
+pub fn node_ls(cluster_handle: &dyn CephBackend, kind: Option<NodeType>) -> RadosResult<NodeList> {
+    let mut cmd = json!({
+        "prefix": "node ls",
+        "format": "json"
+    });
+    if let Some(kind) = kind {
+        cmd["type"] = json!(kind.as_ref());
+    }
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    let nodes: serde_json::Value = json_output(result, &cmd)?;
+    // Asking for one type drops the outer level of the output
+    let nodes = match kind {
+        Some(kind) => json!({ kind.as_ref(): nodes }),
+        None => nodes,
+    };
+    Ok(serde_json::from_value(nodes)?)
+}
+
+/// Count the running versions of every daemon type in the cluster
+#[cfg(feature = "luminous")]
+pub fn versions(cluster_handle: &dyn CephBackend) -> RadosResult<CephVersions> {
+    let cmd = json!({
+        "prefix": "versions",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Show the feature bits and releases of the daemons and clients connected
+/// to the cluster.  Check this for old clients before turning on things like
+/// upmap that they can't understand.
+#[cfg(feature = "luminous")]
+pub fn features(cluster_handle: &dyn CephBackend) -> RadosResult<ClusterFeatures> {
+    let cmd = json!({
+        "prefix": "features",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// List every command the monitors know about, including the ones they
+/// forward to the mgr
+pub fn get_command_descriptions(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<CommandDescription>> {
+    let cmd = json!({
+        "prefix": "get_command_descriptions",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    // Keyed cmd000, cmd001, ... so sorting the keys keeps the mon's order
+    let commands: BTreeMap<String, CommandDescription> = json_output(result, &cmd)?;
+    Ok(commands.into_iter().map(|(_, desc)| desc).collect())
+}
+
+/// Check whether the cluster accepts a command prefix such as
+/// `osd pool autoscale-status` before trying to run it.  Older clusters
+/// answer unknown commands with EINVAL which is hard to tell apart from a
+/// bad argument.
+pub fn supports_command(cluster_handle: &dyn CephBackend, prefix: &str) -> RadosResult<bool> {
+    let prefix = prefix.split_whitespace().collect::<Vec<&str>>().join(" ");
+    Ok(get_command_descriptions(cluster_handle)?
+        .iter()
+        .any(|desc| desc.prefix() == prefix))
+}
+
+/// Add a new monitor to the monmap
+pub fn mon_add(cluster_handle: &dyn CephBackend, name: &str, addr: &SocketAddr) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "mon add",
+        "name": name,
+        "addr": addr.to_string(),
+    });
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Remove a monitor from the monmap
+pub fn mon_remove(cluster_handle: &dyn CephBackend, name: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "mon remove",
+        "name": name,
+    });
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// List the mon features that are supported and the ones that are persisted
+/// in the monmap
+#[cfg(feature = "luminous")]
+pub fn mon_feature_ls(cluster_handle: &dyn CephBackend) -> RadosResult<MonFeatures> {
+    let cmd = json!({
+        "prefix": "mon feature ls",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Persist a mon feature such as `kraken` or `luminous` in the monmap.  This
+/// can't be undone so ceph wants confirmation before it will do it.
+#[cfg(feature = "luminous")]
+pub fn mon_feature_set(cluster_handle: &dyn CephBackend, feature: &str, confirm: bool) -> RadosResult<()> {
+    let cmd = match confirm {
+        true => {
+            json!({
+                "prefix": "mon feature set",
+                "feature_name": feature,
+                "sure": "--yes-i-really-mean-it",
+            })
+        },
+        false => {
+            json!({
+                "prefix": "mon feature set",
+                "feature_name": feature,
+            })
+        },
+    };
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Fetch the metadata for a single monitor
+pub fn mon_metadata(cluster_handle: &dyn CephBackend, mon_id: &str) -> RadosResult<MonMetadata> {
+    let cmd = json!({
+        "prefix": "mon metadata",
+        "id": mon_id,
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// count ceph-mon daemons by metadata field property
+#[cfg(feature = "luminous")]
+pub fn mon_count_metadata(cluster_handle: &dyn CephBackend, property: &str) -> RadosResult<HashMap<String, u64>> {
+    let cmd = json!({
+        "prefix": "mon count-metadata",
+        "format": "json",
+        "property": property,
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// check running versions of ceph-mon daemons
+#[cfg(feature = "luminous")]
+pub fn mon_versions(cluster_handle: &dyn CephBackend) -> RadosResult<HashMap<String, u64>> {
+    let cmd = json!({
+        "prefix": "mon versions",
+        "format": "json",
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the binary encoded monmap, the same data `ceph mon getmap -o <file>`
+/// writes out for `ceph-mon --inject-monmap` and monmaptool.  When no epoch
+/// is given the latest monmap is returned.
+pub fn mon_getmap(cluster_handle: &dyn CephBackend, epoch: Option<u64>) -> RadosResult<Vec<u8>> {
+    let cmd = match epoch {
+        Some(epoch) => {
+            json!({
+                "prefix": "mon getmap",
+                "epoch": epoch,
+            })
+        },
+        None => {
+            json!({
+                "prefix": "mon getmap",
+            })
+        },
+    };
+    let result = cluster_handle.mon_command_bytes(None, &cmd, &[])?;
+    match result.0 {
+        Some(map) => Ok(map),
+        None => Err(RadosError::Error(result.1.unwrap_or(
+            "No response from ceph for mon getmap".into(),
+        ))),
+    }
+}
+
+/// Ask a monitor that previously left the quorum to rejoin it.  The command is
+/// sent to the named monitor directly.
+pub fn mon_quorum_enter(cluster_handle: &dyn CephBackend, mon_name: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "quorum",
+        "quorumcmd": "enter",
+    });
+    cluster_handle.mon_command_target(mon_name, &cmd)?;
+    Ok(())
+}
+
+/// Ask the named monitor to drop out of the quorum, for example before doing
+/// maintenance on its host.  The monitor keeps running.
+pub fn mon_quorum_exit(cluster_handle: &dyn CephBackend, mon_name: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "quorum",
+        "quorumcmd": "exit",
+    });
+    cluster_handle.mon_command_target(mon_name, &cmd)?;
+    Ok(())
+}
+
+pub fn osd_pool_quota_get(cluster_handle: &dyn CephBackend, pool: &str) -> RadosResult<u64> {
     let cmd = json!({
         "prefix": "osd pool get-quota",
         "pool": pool
     });
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
+    let result = cluster_handle.mon_command(&cmd, &[])?;
     if let Some(return_data) = result.0 {
         let mut l = return_data.lines();
         match l.next() {
@@ -800,32 +1984,28 @@ pub fn osd_pool_quota_get(cluster_handle: rados_t, pool: &str) -> RadosResult<u6
     Err(RadosError::Error("No response from ceph for osd pool quota-get".into()))
 }
 
-pub fn auth_del(cluster_handle: rados_t, osd_id: u64, simulate: bool) -> RadosResult<()> {
+pub fn auth_del(cluster_handle: &dyn CephBackend, osd_id: u64) -> RadosResult<()> {
     let cmd = json!({
         "prefix": "auth del",
         "entity": format!("osd.{}", osd_id)
     });
 
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    }
+    cluster_handle.mon_command(&cmd, &[])?;
     Ok(())
 }
 
-pub fn osd_rm(cluster_handle: rados_t, osd_id: u64, simulate: bool) -> RadosResult<()> {
+pub fn osd_rm(cluster_handle: &dyn CephBackend, osd_id: u64) -> RadosResult<()> {
     let cmd = json!({
         "prefix": "osd rm",
         "ids": [osd_id.to_string()]
     });
 
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    }
+    cluster_handle.mon_command(&cmd, &[])?;
     Ok(())
 
 }
 
-pub fn osd_create(cluster_handle: rados_t, id: Option<u64>, simulate: bool) -> RadosResult<u64> {
+pub fn osd_create(cluster_handle: &dyn CephBackend, id: Option<u64>) -> RadosResult<u64> {
     let cmd = match id {
         Some(osd_id) => {
             json!({
@@ -840,11 +2020,10 @@ pub fn osd_create(cluster_handle: rados_t, id: Option<u64>, simulate: bool) -> R
         },
     };
 
-    if simulate {
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    if cluster_handle.is_dry_run() {
         return Ok(0);
     }
-
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
     if let Some(return_data) = result.0 {
         let mut l = return_data.lines();
         match l.next() {
@@ -861,42 +2040,39 @@ pub fn osd_create(cluster_handle: rados_t, id: Option<u64>, simulate: bool) -> R
 }
 
 // Add a new mgr to the cluster
-pub fn mgr_auth_add(cluster_handle: rados_t, mgr_id: &str, simulate: bool) -> RadosResult<()> {
+#[cfg(feature = "luminous")]
+pub fn mgr_auth_add(cluster_handle: &dyn CephBackend, mgr_id: &str) -> RadosResult<()> {
     let cmd = json!({
         "prefix": "auth add",
         "entity": format!("mgr.{}", mgr_id),
         "caps": ["mon", "allow profile mgr", "osd", "allow *", "mds", "allow *"],
     });
 
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    }
+    cluster_handle.mon_command(&cmd, &[])?;
     Ok(())
 }
 
 // Add a new osd to the cluster
-pub fn osd_auth_add(cluster_handle: rados_t, osd_id: u64, simulate: bool) -> RadosResult<()> {
+pub fn osd_auth_add(cluster_handle: &dyn CephBackend, osd_id: u64) -> RadosResult<()> {
     let cmd = json!({
         "prefix": "auth add",
         "entity": format!("osd.{}", osd_id),
         "caps": ["mon", "allow rwx", "osd", "allow *"],
     });
 
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    }
+    cluster_handle.mon_command(&cmd, &[])?;
     Ok(())
 }
 
 /// Get a ceph-x key.  The id parameter can be either a number or a string
 /// depending on the type of client so I went with string.
-pub fn auth_get_key(cluster_handle: rados_t, client_type: &str, id: &str) -> RadosResult<String> {
+pub fn auth_get_key(cluster_handle: &dyn CephBackend, client_type: &str, id: &str) -> RadosResult<String> {
     let cmd = json!({
         "prefix": "auth get-key",
         "entity": format!("{}.{}", client_type, id),
     });
 
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
+    let result = cluster_handle.mon_command(&cmd, &[])?;
     if let Some(return_data) = result.0 {
         let mut l = return_data.lines();
         match l.next() {
@@ -912,105 +2088,865 @@ pub fn auth_get_key(cluster_handle: rados_t, client_type: &str, id: &str) -> Rad
     Err(RadosError::Error(format!("Unable to parse auth get-key output: {:?}", result)))
 }
 
-// ceph osd crush add {id-or-name} {weight}  [{bucket-type}={bucket-name} ...]
-/// add or update crushmap position and weight for an osd
-pub fn osd_crush_add(cluster_handle: rados_t, osd_id: u64, weight: f64, host: &str, simulate: bool) -> RadosResult<()> {
+/// List every cephx entity along with its key and caps
+pub fn auth_list(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<AuthEntity>> {
     let cmd = json!({
-        "prefix": "osd crush add",
-        "id": osd_id,
-        "weight": weight,
-        "args": [format!("host={}", host)]
+        "prefix": "auth list",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    let dump: AuthDump = json_output(result, &cmd)?;
+    Ok(dump.auth_dump)
+}
+
+/// Fetch the key for an entity, creating the entity with these caps first if
+/// it doesn't exist yet.  Ceph refuses if the entity exists with different
+/// caps.  An empty key is returned in a dry run.
+pub fn auth_get_or_create(cluster_handle: &dyn CephBackend, entity: &str, caps: &CapsBuilder)
+    -> RadosResult<String> {
+    let cmd = json!({
+        "prefix": "auth get-or-create",
+        "entity": entity,
+        "caps": caps.as_args(),
+        "format": "json"
     });
 
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    if cluster_handle.is_dry_run() {
+        return Ok(String::new());
+    }
+    let entities: Vec<AuthEntity> = json_output(result, &cmd)?;
+    match entities.into_iter().next() {
+        Some(e) => Ok(e.key),
+        None => Err(RadosError::Error("No key in the auth get-or-create output".into())),
     }
-    Ok(())
 }
 
-// Luminous mgr commands below
-
-/// dump the latest MgrMap
-pub fn mgr_dump(cluster_handle: rados_t) -> RadosResult<MgrDump> {
+/// Export an entity in keyring format, caps included, the same text that
+/// `ceph auth export` prints
+pub fn auth_export(cluster_handle: &dyn CephBackend, entity: &str) -> RadosResult<String> {
     let cmd = json!({
-        "prefix": "mgr dump",
+        "prefix": "auth export",
+        "entity": entity,
     });
 
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
-            None => {
-                return Err(RadosError::Error(format!(
-                "Unable to parse mgr dump: {:?}",
-                return_data,
-            )))
-            },
-        }
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    match result.0 {
+        Some(keyring) => Ok(keyring),
+        None => Err(RadosError::Error(format!("Unable to parse auth export output: {:?}", result))),
     }
-    Err(RadosError::Error(format!("Unable to parse mgr dump output: {:?}", result)))
 }
 
-/// Treat the named manager daemon as failed
-pub fn mgr_fail(cluster_handle: rados_t, mgr_id: &str, simulate: bool) -> RadosResult<()> {
+/// Import the entities in a keyring into the auth database.  Entities that
+/// already exist have their keys and caps replaced.
+pub fn auth_import(cluster_handle: &dyn CephBackend, keyring: &str) -> RadosResult<()> {
     let cmd = json!({
-        "prefix": "mgr fail",
-        "name": mgr_id,
+        "prefix": "auth import",
     });
 
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    }
+    cluster_handle.mon_command(&cmd, keyring.as_bytes())?;
     Ok(())
 }
 
-/// List active mgr modules
-pub fn mgr_list_modules(cluster_handle: rados_t) -> RadosResult<Vec<String>> {
+#[derive(Deserialize)]
+struct PrintKey {
+    key: String,
+}
+
+/// Get the ceph-x key of any entity.  Newer releases answer with a json
+/// object holding the key while older ones print the bare key.
+pub fn auth_print_key(cluster_handle: &dyn CephBackend, entity: &CephEntity) -> RadosResult<String> {
     let cmd = json!({
-        "prefix": "mgr module ls",
+        "prefix": "auth print-key",
+        "entity": entity.to_string(),
+        "format": "json"
     });
 
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
-            None => {
-                return Err(RadosError::Error(format!(
-                "Unable to parse mgr module ls: {:?}",
-                return_data,
-            )))
-            },
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    if let Some(ref return_data) = result.0 {
+        let trimmed = return_data.trim();
+        if trimmed.starts_with('{') {
+            let print_key: PrintKey = serde_json::from_str(trimmed)?;
+            return Ok(print_key.key);
+        }
+        if !trimmed.is_empty() {
+            return Ok(trimmed.to_string());
         }
     }
-    Err(RadosError::Error(format!("Unable to parse mgr ls output: {:?}", result)))
+    Err(RadosError::Error(format!("Unable to parse auth print-key output: {:?}", result)))
 }
 
-/// List service endpoints provided by mgr modules
-pub fn mgr_list_services(cluster_handle: rados_t) -> RadosResult<Vec<String>> {
+// ceph osd crush add {id-or-name} {weight}  [{bucket-type}={bucket-name} ...]
+/// add or update crushmap position and weight for an osd
+pub fn osd_crush_add(cluster_handle: &dyn CephBackend, osd_id: u64, weight: f64, host: &str) -> RadosResult<()> {
     let cmd = json!({
-        "prefix": "mgr services",
+        "prefix": "osd crush add",
+        "id": osd_id,
+        "weight": weight,
+        "args": [format!("host={}", host)]
     });
 
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Show which osds are blocking peering and how many pgs each one blocks
+pub fn osd_blocked_by(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<OsdBlockedBy>> {
+    let cmd = json!({
+        "prefix": "osd blocked-by",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Find the ip address, host and crush location of an osd
+pub fn osd_find(cluster_handle: &dyn CephBackend, osd_id: u64) -> RadosResult<OsdFind> {
+    let cmd = json!({
+        "prefix": "osd find",
+        "id": osd_id,
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Fetch the metadata for a single osd
+pub fn osd_metadata(cluster_handle: &dyn CephBackend, osd_id: u64) -> RadosResult<OsdMetadata> {
+    let cmd = json!({
+        "prefix": "osd metadata",
+        "id": osd_id,
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Fetch the metadata for every osd in the cluster
+pub fn osd_metadata_all(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<OsdMetadata>> {
+    let cmd = json!({
+        "prefix": "osd metadata",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the commit and apply latency of every osd
+pub fn osd_perf(cluster_handle: &dyn CephBackend) -> RadosResult<OsdPerf> {
+    let cmd = json!({
+        "prefix": "osd perf",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    let mut perf: serde_json::Value = json_output(result, &cmd)?;
+    // Nautilus and newer nest the perf infos under an osdstats key
+    if let Some(stats) = perf.as_object_mut().and_then(|o| o.remove("osdstats")) {
+        perf = stats;
+    }
+    Ok(serde_json::from_value(perf)?)
+}
+
+/// Mark an osd as destroyed.  The osd id is kept so a replacement osd can
+/// reuse it.  Ceph refuses to do this unless force is set.
+#[cfg(feature = "luminous")]
+pub fn osd_destroy(cluster_handle: &dyn CephBackend, osd_id: u64, force: bool) -> RadosResult<()> {
+    let cmd = match force {
+        true => {
+            json!({
+                "prefix": "osd destroy",
+                "id": osd_id,
+                "sure": "--yes-i-really-mean-it",
+            })
+        },
+        false => {
+            json!({
+                "prefix": "osd destroy",
+                "id": osd_id,
+            })
+        },
+    };
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Remove an osd from the crush map, delete its auth key and remove it from
+/// the osd map all in one step.  Ceph refuses to do this unless force is set.
+#[cfg(feature = "luminous")]
+pub fn osd_purge(cluster_handle: &dyn CephBackend, osd_id: u64, force: bool) -> RadosResult<()> {
+    let cmd = match force {
+        true => {
+            json!({
+                "prefix": "osd purge",
+                "id": osd_id,
+                "sure": "--yes-i-really-mean-it",
+            })
+        },
+        false => {
+            json!({
+                "prefix": "osd purge",
+                "id": osd_id,
+            })
+        },
+    };
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Allocate a new osd id for the osd with this uuid and register its cephx
+/// secrets in one atomic step.  This is what ceph-volume uses when it
+/// provisions an osd.  The secrets are the json blob that `ceph osd new` reads
+/// with `-i`, ie: `{"cephx_secret": "AQ..."}`.  Returns the osd id.
+#[cfg(feature = "luminous")]
+pub fn osd_new(cluster_handle: &dyn CephBackend, uuid: &Uuid, id: Option<u64>, secrets: Option<&serde_json::Value>)
+    -> RadosResult<u64> {
+    let cmd = match id {
+        Some(osd_id) => {
+            json!({
+                "prefix": "osd new",
+                "uuid": uuid.hyphenated().to_string(),
+                "id": osd_id,
+            })
+        },
+        None => {
+            json!({
+                "prefix": "osd new",
+                "uuid": uuid.hyphenated().to_string(),
+            })
+        },
+    };
+
+    let input = match secrets {
+        Some(secrets) => secrets.to_string().into_bytes(),
+        None => Vec::new(),
+    };
+    let result = cluster_handle.mon_command(&cmd, &input)?;
+    if cluster_handle.is_dry_run() {
+        return Ok(id.unwrap_or(0));
+    }
+    if let Some(return_data) = result.0 {
+        let mut l = return_data.lines();
+        match l.next() {
+            Some(num) => return Ok(u64::from_str(num.trim())?),
+            None => {
+                return Err(RadosError::Error(format!(
+                "Unable to parse osd new output: {:?}",
+                return_data,
+            )))
+            },
+        }
+    }
+    Err(RadosError::Error(format!("Unable to parse osd new output: {:?}", result)))
+}
+
+/// Mark an osd as permanently lost.  Any data that only existed on this osd
+/// is gone for good, so this is only for disaster recovery.  Nothing is sent
+/// unless confirm is set.
+pub fn osd_lost(cluster_handle: &dyn CephBackend, osd_id: u64, confirm: bool) -> RadosResult<()> {
+    if !confirm {
+        return Err(RadosError::new(format!("Refusing to mark osd.{} lost without confirmation", osd_id)));
+    }
+    let cmd = json!({
+        "prefix": "osd lost",
+        "id": osd_id,
+        "sure": "--yes-i-really-mean-it",
+    });
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Get a summary of how many osds are up and in
+pub fn osd_stat(cluster_handle: &dyn CephBackend) -> RadosResult<OsdStat> {
+    let cmd = json!({
+        "prefix": "osd stat",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    let mut stat: serde_json::Value = json_output(result, &cmd)?;
+    // Jewel and older nest the counts under an osdmap key
+    if let Some(osdmap) = stat.as_object_mut().and_then(|o| o.remove("osdmap")) {
+        stat = osdmap;
+    }
+    Ok(serde_json::from_value(stat)?)
+}
+
+/// Dump the latest osd map
+pub fn osd_dump(cluster_handle: &dyn CephBackend) -> RadosResult<OsdMap> {
+    let cmd = json!({
+        "prefix": "osd dump",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the binary encoded osd map.  This is the same data that
+/// `ceph osd getmap -o <file>` writes out for osdmaptool.  When no epoch is
+/// given the latest osd map is returned.
+pub fn osd_getmap(cluster_handle: &dyn CephBackend, epoch: Option<u64>) -> RadosResult<Vec<u8>> {
+    let cmd = match epoch {
+        Some(epoch) => {
+            json!({
+                "prefix": "osd getmap",
+                "epoch": epoch,
+            })
+        },
+        None => {
+            json!({
+                "prefix": "osd getmap",
+            })
+        },
+    };
+    let result = cluster_handle.mon_command_bytes(None, &cmd, &[])?;
+    match result.0 {
+        Some(map) => Ok(map),
+        None => Err(RadosError::Error(result.1.unwrap_or(
+            "No response from ceph for osd getmap".into(),
+        ))),
+    }
+}
+
+/// Get the binary encoded CRUSH map, the same data
+/// `ceph osd getcrushmap -o <file>` writes out for crushtool
+pub fn osd_getcrushmap(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<u8>> {
+    let cmd = json!({
+        "prefix": "osd getcrushmap",
+    });
+    let result = cluster_handle.mon_command_bytes(None, &cmd, &[])?;
+    match result.0 {
+        Some(map) => Ok(map),
+        None => Err(RadosError::Error(result.1.unwrap_or(
+            "No response from ceph for osd getcrushmap".into(),
+        ))),
+    }
+}
+
+/// Read the current cluster wide flags out of the osd map
+fn osd_map_flags(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<String>> {
+    let osd_map = osd_dump(cluster_handle)?;
+    Ok(
+        osd_map
+            .flags
+            .split(',')
+            .filter(|f| !f.is_empty())
+            .map(|f| f.to_string())
+            .collect(),
+    )
+}
+
+/// Pause all client io to the cluster.  This sets the pause flag and then
+/// reads back the osd map to make sure both pauserd and pausewr took effect.
+pub fn cluster_pause(cluster_handle: &dyn CephBackend) -> RadosResult<()> {
+    osd_set(cluster_handle, &OsdOption::Pause, false)?;
+    if cluster_handle.is_dry_run() {
+        return Ok(());
+    }
+    let flags = osd_map_flags(cluster_handle)?;
+    if !flags.iter().any(|f| f == "pauserd") || !flags.iter().any(|f| f == "pausewr") {
+        return Err(RadosError::Error(format!("Cluster did not pause.  Current osd map flags: {:?}", flags)));
+    }
+    Ok(())
+}
+
+/// Resume client io to the cluster.  This unsets the pause flag and then
+/// reads back the osd map to make sure pauserd and pausewr are cleared.
+pub fn cluster_unpause(cluster_handle: &dyn CephBackend) -> RadosResult<()> {
+    osd_unset(cluster_handle, &OsdOption::Pause)?;
+    if cluster_handle.is_dry_run() {
+        return Ok(());
+    }
+    let flags = osd_map_flags(cluster_handle)?;
+    if flags.iter().any(|f| f == "pauserd" || f == "pausewr") {
+        return Err(RadosError::Error(format!("Cluster did not unpause.  Current osd map flags: {:?}", flags)));
+    }
+    Ok(())
+}
+
+/// Reweight osds that are more than oload percent above the average
+/// utilization.  oload defaults to 120, max_change to 0.05 and max_osds to 4
+/// on the ceph side.  When dry_run is set the test-reweight-by-utilization
+/// variant is used which only reports the reweights it would make.
+pub fn osd_reweight_by_utilization(cluster_handle: &dyn CephBackend, oload: Option<u64>, max_change: Option<f64>,
+                                   max_osds: Option<u64>, dry_run: bool)
+    -> RadosResult<ReweightByUtilization> {
+    let mut cmd = match dry_run {
+        true => {
+            json!({
+                "prefix": "osd test-reweight-by-utilization",
+                "format": "json"
+            })
+        },
+        false => {
+            json!({
+                "prefix": "osd reweight-by-utilization",
+                "format": "json"
+            })
+        },
+    };
+    if let Some(oload) = oload {
+        cmd["oload"] = json!(oload);
+    }
+    if let Some(max_change) = max_change {
+        cmd["max_change"] = json!(max_change);
+    }
+    if let Some(max_osds) = max_osds {
+        cmd["max_osds"] = json!(max_osds);
+    }
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Set the oldest client release that is allowed to connect to the cluster.
+/// This needs to be at least luminous before pg-upmap can be used.  Ceph
+/// refuses to do this while older clients are connected unless confirm is set.
+#[cfg(feature = "luminous")]
+pub fn osd_set_require_min_compat_client(cluster_handle: &dyn CephBackend, release: CephRelease, confirm: bool)
+    -> RadosResult<()> {
+    let cmd = match confirm {
+        true => {
+            json!({
+                "prefix": "osd set-require-min-compat-client",
+                "version": release,
+                "sure": "--yes-i-really-mean-it",
+            })
+        },
+        false => {
+            json!({
+                "prefix": "osd set-require-min-compat-client",
+                "version": release,
+            })
+        },
+    };
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Require every osd to be running at least this release.  This is the final
+/// step of an upgrade and only luminous or newer releases are accepted.  Ceph
+/// refuses to do this while some up osds lack the release's features unless
+/// confirm is set.
+#[cfg(feature = "luminous")]
+pub fn osd_require_osd_release(cluster_handle: &dyn CephBackend, release: CephRelease, confirm: bool)
+    -> RadosResult<()> {
+    if release < CephRelease::Luminous {
+        return Err(RadosError::Error(format!(
+            "osd require-osd-release only accepts luminous or newer, not {}",
+            release
+        )));
+    }
+    let cmd = match confirm {
+        true => {
+            json!({
+                "prefix": "osd require-osd-release",
+                "release": release,
+                "sure": "--yes-i-really-mean-it",
+            })
+        },
+        false => {
+            json!({
+                "prefix": "osd require-osd-release",
+                "release": release,
+            })
+        },
+    };
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Get the average, min and max number of pgs per osd along with the standard
+/// deviation.  Useful for seeing how well balanced the cluster is.
+pub fn osd_utilization(cluster_handle: &dyn CephBackend) -> RadosResult<OsdUtilization> {
+    let cmd = json!({
+        "prefix": "osd utilization",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the maximum number of osds the osd map currently has room for
+pub fn osd_getmaxosd(cluster_handle: &dyn CephBackend) -> RadosResult<MaxOsd> {
+    let cmd = json!({
+        "prefix": "osd getmaxosd",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Set the maximum number of osds the osd map has room for.  Shrinking this
+/// is how the osd map gets cleaned up after a lot of osds were removed.
+pub fn osd_setmaxosd(cluster_handle: &dyn CephBackend, max_osd: u64) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "osd setmaxosd",
+        "newmax": max_osd,
+    });
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Find the pg and the up and acting osd sets that an object in a pool maps
+/// to.  The object doesn't need to exist.
+pub fn osd_map(cluster_handle: &dyn CephBackend, pool: &str, object: &str) -> RadosResult<ObjectPlacement> {
+    let cmd = json!({
+        "prefix": "osd map",
+        "pool": pool,
+        "object": object,
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+#[derive(Deserialize)]
+struct LsPool {
+    poolnum: i64,
+    poolname: String,
+}
+
+/// List the pool ids and names through the monitor.  Unlike rados_pools this
+/// only needs mon caps.
+pub fn osd_lspools(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<(i64, String)>> {
+    let cmd = json!({
+        "prefix": "osd lspools",
+        "format": "json"
+    });
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    let pools: Vec<LsPool> = json_output(result, &cmd)?;
+    Ok(pools.into_iter().map(|p| (p.poolnum, p.poolname)).collect())
+}
+
+/// check running versions of ceph-osd daemons
+#[cfg(feature = "luminous")]
+pub fn osd_versions(cluster_handle: &dyn CephBackend) -> RadosResult<HashMap<String, u64>> {
+    let cmd = json!({
+        "prefix": "osd versions",
+        "format": "json",
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// count ceph-osd daemons by metadata field property
+#[cfg(feature = "luminous")]
+pub fn osd_count_metadata(cluster_handle: &dyn CephBackend, property: &str) -> RadosResult<HashMap<String, u64>> {
+    let cmd = json!({
+        "prefix": "osd count-metadata",
+        "format": "json",
+        "property": property,
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Explicitly remap a pg away from osds.  Each mapping is a (from, to) pair
+/// of osd ids.  The cluster needs require-min-compat-client luminous first.
+#[cfg(feature = "luminous")]
+pub fn osd_pg_upmap_items(cluster_handle: &dyn CephBackend, pgid: &str, mappings: &[(u64, u64)])
+    -> RadosResult<()> {
+    let ids: Vec<u64> = mappings
+        .iter()
+        .flat_map(|&(from, to)| vec![from, to])
+        .collect();
+    let cmd = json!({
+        "prefix": "osd pg-upmap-items",
+        "pgid": pgid,
+        "id": ids,
+    });
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Remove the explicit upmap overrides for a pg
+#[cfg(feature = "luminous")]
+pub fn osd_rm_pg_upmap_items(cluster_handle: &dyn CephBackend, pgid: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "osd rm-pg-upmap-items",
+        "pgid": pgid,
+    });
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Get a value out of the mon config-key store
+pub fn config_key_get(cluster_handle: &dyn CephBackend, key: &str) -> RadosResult<String> {
+    let cmd = json!({
+        "prefix": "config-key get",
+        "key": key,
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    match result.0 {
+        Some(value) => Ok(value),
+        None => Err(RadosError::Error(format!("Unable to parse config-key get output: {:?}", result))),
+    }
+}
+
+/// Store a value in the mon config-key store, replacing any existing value
+pub fn config_key_set(cluster_handle: &dyn CephBackend, key: &str, value: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "config-key set",
+        "key": key,
+        "val": value,
+    });
+
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Remove a key from the mon config-key store
+#[cfg(feature = "luminous")]
+pub fn config_key_rm(cluster_handle: &dyn CephBackend, key: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "config-key rm",
+        "key": key,
+    });
+
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// List the keys in the mon config-key store
+#[cfg(feature = "luminous")]
+pub fn config_key_ls(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<String>> {
+    let cmd = json!({
+        "prefix": "config-key ls",
+        "format": "json"
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Dump every key and value in the mon config-key store
+pub fn config_key_dump(cluster_handle: &dyn CephBackend) -> RadosResult<HashMap<String, String>> {
+    let cmd = json!({
+        "prefix": "config-key dump",
+        "format": "json"
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Dump every option that is set in the central config database
+#[cfg(feature = "mimic")]
+pub fn config_dump(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<ConfigEntry>> {
+    let cmd = json!({
+        "prefix": "config dump",
+        "format": "json"
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Load the options from an ini style ceph.conf into the central config
+/// database.  Ceph hands back a conf with whatever options it could not
+/// assimilate, such as `mon_host`, which still need to stay in the local
+/// ceph.conf.  An empty string is returned in a dry run.
+#[cfg(feature = "mimic")]
+pub fn config_assimilate_conf(cluster_handle: &dyn CephBackend, conf: &str) -> RadosResult<String> {
+    let cmd = json!({
+        "prefix": "config assimilate-conf",
+    });
+
+    let result = cluster_handle.mon_command(&cmd, conf.as_bytes())?;
+    if cluster_handle.is_dry_run() {
+        return Ok(String::new());
+    }
+    Ok(result.0.unwrap_or_default())
+}
+
+/// Change config options on running daemons without restarting them, for
+/// example `&["--osd_max_backfills=4"]`.  The change is lost when the daemon
+/// restarts.
+pub fn tell_injectargs(cluster_handle: &dyn CephBackend, target: &DaemonTarget, args: &[&str])
+    -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "injectargs",
+        "injected_args": args,
+    });
+
+    match target {
+        &DaemonTarget::Osd(id) => {
+            cluster_handle.osd_command_bytes(id as i32, &cmd, &[])?;
+        },
+        &DaemonTarget::AllOsds => {
+            for osd in osd_dump(cluster_handle)?.osds.iter().filter(|o| o.up == 1) {
+                cluster_handle.osd_command_bytes(osd.osd as i32, &cmd, &[])?;
+            }
+        },
+        &DaemonTarget::Mon(ref name) => {
+            cluster_handle.mon_command_target(name, &cmd)?;
+        },
+        &DaemonTarget::AllMons => {
+            for mon in mon_dump(cluster_handle)?.mons {
+                cluster_handle.mon_command_target(&mon.name, &cmd)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Have an osd write `bytes` worth of data to its object store in
+/// `block_size` chunks and report the throughput.  The osd caps how much
+/// data a single bench may write with osd_bench_max_block_size and friends.
+pub fn osd_bench(cluster_handle: &dyn CephBackend, osd_id: u64, bytes: u64, block_size: u64) -> RadosResult<OsdBench> {
+    let cmd = json!({
+        "prefix": "bench",
+        "count": bytes,
+        "size": block_size,
+        "format": "json"
+    });
+
+    let result = cluster_handle.osd_command(osd_id as i32, &cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the last n lines of the cluster log at or above the given level
+#[cfg(feature = "luminous")]
+pub fn log_last(cluster_handle: &dyn CephBackend, n: u64, level: LogLevel, channel: LogChannel) -> RadosResult<Vec<LogEntry>> {
+    let cmd = json!({
+        "prefix": "log last",
+        "num": n,
+        "level": level.as_ref(),
+        "channel": channel.as_ref(),
+        "format": "json"
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the full `ceph report` as json.  The report is pretty printed over
+/// many lines and can be several megabytes on a big cluster.
+pub fn report_json(cluster_handle: &dyn CephBackend) -> RadosResult<serde_json::Value> {
+    let cmd = json!({
+        "prefix": "report",
+        "format": "json"
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get `ceph report` with the health, osdmap and pgmap sections pulled out
+pub fn report(cluster_handle: &dyn CephBackend) -> RadosResult<CephReport> {
+    Ok(serde_json::from_value(report_json(cluster_handle)?)?)
+}
+
+/// Set an option in the central config database.  `who` is a single daemon
+/// such as `osd.3`, a daemon type such as `osd`, or `global`.
+#[cfg(feature = "mimic")]
+pub fn config_set(cluster_handle: &dyn CephBackend, who: &str, name: &str, value: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "config set",
+        "who": who,
+        "name": name,
+        "value": value,
+    });
+
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Get the value of an option from the central config database as it applies
+/// to `who`
+#[cfg(feature = "mimic")]
+pub fn config_get(cluster_handle: &dyn CephBackend, who: &str, name: &str) -> RadosResult<String> {
+    let cmd = json!({
+        "prefix": "config get",
+        "who": who,
+        "key": name,
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    if let Some(return_data) = result.0 {
+        let mut l = return_data.lines();
         match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
+            Some(value) => return Ok(value.into()),
             None => {
                 return Err(RadosError::Error(format!(
-                "Unable to parse mgr services: {:?}",
+                "Unable to parse config get output: {:?}",
                 return_data,
             )))
             },
         }
     }
-    Err(RadosError::Error(format!("Unable to parse mgr services output: {:?}", result)))
+    Err(RadosError::Error("No response from ceph for config get".into()))
+}
+
+/// Set an option of a mgr module, for example the port of the prometheus
+/// module.  Module options live under `mgr/<module>/<key>` in the config
+/// database.
+#[cfg(feature = "mimic")]
+pub fn mgr_config_set(cluster_handle: &dyn CephBackend, module: &str, key: &str, value: &str)
+    -> RadosResult<()> {
+    config_set(cluster_handle, "mgr", &format!("mgr/{}/{}", module, key), value)
+}
+
+/// Get an option of a mgr module
+#[cfg(feature = "mimic")]
+pub fn mgr_config_get(cluster_handle: &dyn CephBackend, module: &str, key: &str) -> RadosResult<String> {
+    config_get(cluster_handle, "mgr", &format!("mgr/{}/{}", module, key))
+}
+
+// Luminous mgr commands below
+
+/// dump the latest MgrMap
+#[cfg(feature = "luminous")]
+pub fn mgr_dump(cluster_handle: &dyn CephBackend) -> RadosResult<MgrDump> {
+    let cmd = json!({
+        "prefix": "mgr dump",
+        "format": "json",
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Treat the named manager daemon as failed
+#[cfg(feature = "luminous")]
+pub fn mgr_fail(cluster_handle: &dyn CephBackend, mgr_id: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "mgr fail",
+        "name": mgr_id,
+    });
+
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// List active mgr modules
+#[cfg(feature = "luminous")]
+pub fn mgr_list_modules(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<String>> {
+    let cmd = json!({
+        "prefix": "mgr module ls",
+        "format": "json",
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// List service endpoints provided by mgr modules
+#[cfg(feature = "luminous")]
+pub fn mgr_list_services(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<String>> {
+    let cmd = json!({
+        "prefix": "mgr services",
+        "format": "json",
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
 }
 
 /// Enable a mgr module
-pub fn mgr_enable_module(cluster_handle: rados_t, module: &str, force: bool, simulate: bool) -> RadosResult<()> {
+#[cfg(feature = "luminous")]
+pub fn mgr_enable_module(cluster_handle: &dyn CephBackend, module: &str, force: bool) -> RadosResult<()> {
     let cmd = match force {
         true => {
             json!({
@@ -1027,88 +2963,413 @@ pub fn mgr_enable_module(cluster_handle: rados_t, module: &str, force: bool, sim
         },
     };
 
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    }
+    cluster_handle.mon_command(&cmd, &[])?;
     Ok(())
 }
 
 /// Disable a mgr module
-pub fn mgr_disable_module(cluster_handle: rados_t, module: &str, simulate: bool) -> RadosResult<()> {
+#[cfg(feature = "luminous")]
+pub fn mgr_disable_module(cluster_handle: &dyn CephBackend, module: &str) -> RadosResult<()> {
     let cmd = json!({
         "prefix": "mgr module disable",
         "module": module,
     });
 
-    if !simulate {
-        ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    }
+    cluster_handle.mon_command(&cmd, &[])?;
     Ok(())
 }
 
 /// dump metadata for all daemons
-pub fn mgr_metadata(cluster_handle: rados_t) -> RadosResult<MgrMetadata> {
+#[cfg(feature = "luminous")]
+pub fn mgr_metadata(cluster_handle: &dyn CephBackend) -> RadosResult<MgrMetadata> {
     let cmd = json!({
         "prefix": "mgr metadata",
+        "format": "json",
     });
 
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
-            None => {
-                return Err(RadosError::Error(format!(
-                "Unable to parse mgr metadata: {:?}",
-                return_data,
-            )))
-            },
-        }
-    }
-    Err(RadosError::Error(format!("Unable to parse mgr metadata output: {:?}", result)))
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
 }
 
 /// count ceph-mgr daemons by metadata field property
-pub fn mgr_count_metadata(cluster_handle: rados_t, property: &str) -> RadosResult<HashMap<String, u64>> {
+#[cfg(feature = "luminous")]
+pub fn mgr_count_metadata(cluster_handle: &dyn CephBackend, property: &str) -> RadosResult<HashMap<String, u64>> {
     let cmd = json!({
         "prefix": "mgr count-metadata",
+        "format": "json",
         "name": property,
     });
 
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
-    if let Some(return_data) = result.0 {
-        let mut l = return_data.lines();
-        match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
-            None => {
-                return Err(RadosError::Error(format!(
-                "Unable to parse mgr count-metadata: {:?}",
-                return_data,
-            )))
-            },
-        }
-    }
-    Err(RadosError::Error(format!("Unable to parse mgr count-metadata output: {:?}", result)))
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
 }
 
 /// check running versions of ceph-mgr daemons
-pub fn mgr_versions(cluster_handle: rados_t) -> RadosResult<HashMap<String, u64>> {
+#[cfg(feature = "luminous")]
+pub fn mgr_versions(cluster_handle: &dyn CephBackend) -> RadosResult<HashMap<String, u64>> {
     let cmd = json!({
         "prefix": "mgr versions",
+        "format": "json",
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Dump the servicemap of registered service daemons
+#[cfg(feature = "luminous")]
+pub fn service_dump(cluster_handle: &dyn CephBackend) -> RadosResult<ServiceMap> {
+    let cmd = json!({
+        "prefix": "service dump",
+        "format": "json"
+    });
+
+    let result = cluster_handle.mgr_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the last reported status of each registered service daemon, keyed
+/// by service and then daemon name
+#[cfg(feature = "luminous")]
+pub fn service_status(cluster_handle: &dyn CephBackend)
+    -> RadosResult<HashMap<String, HashMap<String, ServiceDaemonStatus>>> {
+    let cmd = json!({
+        "prefix": "service status",
+        "format": "json"
+    });
+
+    let result = cluster_handle.mgr_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the events of the mgr progress module as json
+#[cfg(feature = "nautilus")]
+pub fn progress_json(cluster_handle: &dyn CephBackend) -> RadosResult<serde_json::Value> {
+    let cmd = json!({
+        "prefix": "progress json",
+        "format": "json",
+    });
+
+    let result = cluster_handle.mgr_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the ongoing and completed events of the mgr progress module
+#[cfg(feature = "nautilus")]
+pub fn progress(cluster_handle: &dyn CephBackend) -> RadosResult<Progress> {
+    Ok(serde_json::from_value(progress_json(cluster_handle)?)?)
+}
+
+/// List the devices that the devicehealth module is tracking
+#[cfg(feature = "nautilus")]
+pub fn device_ls(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<Device>> {
+    let cmd = json!({
+        "prefix": "device ls",
+        "format": "json"
+    });
+
+    let result = cluster_handle.mgr_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// List the devices attached to one host
+#[cfg(feature = "nautilus")]
+pub fn device_ls_by_host(cluster_handle: &dyn CephBackend, host: &str) -> RadosResult<Vec<Device>> {
+    let cmd = json!({
+        "prefix": "device ls-by-host",
+        "host": host,
+        "format": "json"
+    });
+
+    let result = cluster_handle.mgr_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the scraped SMART data for a device keyed by the time it was scraped
+#[cfg(feature = "nautilus")]
+pub fn device_health_metrics(cluster_handle: &dyn CephBackend, devid: &str) -> RadosResult<HashMap<String, SmartSummary>> {
+    let cmd = json!({
+        "prefix": "device get-health-metrics",
+        "devid": devid,
+        "format": "json"
+    });
+
+    let result = cluster_handle.mgr_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+// CephFS commands below
+
+/// List the CephFS filesystems and the pools they use
+pub fn fs_ls(cluster_handle: &dyn CephBackend) -> RadosResult<Vec<CephFs>> {
+    let cmd = json!({
+        "prefix": "fs ls",
+        "format": "json"
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Create a new CephFS filesystem on top of existing metadata and data pools
+pub fn fs_new(cluster_handle: &dyn CephBackend, name: &str, metadata_pool: &str, data_pool: &str)
+    -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "fs new",
+        "fs_name": name,
+        "metadata": metadata_pool,
+        "data": data_pool,
+    });
+
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Remove a CephFS filesystem.  The pools are left alone.  Ceph only allows
+/// this once every mds of the filesystem has been failed and wants
+/// confirmation before it will do it.
+pub fn fs_rm(cluster_handle: &dyn CephBackend, name: &str, confirm: bool) -> RadosResult<()> {
+    let cmd = match confirm {
+        true => {
+            json!({
+                "prefix": "fs rm",
+                "fs_name": name,
+                "sure": "--yes-i-really-mean-it",
+            })
+        },
+        false => {
+            json!({
+                "prefix": "fs rm",
+                "fs_name": name,
+            })
+        },
+    };
+
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Get the mds ranks, client counts and pool usage of the filesystems, or of
+/// just one filesystem when a name is given
+#[cfg(feature = "luminous")]
+pub fn fs_status(cluster_handle: &dyn CephBackend, fs: Option<&str>) -> RadosResult<FsStatus> {
+    let mut cmd = json!({
+        "prefix": "fs status",
+        "format": "json"
+    });
+    if let Some(fs) = fs {
+        cmd["fs"] = json!(fs);
+    }
+
+    let result = cluster_handle.mgr_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Get the fsmap with the state of every mds
+pub fn mds_stat(cluster_handle: &dyn CephBackend) -> RadosResult<MdsStat> {
+    let cmd = json!({
+        "prefix": "mds stat",
+        "format": "json"
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Fetch the metadata for a single mds
+pub fn mds_metadata(cluster_handle: &dyn CephBackend, name: &str) -> RadosResult<MdsMetadata> {
+    let cmd = json!({
+        "prefix": "mds metadata",
+        "who": name,
+        "format": "json"
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    json_output(result, &cmd)
+}
+
+/// Mark an mds as failed so a standby takes over its rank.  The mds can be
+/// given by name, gid or rank.
+pub fn mds_fail(cluster_handle: &dyn CephBackend, name_or_gid: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "mds fail",
+        "role_or_gid": name_or_gid,
+    });
+
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Clear the damaged flag of a rank once its metadata has been repaired so
+/// an mds can take it again.  The rank is either a number or `<fs>:<rank>`
+/// when there is more than one filesystem.
+pub fn mds_repaired(cluster_handle: &dyn CephBackend, rank: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "mds repaired",
+        "role": rank,
+    });
+
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Set an option on a filesystem.  Setting `down` to true fails every mds of
+/// the filesystem in an orderly way, which is needed before upgrades or
+/// `fs rm`.
+pub fn fs_set(cluster_handle: &dyn CephBackend, fs: &str, key: &FsOption, value: &str) -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "fs set",
+        "fs_name": fs,
+        "var": key,
+        "val": value,
+    });
+
+    cluster_handle.mon_command(&cmd, &[])?;
+    Ok(())
+}
+
+#[cfg(feature = "nautilus")]
+#[derive(Deserialize)]
+struct VolumeEntry {
+    name: String,
+}
+
+/// Create a subvolume in a CephFS volume, optionally inside a subvolume
+/// group and with a quota in bytes
+#[cfg(feature = "nautilus")]
+pub fn fs_subvolume_create(cluster_handle: &dyn CephBackend, vol_name: &str, sub_name: &str, group: Option<&str>,
+                           size: Option<u64>) -> RadosResult<()> {
+    let mut cmd = json!({
+        "prefix": "fs subvolume create",
+        "vol_name": vol_name,
+        "sub_name": sub_name,
+    });
+    if let Some(group) = group {
+        cmd["group_name"] = json!(group);
+    }
+    if let Some(size) = size {
+        cmd["size"] = json!(size);
+    }
+
+    cluster_handle.mgr_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// List the subvolumes in a volume or in one of its subvolume groups
+#[cfg(feature = "nautilus")]
+pub fn fs_subvolume_ls(cluster_handle: &dyn CephBackend, vol_name: &str, group: Option<&str>) -> RadosResult<Vec<String>> {
+    let mut cmd = json!({
+        "prefix": "fs subvolume ls",
+        "vol_name": vol_name,
+        "format": "json"
+    });
+    if let Some(group) = group {
+        cmd["group_name"] = json!(group);
+    }
+
+    let result = cluster_handle.mgr_command(&cmd, &[])?;
+    let subvolumes: Vec<VolumeEntry> = json_output(result, &cmd)?;
+    Ok(subvolumes.into_iter().map(|s| s.name).collect())
+}
+
+/// Remove a subvolume and its data.  With force set a subvolume that doesn't
+/// exist is not an error.
+#[cfg(feature = "nautilus")]
+pub fn fs_subvolume_rm(cluster_handle: &dyn CephBackend, vol_name: &str, sub_name: &str, group: Option<&str>, force: bool)
+    -> RadosResult<()> {
+    let mut cmd = json!({
+        "prefix": "fs subvolume rm",
+        "vol_name": vol_name,
+        "sub_name": sub_name,
+        "force": force,
+    });
+    if let Some(group) = group {
+        cmd["group_name"] = json!(group);
+    }
+
+    cluster_handle.mgr_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// Get the path of a subvolume inside the filesystem, which is what clients
+/// mount
+#[cfg(feature = "nautilus")]
+pub fn fs_subvolume_getpath(cluster_handle: &dyn CephBackend, vol_name: &str, sub_name: &str, group: Option<&str>)
+    -> RadosResult<String> {
+    let mut cmd = json!({
+        "prefix": "fs subvolume getpath",
+        "vol_name": vol_name,
+        "sub_name": sub_name,
     });
+    if let Some(group) = group {
+        cmd["group_name"] = json!(group);
+    }
 
-    let result = ceph_mon_command_without_data(cluster_handle, &cmd)?;
+    let result = cluster_handle.mgr_command(&cmd, &[])?;
     if let Some(return_data) = result.0 {
         let mut l = return_data.lines();
         match l.next() {
-            Some(res) => return Ok(serde_json::from_str(res)?),
+            Some(path) => return Ok(path.into()),
             None => {
                 return Err(RadosError::Error(format!(
-                "Unable to parse mgr versions: {:?}",
+                "Unable to parse fs subvolume getpath output: {:?}",
                 return_data,
             )))
             },
         }
     }
-    Err(RadosError::Error(format!("Unable to parse mgr versions output: {:?}", result)))
+    Err(RadosError::Error("No response from ceph for fs subvolume getpath".into()))
+}
+
+/// Create a subvolume group in a CephFS volume
+#[cfg(feature = "nautilus")]
+pub fn fs_subvolumegroup_create(cluster_handle: &dyn CephBackend, vol_name: &str, group: &str)
+    -> RadosResult<()> {
+    let cmd = json!({
+        "prefix": "fs subvolumegroup create",
+        "vol_name": vol_name,
+        "group_name": group,
+    });
+
+    cluster_handle.mgr_command(&cmd, &[])?;
+    Ok(())
+}
+
+/// List the subvolume groups in a CephFS volume
+#[cfg(feature = "nautilus")]
+pub fn fs_subvolumegroup_ls(cluster_handle: &dyn CephBackend, vol_name: &str) -> RadosResult<Vec<String>> {
+    let cmd = json!({
+        "prefix": "fs subvolumegroup ls",
+        "vol_name": vol_name,
+        "format": "json"
+    });
+
+    let result = cluster_handle.mgr_command(&cmd, &[])?;
+    let groups: Vec<VolumeEntry> = json_output(result, &cmd)?;
+    Ok(groups.into_iter().map(|g| g.name).collect())
+}
+
+/// Create a client key for a filesystem limited to a path, with access such
+/// as `r` or `rw`, and return the key.  An empty key is returned in a
+/// dry run.
+#[cfg(feature = "luminous")]
+pub fn fs_authorize(cluster_handle: &dyn CephBackend, fs: &str, client_id: &str, path: &str, access: &str)
+    -> RadosResult<String> {
+    let cmd = json!({
+        "prefix": "fs authorize",
+        "filesystem": fs,
+        "entity": format!("client.{}", client_id),
+        "caps": [path, access],
+        "format": "json"
+    });
+
+    let result = cluster_handle.mon_command(&cmd, &[])?;
+    if cluster_handle.is_dry_run() {
+        return Ok(String::new());
+    }
+    let entities: Vec<AuthEntity> = json_output(result, &cmd)?;
+    match entities.into_iter().next() {
+        Some(e) => Ok(e.key),
+        None => Err(RadosError::Error("No key in the fs authorize output".into())),
+    }
 }
@@ -0,0 +1,394 @@
+//! Reading ceph.conf without going through librados, for tools that want
+//! the mon hosts or fsid before connecting or without a cluster at all.
+//! Values are looked up the way the daemons do: in the section for the
+//! entity, then its type, then `[global]`, with `$cluster`, `$type`, `$id`,
+//! `$name`, `$host` and references to other options expanded.
+//!
+//! ```rust,no_run
+//! # use ceph::conf::CephConf;
+//! # use ceph::error::RadosError;
+//! # fn main() {
+//! #   let _ = run();
+//! # }
+//! # fn run() -> Result<(), RadosError> {
+//! let conf = CephConf::load("/etc/ceph/ceph.conf")?;
+//! println!("fsid {:?} mons {:?}", conf.fsid()?, conf.mon_hosts());
+//! println!("keyrings {:?}", conf.keyring_paths("client.admin"));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use libc::{c_char, gethostname};
+use uuid::Uuid;
+
+use error::{RadosError, RadosResult};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONF: &str = r#"
+# deployed by hand
+[global]
+fsid = 9d5b3c1e-4f0d-4c0e-9a6f-2a9c8e0b3f11
+mon host = [v2:10.0.0.1:3300,v1:10.0.0.1:6789], 10.0.0.2 10.0.0.3  # three mons
+public_network = 10.0.0.0/24
+log file = /var/log/ceph/$cluster-$name.log
+auth cluster required = cephx
+
+[client]
+admin socket = /var/run/ceph/$cluster-$type.$id.asok
+rbd cache = true
+
+[client.rgw.gw1]
+rgw frontends = "beast port=8080" ; quoted
+log_file = $run_dir/$name.log
+run dir = /srv/\
+run
+"#;
+
+    #[test]
+    fn it_looks_up_values_by_entity() {
+        let conf: CephConf = CONF.parse().unwrap();
+        assert_eq!(conf.sections(), vec!["client", "client.rgw.gw1", "global"]);
+        assert_eq!(conf.get_raw("global", "auth-cluster-required"), Some("cephx"));
+        assert_eq!(conf.get("client.admin", "rbd_cache"), Some("true".to_string()));
+        assert_eq!(conf.get_parsed::<bool>("client.admin", "rbd cache").unwrap(), Some(true));
+        assert_eq!(conf.get("osd.0", "rbd_cache"), None);
+        assert_eq!(conf.get("client.rgw.gw1", "rgw_frontends"), Some("beast port=8080".to_string()));
+    }
+
+    #[test]
+    fn it_expands_variables() {
+        let conf = CONF.parse::<CephConf>().unwrap().with_cluster("backup");
+        assert_eq!(conf.get("osd.3", "log_file"), Some("/var/log/ceph/backup-osd.3.log".to_string()));
+        assert_eq!(
+            conf.get("client.admin", "admin_socket"),
+            Some("/var/run/ceph/backup-client.admin.asok".to_string())
+        );
+        assert_eq!(conf.get("client.rgw.gw1", "log_file"), Some("/srv/run/client.rgw.gw1.log".to_string()));
+        assert_eq!(conf.expand("client.admin", "${name}:$unknown"), "client.admin:$unknown");
+    }
+
+    #[test]
+    fn it_reads_typed_values() {
+        let conf: CephConf = CONF.parse().unwrap();
+        assert_eq!(conf.fsid().unwrap().unwrap().to_string(), "9d5b3c1e-4f0d-4c0e-9a6f-2a9c8e0b3f11");
+        assert_eq!(conf.mon_hosts(), vec!["[v2:10.0.0.1:3300,v1:10.0.0.1:6789]", "10.0.0.2", "10.0.0.3"]);
+        assert_eq!(
+            conf.keyring_paths("client.admin"),
+            vec![
+                "/etc/ceph/ceph.client.admin.keyring",
+                "/etc/ceph/ceph.keyring",
+                "/etc/ceph/keyring",
+                "/etc/ceph/keyring.bin",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_falls_back_to_mon_sections() {
+        let conf: CephConf = "[mon.a]\nmon addr = 10.0.0.1:6789\n[mon.b]\nmon_addr = 10.0.0.2:6789\n"
+            .parse()
+            .unwrap();
+        assert_eq!(conf.mon_hosts(), vec!["10.0.0.1:6789", "10.0.0.2:6789"]);
+        assert_eq!(conf.fsid().unwrap(), None);
+    }
+
+    #[test]
+    fn it_rejects_broken_confs() {
+        assert!("fsid = x\n".parse::<CephConf>().is_err());
+        assert!("[global\n".parse::<CephConf>().is_err());
+        assert!("[global]\nfsid\n".parse::<CephConf>().is_err());
+        assert!("[global]\nfsid = \"x\n".parse::<CephConf>().is_err());
+    }
+}
+
+// Guards against options that refer to each other
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+const DEFAULT_KEYRING: &str =
+    "/etc/ceph/$cluster.$name.keyring,/etc/ceph/$cluster.keyring,/etc/ceph/keyring,/etc/ceph/keyring.bin";
+
+/// A parsed ceph.conf.  Option names are normalized, so `mon host`,
+/// `mon_host` and `mon-host` are all the same option.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CephConf {
+    cluster: String,
+    sections: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl CephConf {
+    /// Read and parse a config file.  The cluster name comes from the file
+    /// name the same way the ceph tools work it out, so `backup.conf` is
+    /// the `backup` cluster.
+    pub fn load<P: AsRef<Path>>(path: P) -> RadosResult<CephConf> {
+        let path = path.as_ref();
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        let conf: CephConf = contents.parse()?;
+        match path.file_name().and_then(|f| f.to_str()) {
+            Some(file) if file.ends_with(".conf") => Ok(conf.with_cluster(&file[..file.len() - ".conf".len()])),
+            _ => Ok(conf),
+        }
+    }
+
+    /// Set the cluster name `$cluster` expands to, `ceph` by default
+    pub fn with_cluster(mut self, cluster: &str) -> CephConf {
+        self.cluster = cluster.to_string();
+        self
+    }
+
+    pub fn cluster(&self) -> &str {
+        &self.cluster
+    }
+
+    /// The names of the sections in the file, sorted
+    pub fn sections(&self) -> Vec<&str> {
+        self.sections.keys().map(|s| s.as_str()).collect()
+    }
+
+    /// An option as written in one section, without expanding variables
+    pub fn get_raw(&self, section: &str, option: &str) -> Option<&str> {
+        self.sections
+            .get(section)
+            .and_then(|s| s.get(&normalize(option)))
+            .map(|v| v.as_str())
+    }
+
+    /// Look up an option for an entity such as `client.admin` or `osd.3`,
+    /// trying its own section, then its type's section, then `[global]`
+    pub fn get(&self, name: &str, option: &str) -> Option<String> {
+        self.get_expanded(name, &normalize(option), 0)
+    }
+
+    /// Look up an option and parse it, e.g. into a `bool` or `u64`
+    pub fn get_parsed<T>(&self, name: &str, option: &str) -> RadosResult<Option<T>>
+    where
+        T: FromStr,
+        T::Err: ::std::fmt::Display,
+    {
+        match self.get(name, option) {
+            Some(value) => match value.parse() {
+                Ok(parsed) => Ok(Some(parsed)),
+                Err(e) => Err(RadosError::new(format!("Invalid value {:?} for {}: {}", value, option, e))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Expand the variables in a value as they would be for an entity
+    pub fn expand(&self, name: &str, value: &str) -> String {
+        self.expand_depth(name, value, 0)
+    }
+
+    /// The cluster fsid from `[global]`, if set
+    pub fn fsid(&self) -> RadosResult<Option<Uuid>> {
+        match self.get("global", "fsid") {
+            Some(fsid) => Ok(Some(Uuid::parse_str(&fsid)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The monitor addresses clients connect to, from `mon host` or else
+    /// the `mon addr` of each `[mon.X]` section older configs use.  An
+    /// address vector like `[v2:..,v1:..]` is kept together as one host.
+    pub fn mon_hosts(&self) -> Vec<String> {
+        if let Some(hosts) = self.get("global", "mon_host") {
+            return split_list(&hosts);
+        }
+        self.sections
+            .iter()
+            .filter(|&(name, _)| name.starts_with("mon."))
+            .filter_map(|(name, _)| self.get(name, "mon_addr"))
+            .collect()
+    }
+
+    /// Where an entity looks for its keyring, in order
+    pub fn keyring_paths(&self, name: &str) -> Vec<String> {
+        let keyring = self.get(name, "keyring").unwrap_or_else(|| self.expand(name, DEFAULT_KEYRING));
+        split_list(&keyring)
+    }
+
+    fn get_expanded(&self, name: &str, option: &str, depth: usize) -> Option<String> {
+        let entity_type = name.split('.').next().unwrap_or(name);
+        [name, entity_type, "global"]
+            .iter()
+            .filter_map(|section| self.sections.get(*section))
+            .find_map(|section| section.get(option))
+            .map(|value| self.expand_depth(name, value, depth))
+    }
+
+    fn expand_depth(&self, name: &str, value: &str, depth: usize) -> String {
+        let mut expanded = String::new();
+        let mut rest = value;
+        while let Some(dollar) = rest.find('$') {
+            expanded.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+            let (var, len) = if rest.starts_with('{') {
+                match rest.find('}') {
+                    Some(end) => (&rest[1..end], end + 1),
+                    None => ("", 0),
+                }
+            } else {
+                let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                (&rest[..end], end)
+            };
+            match self.variable(name, var, depth) {
+                Some(replacement) => expanded.push_str(&replacement),
+                // Ceph leaves what it can't expand alone
+                None => {
+                    expanded.push('$');
+                    expanded.push_str(&rest[..len]);
+                },
+            }
+            rest = &rest[len..];
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    fn variable(&self, name: &str, var: &str, depth: usize) -> Option<String> {
+        let mut parts = name.splitn(2, '.');
+        let entity_type = parts.next().unwrap_or("");
+        let id = parts.next().unwrap_or("");
+        match var {
+            "" => None,
+            "cluster" => Some(self.cluster.clone()),
+            "type" => Some(entity_type.to_string()),
+            "id" => Some(id.to_string()),
+            "name" => Some(name.to_string()),
+            "host" => hostname(),
+            _ if depth < MAX_EXPANSION_DEPTH => self.get_expanded(name, &normalize(var), depth + 1),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for CephConf {
+    type Err = RadosError;
+
+    fn from_str(s: &str) -> RadosResult<CephConf> {
+        let mut sections: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        let mut current: Option<String> = None;
+        let mut lines = s.lines().enumerate();
+        while let Some((n, line)) = lines.next() {
+            // A trailing backslash continues the line
+            let mut line = line.trim().to_string();
+            while line.ends_with('\\') {
+                line.pop();
+                match lines.next() {
+                    Some((_, next)) => line.push_str(next.trim()),
+                    None => break,
+                }
+            }
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') {
+                let end = match line.find(']') {
+                    Some(end) => end,
+                    None => return Err(RadosError::new(format!("ceph.conf line {}: unterminated section name", n + 1))),
+                };
+                let name = line[1..end].trim().to_string();
+                sections.entry(name.clone()).or_default();
+                current = Some(name);
+                continue;
+            }
+            let section = match current {
+                Some(ref section) => section,
+                None => return Err(RadosError::new(format!("ceph.conf line {}: option outside of a section", n + 1))),
+            };
+            let eq = match line.find('=') {
+                Some(eq) => eq,
+                None => return Err(RadosError::new(format!("ceph.conf line {}: expected name = value", n + 1))),
+            };
+            let value = parse_value(line[eq + 1..].trim())
+                .map_err(|e| RadosError::new(format!("ceph.conf line {}: {}", n + 1, e)))?;
+            // Later settings win, as they do in Ceph
+            if let Some(options) = sections.get_mut(section) {
+                options.insert(normalize(&line[..eq]), value);
+            }
+        }
+        Ok(CephConf {
+            cluster: "ceph".to_string(),
+            sections: sections,
+        })
+    }
+}
+
+/// Parse the value half of a `name = value` line, which is either quoted,
+/// with backslash escapes, or runs up to a comment.  Keyrings share the
+/// format.
+pub(crate) fn parse_value(raw: &str) -> Result<String, String> {
+    if !raw.starts_with('"') {
+        let end = raw.find(&['#', ';'][..]).unwrap_or(raw.len());
+        return Ok(raw[..end].trim_end().to_string());
+    }
+    let mut value = String::new();
+    let mut chars = raw[1..].chars();
+    loop {
+        match chars.next() {
+            Some('"') => break,
+            Some('\\') => match chars.next() {
+                Some(c) => value.push(c),
+                None => return Err("unterminated quoted value".to_string()),
+            },
+            Some(c) => value.push(c),
+            None => return Err("unterminated quoted value".to_string()),
+        }
+    }
+    let rest = chars.as_str().trim_start();
+    if !(rest.is_empty() || rest.starts_with('#') || rest.starts_with(';')) {
+        return Err(format!("unexpected {:?} after a quoted value", rest));
+    }
+    Ok(value)
+}
+
+// "mon host", "mon-host" and "mon_host" are the same option
+fn normalize(option: &str) -> String {
+    option.split_whitespace().collect::<Vec<_>>().join("_").replace('-', "_")
+}
+
+// Split on commas, semicolons and whitespace, but not inside brackets
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut depth = 0;
+    for c in value.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            ',' | ';' | ' ' | '\t' if depth == 0 => {
+                if !item.is_empty() {
+                    items.push(item.split_off(0));
+                }
+                continue;
+            },
+            _ => {},
+        }
+        item.push(c);
+    }
+    if !item.is_empty() {
+        items.push(item);
+    }
+    items
+}
+
+// $host is the short hostname
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    let ret_code = unsafe { gethostname(buf.as_mut_ptr() as *mut c_char, buf.len()) };
+    if ret_code < 0 {
+        return None;
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    let host = String::from_utf8_lossy(&buf[..len]).into_owned();
+    Some(host.split('.').next().unwrap_or("").to_string())
+}
@@ -0,0 +1,648 @@
+//! Decode the binary CRUSH map that `osd getcrushmap` returns, the same
+//! format crushtool reads and writes, without shelling out to crushtool.
+//!
+//! ```rust,no_run
+//! # use ceph::Rados;
+//! # use ceph::cmd::osd_getcrushmap;
+//! # use ceph::crush::CrushMap;
+//! # use ceph::error::RadosError;
+//! # fn main() {
+//! #   let _ = run();
+//! # }
+//! # fn run() -> Result<(), RadosError> {
+//! let rados = Rados::connect("admin", "/etc/ceph/ceph.conf")?;
+//! let map = CrushMap::decode(&osd_getcrushmap(&rados)?)?;
+//! for bucket in &map.buckets {
+//!     println!("{} holds {:?}", map.names[&bucket.id], bucket.items);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use decode::Decoder;
+use error::{RadosError, RadosResult};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_map() -> CrushMap {
+        let mut map = CrushMap {
+            max_buckets: 3,
+            max_rules: 2,
+            max_devices: 2,
+            ..CrushMap::default()
+        };
+        map.buckets.push(Bucket {
+            id: -1,
+            bucket_type: 10,
+            alg: BucketAlg::Straw2 {
+                item_weights: vec![0x10000],
+            },
+            hash: 0,
+            weight: 0x10000,
+            items: vec![-3],
+        });
+        map.buckets.push(Bucket {
+            id: -3,
+            bucket_type: 1,
+            alg: BucketAlg::List {
+                item_weights: vec![0x8000, 0x8000],
+                sum_weights: vec![0x8000, 0x10000],
+            },
+            hash: 0,
+            weight: 0x10000,
+            items: vec![0, 1],
+        });
+        map.rules.push(Rule {
+            id: 1,
+            ruleset: 1,
+            rule_type: 1,
+            min_size: 1,
+            max_size: 10,
+            steps: vec![
+                RuleStep {
+                    op: RuleOp::Take,
+                    arg1: -1,
+                    arg2: 0,
+                },
+                RuleStep {
+                    op: RuleOp::ChooseLeafFirstN,
+                    arg1: 0,
+                    arg2: 1,
+                },
+                RuleStep {
+                    op: RuleOp::Emit,
+                    arg1: 0,
+                    arg2: 0,
+                },
+            ],
+        });
+        map.types.insert(0, "osd".to_string());
+        map.types.insert(1, "host".to_string());
+        map.types.insert(10, "root".to_string());
+        map.names.insert(-1, "default".to_string());
+        map.names.insert(-3, "node1".to_string());
+        map.names.insert(0, "osd.0".to_string());
+        map.rule_names.insert(1, "replicated_rule".to_string());
+        map.device_classes.insert(0, 1);
+        map.class_names.insert(1, "ssd".to_string());
+        map.choose_args.insert(
+            -1,
+            vec![ChooseArg {
+                bucket_id: -1,
+                weight_set: vec![vec![0x10000]],
+                ids: vec![],
+            }],
+        );
+        map
+    }
+
+    #[test]
+    fn it_round_trips_a_map() {
+        let map = sample_map();
+        let encoded = map.encode().unwrap();
+        assert_eq!(&encoded[..4], b"\0\0\x01\0");
+        assert_eq!(CrushMap::decode(&encoded).unwrap(), map);
+    }
+
+    #[test]
+    fn it_defaults_missing_tunables() {
+        let mut map = sample_map();
+        map.choose_args.clear();
+        map.device_classes.clear();
+        map.class_names.clear();
+        let encoded = map.encode().unwrap();
+        // Cut off the tunables, classes and choose args, like an argonaut map
+        let tail_len = 4 * 4 + 2 + 4 + 1 + 4 * 4;
+        let decoded = CrushMap::decode(&encoded[..encoded.len() - tail_len]).unwrap();
+        assert_eq!(decoded.tunables, Tunables::legacy());
+        assert_eq!(decoded.rule_names, map.rule_names);
+    }
+
+    #[test]
+    fn it_rejects_bad_maps() {
+        assert!(CrushMap::decode(b"\0\0\x02\0").is_err());
+        let encoded = sample_map().encode().unwrap();
+        assert!(CrushMap::decode(&encoded[..40]).is_err());
+    }
+}
+
+const CRUSH_MAGIC: u32 = 0x0001_0000;
+
+const CRUSH_BUCKET_UNIFORM: u8 = 1;
+const CRUSH_BUCKET_LIST: u8 = 2;
+const CRUSH_BUCKET_TREE: u8 = 3;
+const CRUSH_BUCKET_STRAW: u8 = 4;
+const CRUSH_BUCKET_STRAW2: u8 = 5;
+
+/// A decoded CRUSH map.  Weights are 16.16 fixed point, so 0x10000 is a
+/// weight of 1.0.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CrushMap {
+    /// Bucket ids run from -1 down to -max_buckets
+    pub max_buckets: i32,
+    pub max_rules: u32,
+    pub max_devices: i32,
+    pub buckets: Vec<Bucket>,
+    pub rules: Vec<Rule>,
+    /// Bucket type ids to names, e.g. 1 => "host"
+    pub types: BTreeMap<i32, String>,
+    /// Device and bucket ids to names
+    pub names: BTreeMap<i32, String>,
+    pub rule_names: BTreeMap<i32, String>,
+    pub tunables: Tunables,
+    /// Device ids to class ids
+    pub device_classes: BTreeMap<i32, i32>,
+    pub class_names: BTreeMap<i32, String>,
+    /// For each bucket, the shadow bucket id that holds only the devices of
+    /// each class
+    pub class_buckets: BTreeMap<i32, BTreeMap<i32, i32>>,
+    /// Alternative weights, keyed by pool id or -1 for the compat set the
+    /// balancer uses
+    pub choose_args: BTreeMap<i64, Vec<ChooseArg>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Bucket {
+    pub id: i32,
+    /// An id from `CrushMap::types`
+    pub bucket_type: u16,
+    pub alg: BucketAlg,
+    pub hash: u8,
+    pub weight: u32,
+    pub items: Vec<i32>,
+}
+
+/// How a bucket picks among its items, with the per-algorithm weights
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BucketAlg {
+    Uniform { item_weight: u32 },
+    List {
+        item_weights: Vec<u32>,
+        sum_weights: Vec<u32>,
+    },
+    Tree { node_weights: Vec<u32> },
+    Straw {
+        item_weights: Vec<u32>,
+        straws: Vec<u32>,
+    },
+    Straw2 { item_weights: Vec<u32> },
+}
+
+impl BucketAlg {
+    fn id(&self) -> u8 {
+        match *self {
+            BucketAlg::Uniform { .. } => CRUSH_BUCKET_UNIFORM,
+            BucketAlg::List { .. } => CRUSH_BUCKET_LIST,
+            BucketAlg::Tree { .. } => CRUSH_BUCKET_TREE,
+            BucketAlg::Straw { .. } => CRUSH_BUCKET_STRAW,
+            BucketAlg::Straw2 { .. } => CRUSH_BUCKET_STRAW2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rule {
+    pub id: u32,
+    /// The same as the id since luminous
+    pub ruleset: u8,
+    /// 1 for replicated, 3 for erasure coded pools
+    pub rule_type: u8,
+    pub min_size: u8,
+    pub max_size: u8,
+    pub steps: Vec<RuleStep>,
+}
+
+/// One step of a rule.  What the args mean depends on the op, e.g. for
+/// `Take` arg1 is the bucket and for the choose ops arg1 is the number of
+/// items and arg2 the bucket type.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RuleStep {
+    pub op: RuleOp,
+    pub arg1: i32,
+    pub arg2: i32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RuleOp {
+    Noop,
+    Take,
+    ChooseFirstN,
+    ChooseIndep,
+    Emit,
+    ChooseLeafFirstN,
+    ChooseLeafIndep,
+    SetChooseTries,
+    SetChooseLeafTries,
+    SetChooseLocalTries,
+    SetChooseLocalFallbackTries,
+    SetChooseLeafVaryR,
+    SetChooseLeafStable,
+    /// An op newer than this crate
+    Other(u32),
+}
+
+impl RuleOp {
+    fn from_u32(op: u32) -> RuleOp {
+        match op {
+            0 => RuleOp::Noop,
+            1 => RuleOp::Take,
+            2 => RuleOp::ChooseFirstN,
+            3 => RuleOp::ChooseIndep,
+            4 => RuleOp::Emit,
+            6 => RuleOp::ChooseLeafFirstN,
+            7 => RuleOp::ChooseLeafIndep,
+            8 => RuleOp::SetChooseTries,
+            9 => RuleOp::SetChooseLeafTries,
+            10 => RuleOp::SetChooseLocalTries,
+            11 => RuleOp::SetChooseLocalFallbackTries,
+            12 => RuleOp::SetChooseLeafVaryR,
+            13 => RuleOp::SetChooseLeafStable,
+            other => RuleOp::Other(other),
+        }
+    }
+
+    fn to_u32(self) -> u32 {
+        match self {
+            RuleOp::Noop => 0,
+            RuleOp::Take => 1,
+            RuleOp::ChooseFirstN => 2,
+            RuleOp::ChooseIndep => 3,
+            RuleOp::Emit => 4,
+            RuleOp::ChooseLeafFirstN => 6,
+            RuleOp::ChooseLeafIndep => 7,
+            RuleOp::SetChooseTries => 8,
+            RuleOp::SetChooseLeafTries => 9,
+            RuleOp::SetChooseLocalTries => 10,
+            RuleOp::SetChooseLocalFallbackTries => 11,
+            RuleOp::SetChooseLeafVaryR => 12,
+            RuleOp::SetChooseLeafStable => 13,
+            RuleOp::Other(op) => op,
+        }
+    }
+}
+
+/// The tunables shown by `ceph osd crush show-tunables`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tunables {
+    pub choose_local_tries: u32,
+    pub choose_local_fallback_tries: u32,
+    pub choose_total_tries: u32,
+    pub chooseleaf_descend_once: u32,
+    pub chooseleaf_vary_r: u8,
+    pub straw_calc_version: u8,
+    /// A bit per `BucketAlg` id
+    pub allowed_bucket_algs: u32,
+    pub chooseleaf_stable: u8,
+}
+
+impl Tunables {
+    /// What a map without tunables gets, the argonaut behaviour
+    pub fn legacy() -> Tunables {
+        Tunables {
+            choose_local_tries: 2,
+            choose_local_fallback_tries: 5,
+            choose_total_tries: 19,
+            chooseleaf_descend_once: 0,
+            chooseleaf_vary_r: 0,
+            straw_calc_version: 0,
+            allowed_bucket_algs: (1 << CRUSH_BUCKET_UNIFORM) | (1 << CRUSH_BUCKET_LIST) | (1 << CRUSH_BUCKET_STRAW),
+            chooseleaf_stable: 0,
+        }
+    }
+}
+
+impl Default for Tunables {
+    fn default() -> Tunables {
+        Tunables::legacy()
+    }
+}
+
+/// The alternative weights and ids of one bucket
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChooseArg {
+    pub bucket_id: i32,
+    /// One list of item weights per replica position
+    pub weight_set: Vec<Vec<u32>>,
+    /// Item ids to hash with instead of the real ones
+    pub ids: Vec<i32>,
+}
+
+impl CrushMap {
+    pub fn decode(buf: &[u8]) -> RadosResult<CrushMap> {
+        let mut d = Decoder::new(buf);
+        let magic = d.u32()?;
+        if magic != CRUSH_MAGIC {
+            return Err(RadosError::new(format!("Not a CRUSH map, the magic is {:#x}", magic)));
+        }
+        let mut map = CrushMap {
+            max_buckets: d.i32()?,
+            max_rules: d.u32()?,
+            max_devices: d.i32()?,
+            ..CrushMap::default()
+        };
+        for _ in 0..map.max_buckets {
+            if d.u32()? == 0 {
+                // An unused bucket id
+                continue;
+            }
+            map.buckets.push(decode_bucket(&mut d)?);
+        }
+        for id in 0..map.max_rules {
+            if d.u32()? == 0 {
+                continue;
+            }
+            let len = d.u32()?;
+            let mut rule = Rule {
+                id: id,
+                ruleset: d.u8()?,
+                rule_type: d.u8()?,
+                min_size: d.u8()?,
+                max_size: d.u8()?,
+                steps: Vec::new(),
+            };
+            for _ in 0..len {
+                rule.steps.push(RuleStep {
+                    op: RuleOp::from_u32(d.u32()?),
+                    arg1: d.i32()?,
+                    arg2: d.i32()?,
+                });
+            }
+            map.rules.push(rule);
+        }
+        map.types = d.map(Decoder::i32, Decoder::string)?;
+        map.names = d.map(Decoder::i32, Decoder::string)?;
+        map.rule_names = d.map(Decoder::i32, Decoder::string)?;
+
+        // Everything after the names was added over time so older maps
+        // stop early and keep the defaults
+        if d.is_empty() {
+            return Ok(map);
+        }
+        map.tunables.choose_local_tries = d.u32()?;
+        map.tunables.choose_local_fallback_tries = d.u32()?;
+        map.tunables.choose_total_tries = d.u32()?;
+        if d.is_empty() {
+            return Ok(map);
+        }
+        map.tunables.chooseleaf_descend_once = d.u32()?;
+        if d.is_empty() {
+            return Ok(map);
+        }
+        map.tunables.chooseleaf_vary_r = d.u8()?;
+        if d.is_empty() {
+            return Ok(map);
+        }
+        map.tunables.straw_calc_version = d.u8()?;
+        if d.is_empty() {
+            return Ok(map);
+        }
+        map.tunables.allowed_bucket_algs = d.u32()?;
+        if d.is_empty() {
+            return Ok(map);
+        }
+        map.tunables.chooseleaf_stable = d.u8()?;
+        if d.is_empty() {
+            return Ok(map);
+        }
+        map.device_classes = d.map(Decoder::i32, Decoder::i32)?;
+        map.class_names = d.map(Decoder::i32, Decoder::string)?;
+        map.class_buckets = d.map(Decoder::i32, |d| d.map(Decoder::i32, Decoder::i32))?;
+        if d.is_empty() {
+            return Ok(map);
+        }
+        let choose_args = d.u32()?;
+        for _ in 0..choose_args {
+            let key = d.i64()?;
+            let args = d.list(|d| {
+                let position = d.u32()? as i32;
+                Ok(ChooseArg {
+                    bucket_id: -1 - position,
+                    weight_set: d.list(|d| d.list(Decoder::u32))?,
+                    ids: d.list(Decoder::i32)?,
+                })
+            })?;
+            map.choose_args.insert(key, args);
+        }
+        Ok(map)
+    }
+
+    /// Encode the map the way a luminous or newer cluster does, ready for
+    /// `osd setcrushmap`
+    pub fn encode(&self) -> RadosResult<Vec<u8>> {
+        let mut buf = Vec::new();
+        buf.write_u32::<LittleEndian>(CRUSH_MAGIC)?;
+        buf.write_i32::<LittleEndian>(self.max_buckets)?;
+        buf.write_u32::<LittleEndian>(self.max_rules)?;
+        buf.write_i32::<LittleEndian>(self.max_devices)?;
+
+        for position in 0..self.max_buckets {
+            match self.buckets.iter().find(|b| b.id == -1 - position) {
+                Some(bucket) => encode_bucket(&mut buf, bucket)?,
+                None => buf.write_u32::<LittleEndian>(0)?,
+            }
+        }
+        for id in 0..self.max_rules {
+            let rule = match self.rules.iter().find(|r| r.id == id) {
+                Some(rule) => rule,
+                None => {
+                    buf.write_u32::<LittleEndian>(0)?;
+                    continue;
+                },
+            };
+            buf.write_u32::<LittleEndian>(1)?;
+            buf.write_u32::<LittleEndian>(rule.steps.len() as u32)?;
+            buf.write_u8(rule.ruleset)?;
+            buf.write_u8(rule.rule_type)?;
+            buf.write_u8(rule.min_size)?;
+            buf.write_u8(rule.max_size)?;
+            for step in &rule.steps {
+                buf.write_u32::<LittleEndian>(step.op.to_u32())?;
+                buf.write_i32::<LittleEndian>(step.arg1)?;
+                buf.write_i32::<LittleEndian>(step.arg2)?;
+            }
+        }
+        encode_names(&mut buf, &self.types)?;
+        encode_names(&mut buf, &self.names)?;
+        encode_names(&mut buf, &self.rule_names)?;
+
+        let tunables = &self.tunables;
+        buf.write_u32::<LittleEndian>(tunables.choose_local_tries)?;
+        buf.write_u32::<LittleEndian>(tunables.choose_local_fallback_tries)?;
+        buf.write_u32::<LittleEndian>(tunables.choose_total_tries)?;
+        buf.write_u32::<LittleEndian>(tunables.chooseleaf_descend_once)?;
+        buf.write_u8(tunables.chooseleaf_vary_r)?;
+        buf.write_u8(tunables.straw_calc_version)?;
+        buf.write_u32::<LittleEndian>(tunables.allowed_bucket_algs)?;
+        buf.write_u8(tunables.chooseleaf_stable)?;
+
+        buf.write_u32::<LittleEndian>(self.device_classes.len() as u32)?;
+        for (device, class) in &self.device_classes {
+            buf.write_i32::<LittleEndian>(*device)?;
+            buf.write_i32::<LittleEndian>(*class)?;
+        }
+        encode_names(&mut buf, &self.class_names)?;
+        buf.write_u32::<LittleEndian>(self.class_buckets.len() as u32)?;
+        for (bucket, by_class) in &self.class_buckets {
+            buf.write_i32::<LittleEndian>(*bucket)?;
+            buf.write_u32::<LittleEndian>(by_class.len() as u32)?;
+            for (class, shadow) in by_class {
+                buf.write_i32::<LittleEndian>(*class)?;
+                buf.write_i32::<LittleEndian>(*shadow)?;
+            }
+        }
+        buf.write_u32::<LittleEndian>(self.choose_args.len() as u32)?;
+        for (key, args) in &self.choose_args {
+            buf.write_i64::<LittleEndian>(*key)?;
+            buf.write_u32::<LittleEndian>(args.len() as u32)?;
+            for arg in args {
+                buf.write_u32::<LittleEndian>((-1 - arg.bucket_id) as u32)?;
+                buf.write_u32::<LittleEndian>(arg.weight_set.len() as u32)?;
+                for weights in &arg.weight_set {
+                    encode_u32s(&mut buf, weights, true)?;
+                }
+                buf.write_u32::<LittleEndian>(arg.ids.len() as u32)?;
+                for id in &arg.ids {
+                    buf.write_i32::<LittleEndian>(*id)?;
+                }
+            }
+        }
+        Ok(buf)
+    }
+
+    /// The bucket with this id
+    pub fn bucket(&self, id: i32) -> Option<&Bucket> {
+        self.buckets.iter().find(|b| b.id == id)
+    }
+}
+
+fn decode_bucket(d: &mut Decoder) -> RadosResult<Bucket> {
+    let id = d.i32()?;
+    let bucket_type = d.u16()?;
+    let alg = d.u8()?;
+    let hash = d.u8()?;
+    let weight = d.u32()?;
+    let size = d.u32()?;
+    let mut items = Vec::new();
+    for _ in 0..size {
+        items.push(d.i32()?);
+    }
+    let alg = match alg {
+        CRUSH_BUCKET_UNIFORM => BucketAlg::Uniform { item_weight: d.u32()? },
+        CRUSH_BUCKET_LIST => {
+            let mut item_weights = Vec::new();
+            let mut sum_weights = Vec::new();
+            for _ in 0..size {
+                item_weights.push(d.u32()?);
+                sum_weights.push(d.u32()?);
+            }
+            BucketAlg::List {
+                item_weights: item_weights,
+                sum_weights: sum_weights,
+            }
+        },
+        CRUSH_BUCKET_TREE => {
+            let num_nodes = d.u8()?;
+            let mut node_weights = Vec::new();
+            for _ in 0..num_nodes {
+                node_weights.push(d.u32()?);
+            }
+            BucketAlg::Tree { node_weights: node_weights }
+        },
+        CRUSH_BUCKET_STRAW => {
+            let mut item_weights = Vec::new();
+            let mut straws = Vec::new();
+            for _ in 0..size {
+                item_weights.push(d.u32()?);
+                straws.push(d.u32()?);
+            }
+            BucketAlg::Straw {
+                item_weights: item_weights,
+                straws: straws,
+            }
+        },
+        CRUSH_BUCKET_STRAW2 => {
+            let mut item_weights = Vec::new();
+            for _ in 0..size {
+                item_weights.push(d.u32()?);
+            }
+            BucketAlg::Straw2 { item_weights: item_weights }
+        },
+        other => return Err(RadosError::new(format!("Bucket {} has unknown algorithm {}", id, other))),
+    };
+    Ok(Bucket {
+        id: id,
+        bucket_type: bucket_type,
+        alg: alg,
+        hash: hash,
+        weight: weight,
+        items: items,
+    })
+}
+
+fn encode_bucket(buf: &mut Vec<u8>, bucket: &Bucket) -> RadosResult<()> {
+    // The algorithm goes out twice, the first time as a u32 that says
+    // whether the slot is used
+    buf.write_u32::<LittleEndian>(bucket.alg.id() as u32)?;
+    buf.write_i32::<LittleEndian>(bucket.id)?;
+    buf.write_u16::<LittleEndian>(bucket.bucket_type)?;
+    buf.write_u8(bucket.alg.id())?;
+    buf.write_u8(bucket.hash)?;
+    buf.write_u32::<LittleEndian>(bucket.weight)?;
+    buf.write_u32::<LittleEndian>(bucket.items.len() as u32)?;
+    for item in &bucket.items {
+        buf.write_i32::<LittleEndian>(*item)?;
+    }
+    match bucket.alg {
+        BucketAlg::Uniform { item_weight } => buf.write_u32::<LittleEndian>(item_weight)?,
+        BucketAlg::List {
+            ref item_weights,
+            ref sum_weights,
+        } => {
+            for (item_weight, sum_weight) in item_weights.iter().zip(sum_weights) {
+                buf.write_u32::<LittleEndian>(*item_weight)?;
+                buf.write_u32::<LittleEndian>(*sum_weight)?;
+            }
+        },
+        BucketAlg::Tree { ref node_weights } => {
+            buf.write_u8(node_weights.len() as u8)?;
+            encode_u32s(buf, node_weights, false)?;
+        },
+        BucketAlg::Straw {
+            ref item_weights,
+            ref straws,
+        } => {
+            for (item_weight, straw) in item_weights.iter().zip(straws) {
+                buf.write_u32::<LittleEndian>(*item_weight)?;
+                buf.write_u32::<LittleEndian>(*straw)?;
+            }
+        },
+        BucketAlg::Straw2 { ref item_weights } => encode_u32s(buf, item_weights, false)?,
+    }
+    Ok(())
+}
+
+fn encode_u32s(buf: &mut Vec<u8>, values: &[u32], with_len: bool) -> RadosResult<()> {
+    if with_len {
+        buf.write_u32::<LittleEndian>(values.len() as u32)?;
+    }
+    for value in values {
+        buf.write_u32::<LittleEndian>(*value)?;
+    }
+    Ok(())
+}
+
+fn encode_names(buf: &mut Vec<u8>, names: &BTreeMap<i32, String>) -> RadosResult<()> {
+    buf.write_u32::<LittleEndian>(names.len() as u32)?;
+    for (id, name) in names {
+        buf.write_i32::<LittleEndian>(*id)?;
+        buf.write_u32::<LittleEndian>(name.len() as u32)?;
+        buf.extend_from_slice(name.as_bytes());
+    }
+    Ok(())
+}
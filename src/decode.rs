@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use byteorder::{ByteOrder, LittleEndian};
+use uuid::Uuid;
+
+use error::{RadosError, RadosResult};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_little_endian() {
+        let mut decoder = Decoder::new(b"\x01\x02\x00\x03\x00\x00\x00\x02\x00\x00\x00hi");
+        assert_eq!(decoder.u8().unwrap(), 1);
+        assert_eq!(decoder.u16().unwrap(), 2);
+        assert_eq!(decoder.u32().unwrap(), 3);
+        assert_eq!(decoder.string().unwrap(), "hi");
+        assert!(decoder.is_empty());
+        assert!(decoder.u8().is_err());
+    }
+
+    #[test]
+    fn it_skips_unknown_struct_fields() {
+        // Version 2 of a struct with a field this decoder doesn't know
+        let mut decoder = Decoder::new(b"\x02\x01\x05\x00\x00\x00\x07\x00\x00\x00\xff\x09");
+        let header = decoder.start("test", 1).unwrap();
+        assert_eq!(header.version, 2);
+        assert_eq!(decoder.u32().unwrap(), 7);
+        decoder.finish(header).unwrap();
+        assert_eq!(decoder.u8().unwrap(), 9);
+
+        let mut too_new = Decoder::new(b"\x03\x03\x00\x00\x00\x00");
+        assert!(too_new.start("test", 2).is_err());
+    }
+}
+
+// Builds encoded structs for the decoder tests
+#[cfg(test)]
+pub(crate) mod encode {
+    use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+
+    /// Write an ENCODE_START header, returning where the struct starts
+    /// for `end_struct` to fill in the length
+    pub fn start_struct(buf: &mut Vec<u8>, version: u8, compat: u8) -> usize {
+        buf.push(version);
+        buf.push(compat);
+        buf.extend_from_slice(&[0; 4]);
+        buf.len()
+    }
+
+    pub fn end_struct(buf: &mut Vec<u8>, start: usize) {
+        let len = (buf.len() - start) as u32;
+        LittleEndian::write_u32(&mut buf[start - 4..start], len);
+    }
+
+    pub fn string(buf: &mut Vec<u8>, s: &str) {
+        buf.write_u32::<LittleEndian>(s.len() as u32).unwrap();
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    /// A v2 protocol entity_addr_t for an IPv4 address
+    pub fn addr_v4(buf: &mut Vec<u8>, addr_type: u32, ip: [u8; 4], port: u16) {
+        buf.push(1);
+        let start = start_struct(buf, 1, 1);
+        buf.write_u32::<LittleEndian>(addr_type).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(16).unwrap();
+        buf.write_u16::<LittleEndian>(2).unwrap();
+        buf.extend_from_slice(&[(port >> 8) as u8, port as u8]);
+        buf.extend_from_slice(&ip);
+        buf.extend_from_slice(&[0; 8]);
+        end_struct(buf, start);
+    }
+
+    /// A pre-nautilus entity_addr_t for an IPv4 address
+    pub fn legacy_addr_v4(buf: &mut Vec<u8>, ip: [u8; 4], port: u16) {
+        buf.extend_from_slice(&[0; 8]);
+        let mut storage = [0u8; 128];
+        BigEndian::write_u16(&mut storage[0..2], 2);
+        BigEndian::write_u16(&mut storage[2..4], port);
+        storage[4..8].copy_from_slice(&ip);
+        buf.extend_from_slice(&storage);
+    }
+}
+
+pub(crate) struct StructHeader {
+    pub version: u8,
+    end: usize,
+}
+
+// Reads the little endian encoding Ceph's encode()/decode() use for its
+// maps.  Running off the end is an error rather than a panic so truncated
+// maps are reported.
+pub(crate) struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Decoder<'a> {
+        Decoder { buf: buf, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> RadosResult<&'a [u8]> {
+        if self.buf.len() - self.pos < len {
+            return Err(RadosError::new(format!(
+                "Truncated data: wanted {} bytes at offset {} of {}",
+                len,
+                self.pos,
+                self.buf.len()
+            )));
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> RadosResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> RadosResult<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> RadosResult<u16> {
+        Ok(LittleEndian::read_u16(self.bytes(2)?))
+    }
+
+    pub fn u32(&mut self) -> RadosResult<u32> {
+        Ok(LittleEndian::read_u32(self.bytes(4)?))
+    }
+
+    pub fn i32(&mut self) -> RadosResult<i32> {
+        Ok(LittleEndian::read_i32(self.bytes(4)?))
+    }
+
+    pub fn u64(&mut self) -> RadosResult<u64> {
+        Ok(LittleEndian::read_u64(self.bytes(8)?))
+    }
+
+    pub fn i64(&mut self) -> RadosResult<i64> {
+        Ok(LittleEndian::read_i64(self.bytes(8)?))
+    }
+
+    /// A uuid as its 16 raw bytes
+    pub fn uuid(&mut self) -> RadosResult<Uuid> {
+        Ok(Uuid::from_bytes(self.bytes(16)?)?)
+    }
+
+    /// A utime_t, seconds and nanoseconds as u32s
+    pub fn utime(&mut self) -> RadosResult<SystemTime> {
+        let secs = self.u32()?;
+        let nanos = self.u32()?;
+        Ok(UNIX_EPOCH + Duration::new(secs as u64, nanos))
+    }
+
+    /// The header ENCODE_START writes: the struct version, the oldest
+    /// version that can decode it and the length.  `supported` is the
+    /// newest version the caller understands.
+    pub fn start(&mut self, what: &str, supported: u8) -> RadosResult<StructHeader> {
+        let version = self.u8()?;
+        let compat = self.u8()?;
+        if compat > supported {
+            return Err(RadosError::new(format!(
+                "{} version {} needs a decoder for version {}, only {} is supported",
+                what,
+                version,
+                compat,
+                supported
+            )));
+        }
+        let len = self.u32()? as usize;
+        if self.buf.len() - self.pos < len {
+            return Err(RadosError::new(format!("Truncated data: struct of {} bytes at offset {}", len, self.pos)));
+        }
+        Ok(StructHeader {
+            version: version,
+            end: self.pos + len,
+        })
+    }
+
+    /// Skip whatever a newer encoder added to the end of the struct
+    pub fn finish(&mut self, header: StructHeader) -> RadosResult<()> {
+        if self.pos > header.end {
+            return Err(RadosError::new(format!("Read past the end of a struct at offset {}", header.end)));
+        }
+        self.pos = header.end;
+        Ok(())
+    }
+
+    /// A u32 length followed by that many bytes
+    pub fn string(&mut self) -> RadosResult<String> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    /// A u32 count followed by that many items
+    pub fn list<T, F>(&mut self, mut item: F) -> RadosResult<Vec<T>>
+    where
+        F: FnMut(&mut Decoder<'a>) -> RadosResult<T>,
+    {
+        let count = self.u32()?;
+        let mut items = Vec::new();
+        for _ in 0..count {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    /// A u32 count followed by that many key value pairs
+    pub fn map<K, V, FK, FV>(&mut self, mut key: FK, mut value: FV) -> RadosResult<BTreeMap<K, V>>
+    where
+        K: Ord,
+        FK: FnMut(&mut Decoder<'a>) -> RadosResult<K>,
+        FV: FnMut(&mut Decoder<'a>) -> RadosResult<V>,
+    {
+        let count = self.u32()?;
+        let mut map = BTreeMap::new();
+        for _ in 0..count {
+            let k = key(self)?;
+            map.insert(k, value(self)?);
+        }
+        Ok(map)
+    }
+}
@@ -15,7 +15,8 @@ extern crate serde_json;
 
 
 
-use ceph_version::CephVersion;
+use ceph_version::{CephRelease, CephVersion};
+use serde_json::Value;
 use serde_json::error::Error as SerdeJsonError;
 use std::{fmt, str};
 use std::error::Error as StdError;
@@ -36,16 +37,29 @@ pub enum RadosError {
     ParseIntError(ParseIntError),
     ParseError(ParseError),
     SerdeError(SerdeJsonError),
-    /// This should be the minimum version and the current version
-    MinVersion(CephVersion, CephVersion),
+    /// This should be the minimum release and the current version
+    MinVersion(CephRelease, CephVersion),
     Parse(String),
+    /// The operation was stopped through its `CancellationToken`
+    Cancelled,
+    /// A command failed to run or its output couldn't be parsed.  `args`
+    /// names everything that was sent along with the prefix.  Their values
+    /// are left out since some, like keys and caps, are secret.
+    CommandFailed {
+        prefix: String,
+        args: Vec<String>,
+        source: Box<RadosError>,
+    },
 }
 
 pub type RadosResult<T> = Result<T, RadosError>;
 
 impl fmt::Display for RadosError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(self.description())
+        match *self {
+            RadosError::CommandFailed { .. } => f.write_str(&self.to_string()),
+            _ => f.write_str(self.description()),
+        }
     }
 }
 
@@ -62,7 +76,8 @@ impl StdError for RadosError {
             RadosError::SerdeError(ref e) => e.description(),
             RadosError::MinVersion(ref _min, ref _current_version) => "Ceph version is too low",
             RadosError::Parse(ref _input) => "An error occurred during parsing",
-
+            RadosError::Cancelled => "The operation was cancelled",
+            RadosError::CommandFailed { ref source, .. } => source.description(),
         }
     }
     fn cause(&self) -> Option<&StdError> {
@@ -77,6 +92,8 @@ impl StdError for RadosError {
             RadosError::SerdeError(ref e) => e.cause(),
             RadosError::MinVersion(ref _min, ref _current_version) => None,
             RadosError::Parse(ref _input) => None,
+            RadosError::Cancelled => None,
+            RadosError::CommandFailed { ref source, .. } => Some(&**source),
         }
     }
 }
@@ -87,6 +104,23 @@ impl RadosError {
         RadosError::Error(err)
     }
 
+    /// Wrap an error with the command that caused it.  Errors that already
+    /// carry their command are passed through untouched.
+    pub fn command_failed(cmd: &Value, source: RadosError) -> RadosError {
+        if let RadosError::CommandFailed { .. } = source {
+            return source;
+        }
+        let args = match cmd.as_object() {
+            Some(map) => map.keys().filter(|k| *k != "prefix").cloned().collect(),
+            None => Vec::new(),
+        };
+        RadosError::CommandFailed {
+            prefix: cmd["prefix"].as_str().unwrap_or_default().to_string(),
+            args: args,
+            source: Box::new(source),
+        }
+    }
+
     /// Convert a RadosError into a String representation.
     pub fn to_string(&self) -> String {
         match *self {
@@ -99,9 +133,15 @@ impl RadosError {
             RadosError::ParseIntError(ref err) => err.description().to_string(),
             RadosError::SerdeError(ref err) => err.description().to_string(),
             RadosError::MinVersion(ref min, ref current_version) => {
-                format!("{:?} minimum, your version is {:?}", min, current_version)
+                format!("{} minimum, your version is {}", min, current_version)
             },
             RadosError::Parse(ref input) => format!("Couldn't parse the CephVersion from {}", input),
+            RadosError::Cancelled => "The operation was cancelled".to_string(),
+            RadosError::CommandFailed {
+                ref prefix,
+                ref args,
+                ref source,
+            } => format!("{} ({}) failed: {}", prefix, args.join(", "), source.to_string()),
         }
     }
 }
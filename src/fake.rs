@@ -0,0 +1,420 @@
+//! An in-memory stand in for a pool, for testing code written against
+//! `ObjectBackend` without a cluster.  It follows librados closely enough
+//! for tests: missing objects fail with ENOENT, missing xattrs with
+//! ENODATA, writes while reading a snapshot with EROFS and growing an
+//! object past the osds' default 128MiB limit with EFBIG.
+//!
+//! Turn the feature on in `[dev-dependencies]`.  To run the tests where
+//! librados isn't installed, also build them with
+//! `RUSTFLAGS="--cfg ceph_without_librados"`, which leaves out the link
+//! against it.  Tests that still call into librados then fail to link.
+//!
+//! ```
+//! # use ceph::ObjectBackend;
+//! # use ceph::fake::FakeIoCtx;
+//! // Code under test takes any backend
+//! fn save<B: ObjectBackend>(pool: &B, name: &str, data: &[u8]) -> ceph::error::RadosResult<()> {
+//!     pool.write_full(name, data)?;
+//!     pool.setxattr(name, "saved-by", b"test")
+//! }
+//!
+//! let pool = FakeIoCtx::new();
+//! save(&pool, "greeting", b"hello").unwrap();
+//! assert_eq!(pool.read("greeting", 64, 0).unwrap(), b"hello");
+//! assert_eq!(pool.getxattr("greeting", "saved-by").unwrap(), b"test");
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use libc::{c_int, EEXIST, EFBIG, EINVAL, ENODATA, ENOENT, EROFS};
+
+use ceph::get_error;
+use error::{RadosError, RadosResult};
+use object_backend::ObjectBackend;
+use rados::LIBRADOS_SNAP_HEAD;
+
+// The osds' default osd_max_object_size.  Writes past it fail with EFBIG.
+const MAX_OBJECT_SIZE: u64 = 128 << 20;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_and_writes_objects() {
+        let pool = FakeIoCtx::new();
+        pool.write("obj", b"world", 6).unwrap();
+        pool.write("obj", b"hello", 0).unwrap();
+        assert_eq!(pool.read("obj", 64, 0).unwrap(), b"hello\0world");
+        assert_eq!(pool.read("obj", 3, 6).unwrap(), b"wor");
+        assert_eq!(pool.read("obj", 3, 100).unwrap(), b"");
+
+        pool.append("obj", b"!").unwrap();
+        pool.trunc("obj", 5).unwrap();
+        assert_eq!(pool.stat("obj").unwrap().0, 5);
+        pool.write_full("other", b"x").unwrap();
+        let mut names = pool.object_names().unwrap();
+        names.sort();
+        assert_eq!(names, vec!["obj", "other"]);
+
+        pool.remove("obj").unwrap();
+        assert!(pool.read("obj", 1, 0).is_err());
+        assert!(pool.remove("obj").is_err());
+    }
+
+    #[test]
+    fn it_rejects_offsets_past_the_address_space() {
+        let pool = FakeIoCtx::new();
+        pool.write("obj", b"hello", 0).unwrap();
+        assert!(pool.write("obj", b"x", u64::MAX).is_err());
+        assert!(pool.read("obj", usize::MAX, 1).is_err());
+        assert_eq!(pool.read("obj", usize::MAX, 0).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn it_rejects_objects_past_the_max_size() {
+        let pool = FakeIoCtx::new();
+        pool.write("obj", b"hello", 0).unwrap();
+        assert!(pool.write("obj", b"x", u64::MAX - 1).is_err());
+        assert!(pool.write("obj", b"x", MAX_OBJECT_SIZE).is_err());
+        assert!(pool.trunc("obj", u64::MAX).is_err());
+        assert!(pool.trunc("obj", MAX_OBJECT_SIZE + 1).is_err());
+        assert_eq!(pool.read("obj", 64, 0).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn it_keeps_xattrs_and_omap() {
+        let pool = FakeIoCtx::new();
+        pool.setxattr("obj", "user.a", b"1").unwrap();
+        assert_eq!(pool.getxattr("obj", "user.a").unwrap(), b"1");
+        assert!(pool.getxattr("obj", "user.b").is_err());
+        pool.rmxattr("obj", "user.a").unwrap();
+        assert!(pool.xattrs("obj").unwrap().is_empty());
+
+        let mut entries = BTreeMap::new();
+        for key in &["a", "b", "c"] {
+            entries.insert(key.to_string(), key.as_bytes().to_vec());
+        }
+        pool.omap_set("obj", &entries).unwrap();
+        let page = pool.omap_get("obj", "a", 1).unwrap();
+        assert_eq!(page.keys().collect::<Vec<_>>(), vec!["b"]);
+        pool.omap_rm_keys("obj", &["b"]).unwrap();
+        assert_eq!(pool.omap_get("obj", "", 10).unwrap().len(), 2);
+        pool.omap_clear("obj").unwrap();
+        assert!(pool.omap_get("obj", "", 10).unwrap().is_empty());
+        assert!(pool.omap_get("missing", "", 10).is_err());
+    }
+
+    #[test]
+    fn it_snapshots_the_pool() {
+        let pool = FakeIoCtx::new();
+        pool.write_full("obj", b"before").unwrap();
+        pool.snap_create("snap1").unwrap();
+        assert!(pool.snap_create("snap1").is_err());
+        pool.write_full("obj", b"after").unwrap();
+        pool.write_full("new", b"new").unwrap();
+
+        // Another handle on the pool reads the snapshot
+        let reader = pool.clone();
+        reader.snap_set_read(pool.snap_lookup("snap1").unwrap()).unwrap();
+        assert_eq!(reader.read("obj", 64, 0).unwrap(), b"before");
+        assert!(reader.stat("new").is_err());
+        assert!(reader.write_full("obj", b"nope").is_err());
+        reader.snap_set_read(LIBRADOS_SNAP_HEAD).unwrap();
+        assert_eq!(reader.read("obj", 64, 0).unwrap(), b"after");
+
+        pool.snap_rollback("obj", "snap1").unwrap();
+        pool.snap_rollback("new", "snap1").unwrap();
+        assert_eq!(pool.read("obj", 64, 0).unwrap(), b"before");
+        assert!(pool.stat("new").is_err());
+
+        pool.snap_remove("snap1").unwrap();
+        assert!(pool.snap_lookup("snap1").is_err());
+    }
+}
+
+#[derive(Clone, Debug)]
+struct FakeObject {
+    data: Vec<u8>,
+    mtime: SystemTime,
+    xattrs: BTreeMap<String, Vec<u8>>,
+    omap: BTreeMap<String, Vec<u8>>,
+}
+
+impl FakeObject {
+    fn new() -> FakeObject {
+        FakeObject {
+            data: Vec::new(),
+            mtime: SystemTime::now(),
+            xattrs: BTreeMap::new(),
+            omap: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FakePool {
+    objects: BTreeMap<String, FakeObject>,
+    // Snap ids to the name and a copy of every object at the time
+    snaps: BTreeMap<u64, (String, BTreeMap<String, FakeObject>)>,
+    last_snap: u64,
+}
+
+impl FakePool {
+    fn snap_id(&self, name: &str) -> RadosResult<u64> {
+        match self.snaps.iter().find(|&(_, &(ref snap, _))| snap == name) {
+            Some((id, _)) => Ok(*id),
+            None => errno(ENOENT),
+        }
+    }
+}
+
+/// A pool held in memory.  Clones are further handles on the same pool,
+/// each reading from its own snapshot the way separate `IoCtx`s do.
+#[derive(Clone, Debug)]
+pub struct FakeIoCtx {
+    pool: Rc<RefCell<FakePool>>,
+    read_snap: Cell<u64>,
+}
+
+impl Default for FakeIoCtx {
+    fn default() -> FakeIoCtx {
+        FakeIoCtx {
+            pool: Rc::new(RefCell::new(FakePool::default())),
+            read_snap: Cell::new(LIBRADOS_SNAP_HEAD),
+        }
+    }
+}
+
+impl FakeIoCtx {
+    /// An empty pool
+    pub fn new() -> FakeIoCtx {
+        FakeIoCtx::default()
+    }
+
+    // Look at an object as of the snapshot being read
+    fn with_object<T, F>(&self, object: &str, f: F) -> RadosResult<T>
+    where
+        F: FnOnce(&FakeObject) -> RadosResult<T>,
+    {
+        let pool = self.pool.borrow();
+        let objects = match self.read_snap.get() {
+            LIBRADOS_SNAP_HEAD => &pool.objects,
+            snap => match pool.snaps.get(&snap) {
+                Some(&(_, ref objects)) => objects,
+                None => return errno(ENOENT),
+            },
+        };
+        match objects.get(object) {
+            Some(o) => f(o),
+            None => errno(ENOENT),
+        }
+    }
+
+    // Change an object, creating it first when `create` is set
+    fn modify<F>(&self, object: &str, create: bool, f: F) -> RadosResult<()>
+    where
+        F: FnOnce(&mut FakeObject) -> RadosResult<()>,
+    {
+        if self.read_snap.get() != LIBRADOS_SNAP_HEAD {
+            return errno(EROFS);
+        }
+        let mut pool = self.pool.borrow_mut();
+        if create {
+            pool.objects.entry(object.to_string()).or_insert_with(FakeObject::new);
+        }
+        match pool.objects.get_mut(object) {
+            Some(o) => {
+                f(o)?;
+                o.mtime = SystemTime::now();
+                Ok(())
+            },
+            None => errno(ENOENT),
+        }
+    }
+}
+
+impl ObjectBackend for FakeIoCtx {
+    fn read(&self, object: &str, len: usize, offset: u64) -> RadosResult<Vec<u8>> {
+        self.with_object(object, |o| {
+            let start = cmp::min(offset, o.data.len() as u64) as usize;
+            let end = match start.checked_add(len) {
+                Some(end) => cmp::min(end, o.data.len()),
+                None => return errno(EINVAL),
+            };
+            Ok(o.data[start..end].to_vec())
+        })
+    }
+
+    fn write(&self, object: &str, data: &[u8], offset: u64) -> RadosResult<()> {
+        self.modify(object, true, |o| {
+            let end = match offset.checked_add(data.len() as u64) {
+                Some(end) if end <= MAX_OBJECT_SIZE => end as usize,
+                Some(_) => return errno(EFBIG),
+                None => return errno(EINVAL),
+            };
+            let offset = offset as usize;
+            if o.data.len() < end {
+                o.data.resize(end, 0);
+            }
+            o.data[offset..end].copy_from_slice(data);
+            Ok(())
+        })
+    }
+
+    fn write_full(&self, object: &str, data: &[u8]) -> RadosResult<()> {
+        self.modify(object, true, |o| {
+            if data.len() as u64 > MAX_OBJECT_SIZE {
+                return errno(EFBIG);
+            }
+            o.data = data.to_vec();
+            Ok(())
+        })
+    }
+
+    fn append(&self, object: &str, data: &[u8]) -> RadosResult<()> {
+        self.modify(object, true, |o| {
+            if (o.data.len() + data.len()) as u64 > MAX_OBJECT_SIZE {
+                return errno(EFBIG);
+            }
+            o.data.extend_from_slice(data);
+            Ok(())
+        })
+    }
+
+    fn trunc(&self, object: &str, size: u64) -> RadosResult<()> {
+        self.modify(object, true, |o| {
+            if size > MAX_OBJECT_SIZE {
+                return errno(EFBIG);
+            }
+            o.data.resize(size as usize, 0);
+            Ok(())
+        })
+    }
+
+    fn remove(&self, object: &str) -> RadosResult<()> {
+        if self.read_snap.get() != LIBRADOS_SNAP_HEAD {
+            return errno(EROFS);
+        }
+        match self.pool.borrow_mut().objects.remove(object) {
+            Some(_) => Ok(()),
+            None => errno(ENOENT),
+        }
+    }
+
+    fn stat(&self, object: &str) -> RadosResult<(u64, SystemTime)> {
+        self.with_object(object, |o| Ok((o.data.len() as u64, o.mtime)))
+    }
+
+    fn object_names(&self) -> RadosResult<Vec<String>> {
+        // Listing always shows the head, as in librados
+        Ok(self.pool.borrow().objects.keys().cloned().collect())
+    }
+
+    fn getxattr(&self, object: &str, name: &str) -> RadosResult<Vec<u8>> {
+        self.with_object(object, |o| match o.xattrs.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => errno(ENODATA),
+        })
+    }
+
+    fn setxattr(&self, object: &str, name: &str, value: &[u8]) -> RadosResult<()> {
+        self.modify(object, true, |o| {
+            o.xattrs.insert(name.to_string(), value.to_vec());
+            Ok(())
+        })
+    }
+
+    fn rmxattr(&self, object: &str, name: &str) -> RadosResult<()> {
+        self.modify(object, false, |o| match o.xattrs.remove(name) {
+            Some(_) => Ok(()),
+            None => errno(ENODATA),
+        })
+    }
+
+    fn xattrs(&self, object: &str) -> RadosResult<BTreeMap<String, Vec<u8>>> {
+        self.with_object(object, |o| Ok(o.xattrs.clone()))
+    }
+
+    fn omap_set(&self, object: &str, entries: &BTreeMap<String, Vec<u8>>) -> RadosResult<()> {
+        self.modify(object, true, |o| {
+            o.omap.extend(entries.iter().map(|(k, v)| (k.clone(), v.clone())));
+            Ok(())
+        })
+    }
+
+    fn omap_get(&self, object: &str, start_after: &str, max: u64) -> RadosResult<BTreeMap<String, Vec<u8>>> {
+        self.with_object(object, |o| {
+            Ok(o.omap
+                .iter()
+                .filter(|&(k, _)| k.as_str() > start_after)
+                .take(max as usize)
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect())
+        })
+    }
+
+    fn omap_rm_keys(&self, object: &str, keys: &[&str]) -> RadosResult<()> {
+        self.modify(object, false, |o| {
+            for key in keys {
+                o.omap.remove(*key);
+            }
+            Ok(())
+        })
+    }
+
+    fn omap_clear(&self, object: &str) -> RadosResult<()> {
+        self.modify(object, false, |o| {
+            o.omap.clear();
+            Ok(())
+        })
+    }
+
+    fn snap_create(&self, name: &str) -> RadosResult<()> {
+        let mut pool = self.pool.borrow_mut();
+        if pool.snap_id(name).is_ok() {
+            return errno(EEXIST);
+        }
+        pool.last_snap += 1;
+        let id = pool.last_snap;
+        let objects = pool.objects.clone();
+        pool.snaps.insert(id, (name.to_string(), objects));
+        Ok(())
+    }
+
+    fn snap_remove(&self, name: &str) -> RadosResult<()> {
+        let mut pool = self.pool.borrow_mut();
+        let id = pool.snap_id(name)?;
+        pool.snaps.remove(&id);
+        Ok(())
+    }
+
+    fn snap_lookup(&self, name: &str) -> RadosResult<u64> {
+        self.pool.borrow().snap_id(name)
+    }
+
+    fn snap_rollback(&self, object: &str, snap: &str) -> RadosResult<()> {
+        let mut pool = self.pool.borrow_mut();
+        let id = pool.snap_id(snap)?;
+        // An object that didn't exist yet is removed
+        match pool.snaps[&id].1.get(object).cloned() {
+            Some(old) => pool.objects.insert(object.to_string(), old),
+            None => pool.objects.remove(object),
+        };
+        Ok(())
+    }
+
+    fn snap_set_read(&self, snap_id: u64) -> RadosResult<()> {
+        self.read_snap.set(snap_id);
+        Ok(())
+    }
+}
+
+// Fail the way librados does with this errno
+fn errno<T>(code: c_int) -> RadosResult<T> {
+    Err(RadosError::new(get_error(-code)?))
+}
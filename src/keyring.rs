@@ -0,0 +1,299 @@
+//! Reading and writing cephx keyring files, the `[client.foo]` sections
+//! with a key and caps that `ceph auth export` prints and the daemons read
+//! from `/etc/ceph`.  Writing out a keyring that Ceph wrote gives back the
+//! same text, apart from comments which aren't kept.
+//!
+//! ```rust,no_run
+//! # use ceph::{CapsBuilder, CephClient};
+//! # use ceph::error::RadosError;
+//! # use ceph::keyring::{Keyring, KeyringEntry};
+//! # fn main() {
+//! #   let _ = run();
+//! # }
+//! # fn run() -> Result<(), RadosError> {
+//! let client = CephClient::new("admin", "/etc/ceph/ceph.conf")?;
+//! let caps = CapsBuilder::new().mon_profile("rbd").osd_profile("rbd", Some("volumes"));
+//! let key = client.auth_get_or_create("client.cinder", &caps)?;
+//!
+//! let mut keyring = Keyring::new();
+//! keyring.insert(KeyringEntry::new("client.cinder", &key).with_caps(&caps));
+//! keyring.save("/etc/ceph/ceph.client.cinder.keyring")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use caps_builder::CapsBuilder;
+use cmd::AuthEntity;
+use conf::parse_value;
+use error::{RadosError, RadosResult};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // As written by `ceph auth export`
+    const EXPORTED: &'static str = "[client.admin]
+\tkey = AQBqRWVbAAAAABAAw8yhNHtaP+Rj0vRn7mBYXA==
+\tcaps mds = \"allow *\"
+\tcaps mgr = \"allow *\"
+\tcaps mon = \"allow *\"
+\tcaps osd = \"allow *\"
+[client.cinder]
+\tkey = AQCbRWVbAAAAABAAMn8rO4C1bWHK1xfV8zfIZw==
+\tcaps mon = \"profile rbd\"
+\tcaps osd = \"profile rbd pool=volumes\"
+";
+
+    #[test]
+    fn it_round_trips_exported_keyrings() {
+        let keyring: Keyring = EXPORTED.parse().unwrap();
+        assert_eq!(keyring.entries().len(), 2);
+        let cinder = keyring.get("client.cinder").unwrap();
+        assert_eq!(cinder.key, "AQCbRWVbAAAAABAAMn8rO4C1bWHK1xfV8zfIZw==");
+        assert_eq!(cinder.caps["osd"], "profile rbd pool=volumes");
+        assert_eq!(keyring.to_string(), EXPORTED);
+    }
+
+    #[test]
+    fn it_parses_hand_written_keyrings() {
+        let keyring: Keyring = "# written by hand
+[ client.foo ]
+key=AQCbRWVbAAAAABAAMn8rO4C1bWHK1xfV8zfIZw==   ; trailing comment
+caps  mon = allow r
+caps osd = \"allow rw pool=\\\"odd name\\\"\"
+auid = 0
+"
+            .parse()
+            .unwrap();
+        let foo = keyring.get("client.foo").unwrap();
+        assert_eq!(foo.key, "AQCbRWVbAAAAABAAMn8rO4C1bWHK1xfV8zfIZw==");
+        assert_eq!(foo.caps["mon"], "allow r");
+        assert_eq!(foo.caps["osd"], "allow rw pool=\"odd name\"");
+        assert_eq!(foo.other, vec![("auid".to_string(), "0".to_string())]);
+        assert_eq!(
+            keyring.to_string(),
+            "[client.foo]
+\tkey = AQCbRWVbAAAAABAAMn8rO4C1bWHK1xfV8zfIZw==
+\tauid = 0
+\tcaps mon = \"allow r\"
+\tcaps osd = \"allow rw pool=\\\"odd name\\\"\"
+"
+        );
+    }
+
+    #[test]
+    fn it_rejects_broken_keyrings() {
+        assert!("key = abc\n".parse::<Keyring>().is_err());
+        assert!("[client.foo]\ncaps mon = allow r\n".parse::<Keyring>().is_err());
+        assert!("[client.foo]\nkey\n".parse::<Keyring>().is_err());
+        assert!("[client.foo\nkey = abc\n".parse::<Keyring>().is_err());
+        assert!("[client.foo]\nkey = \"abc\n".parse::<Keyring>().is_err());
+    }
+
+    #[test]
+    fn it_builds_entries_from_caps() {
+        let caps = CapsBuilder::new().mon("allow r").osd_pool("rwx", "images");
+        let entry = KeyringEntry::new("client.glance", "AQC=").with_caps(&caps);
+        assert_eq!(entry.caps["mon"], "allow r");
+        assert_eq!(entry.caps["osd"], "allow rwx pool=images");
+
+        let mut keyring = Keyring::new();
+        keyring.insert(entry);
+        keyring.insert(KeyringEntry::new("client.glance", "AQD="));
+        assert_eq!(keyring.entries().len(), 1);
+        assert!(keyring.get("client.glance").unwrap().caps.is_empty());
+        assert!(keyring.remove("client.glance").is_some());
+    }
+}
+
+/// The key and caps of one cephx entity
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyringEntry {
+    /// The entity name, e.g. `client.admin` or `osd.0`
+    pub entity: String,
+    /// The base64 encoded secret
+    pub key: String,
+    /// Daemon type to cap, e.g. `mon` to `allow r`
+    pub caps: BTreeMap<String, String>,
+    /// Any other settings in the section, like `auid` from older releases
+    pub other: Vec<(String, String)>,
+}
+
+impl KeyringEntry {
+    pub fn new(entity: &str, key: &str) -> KeyringEntry {
+        KeyringEntry {
+            entity: entity.to_string(),
+            key: key.to_string(),
+            caps: BTreeMap::new(),
+            other: Vec::new(),
+        }
+    }
+
+    /// Set the caps from a builder, the same ones given to
+    /// `auth_get_or_create`
+    pub fn with_caps(mut self, caps: &CapsBuilder) -> KeyringEntry {
+        let args = caps.as_args();
+        for pair in args.chunks(2) {
+            self.caps.insert(pair[0].clone(), pair[1].clone());
+        }
+        self
+    }
+}
+
+impl From<AuthEntity> for KeyringEntry {
+    fn from(entity: AuthEntity) -> KeyringEntry {
+        KeyringEntry {
+            entity: entity.entity,
+            key: entity.key,
+            caps: entity.caps.into_iter().collect(),
+            other: Vec::new(),
+        }
+    }
+}
+
+/// The entries of a keyring file, kept in the order they were added
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Keyring {
+    entries: Vec<KeyringEntry>,
+}
+
+impl Keyring {
+    pub fn new() -> Keyring {
+        Keyring::default()
+    }
+
+    /// Read and parse a keyring file
+    pub fn load<P: AsRef<Path>>(path: P) -> RadosResult<Keyring> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        contents.parse()
+    }
+
+    /// Write the keyring out, readable only by its owner when the file is
+    /// created
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> RadosResult<()> {
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+        file.write_all(self.to_string().as_bytes())?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[KeyringEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, entity: &str) -> Option<&KeyringEntry> {
+        self.entries.iter().find(|e| e.entity == entity)
+    }
+
+    pub fn get_mut(&mut self, entity: &str) -> Option<&mut KeyringEntry> {
+        self.entries.iter_mut().find(|e| e.entity == entity)
+    }
+
+    /// Add an entry, replacing any existing one for the same entity
+    pub fn insert(&mut self, entry: KeyringEntry) {
+        match self.entries.iter().position(|e| e.entity == entry.entity) {
+            Some(i) => self.entries[i] = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    pub fn remove(&mut self, entity: &str) -> Option<KeyringEntry> {
+        let i = self.entries.iter().position(|e| e.entity == entity)?;
+        Some(self.entries.remove(i))
+    }
+}
+
+impl FromStr for Keyring {
+    type Err = RadosError;
+
+    fn from_str(s: &str) -> RadosResult<Keyring> {
+        let mut keyring = Keyring::new();
+        let mut entry: Option<(KeyringEntry, bool)> = None;
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(RadosError::new(format!("Keyring line {}: unterminated section name", n + 1)));
+                }
+                if let Some(previous) = entry.take() {
+                    keyring.insert(finish_entry(previous)?);
+                }
+                let name = line[1..line.len() - 1].trim();
+                entry = Some((KeyringEntry::new(name, ""), false));
+                continue;
+            }
+            let (current, has_key) = match entry {
+                Some((ref mut current, ref mut has_key)) => (current, has_key),
+                None => return Err(RadosError::new(format!("Keyring line {}: setting outside of a section", n + 1))),
+            };
+            let eq = match line.find('=') {
+                Some(eq) => eq,
+                None => return Err(RadosError::new(format!("Keyring line {}: expected name = value", n + 1))),
+            };
+            // Ceph treats runs of spaces in setting names as one
+            let name = line[..eq].split_whitespace().collect::<Vec<_>>().join(" ");
+            let value = parse_value(line[eq + 1..].trim())
+                .map_err(|e| RadosError::new(format!("Keyring line {}: {}", n + 1, e)))?;
+            if name == "key" {
+                current.key = value;
+                *has_key = true;
+            } else if let Some(entity) = name.strip_prefix("caps ") {
+                current.caps.insert(entity.to_string(), value);
+            } else {
+                current.other.push((name, value));
+            }
+        }
+        if let Some(last) = entry {
+            keyring.insert(finish_entry(last)?);
+        }
+        Ok(keyring)
+    }
+}
+
+fn finish_entry((entry, has_key): (KeyringEntry, bool)) -> RadosResult<KeyringEntry> {
+    if !has_key {
+        return Err(RadosError::new(format!("Keyring entry {} has no key", entry.entity)));
+    }
+    Ok(entry)
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Only quote other settings when they would not parse back unquoted
+fn needs_quotes(value: &str) -> bool {
+    value.trim() != value || value.starts_with('"') || value.contains(&['#', ';'][..])
+}
+
+impl fmt::Display for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The same layout as Ceph's KeyRing::encode_plaintext
+        for entry in &self.entries {
+            writeln!(f, "[{}]", entry.entity)?;
+            writeln!(f, "\tkey = {}", entry.key)?;
+            for &(ref name, ref value) in &entry.other {
+                if needs_quotes(value) {
+                    writeln!(f, "\t{} = {}", name, quote(value))?;
+                } else {
+                    writeln!(f, "\t{} = {}", name, value)?;
+                }
+            }
+            for (daemon, cap) in &entry.caps {
+                writeln!(f, "\tcaps {} = {}", daemon, quote(cap))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -46,6 +46,13 @@
 //! a PR for it. Thanks!
 //!
 //! See the /examples/ceph.rs for how to use the library.
+//!
+//! The `jewel`, `luminous`, `mimic` and `nautilus` features pick the newest
+//! release the target cluster runs.  Commands that need something newer,
+//! such as the mgr commands before luminous, the config database before
+//! mimic and device health before nautilus, are compiled out.  `nautilus`
+//! is on by default; use `default-features = false` with an older release
+//! to find calls the cluster won't understand at build time.
 
 #[macro_use]
 extern crate bitflags;
@@ -71,15 +78,51 @@ pub mod admin_sockets;
 pub mod json;
 pub mod error;
 pub mod status;
+pub mod metrics;
+pub mod crush;
+pub mod monmap;
+pub mod osdmap;
+pub mod keyring;
+pub mod conf;
+pub mod buffer_pool;
+#[cfg(feature = "rbd")]
+pub mod librbd;
+#[cfg(feature = "rbd")]
+pub mod rbd;
+#[cfg(feature = "cephfs")]
+pub mod libcephfs;
+#[cfg(feature = "cephfs")]
+pub mod cephfs;
+#[cfg(feature = "striper")]
+pub mod libradosstriper;
+#[cfg(feature = "striper")]
+pub mod striper;
+#[cfg(feature = "fake")]
+pub mod fake;
 
+mod aio;
+mod backend;
+mod cancel;
+mod caps_builder;
 mod ceph_client;
+mod cluster;
+mod log_watch;
 mod ceph_version;
+mod decode;
 mod mon_command;
+mod object_backend;
 
+pub use aio::AioFuture;
+pub use backend::{dry_run, CephBackend, DryRunBackend, MockBackend};
+pub use cancel::CancellationToken;
+pub use caps_builder::CapsBuilder;
 pub use ceph_client::CephClient;
-pub use ceph_version::CephVersion;
+pub use cluster::{CommandObserver, CommandRecord, CommandTarget, Rados};
+pub use log_watch::{ClusterLogEntry, LogWatch};
+pub use ceph_version::{CephRelease, CephVersion};
 pub use mon_command::MonCommand;
-pub use cmd::{OsdOption, PoolOption};
+pub use object_backend::ObjectBackend;
+pub use cmd::{FsOption, OsdOption, PoolOption};
 
 pub type JsonData = rustc_serialize::json::Json;
 pub type JsonValue = rustc_serialize::json::Json;
@@ -0,0 +1,142 @@
+// Copyright 2017 LambdaStack All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bindings for the parts of libcephfs that the `cephfs` module wraps.  The
+//! declarations follow include/cephfs/libcephfs.h.
+
+#![allow(non_camel_case_types)]
+
+extern crate libc;
+
+use self::libc::{c_char, c_int, c_uint, c_void, dev_t, dirent, mode_t, size_t, statvfs, timespec};
+
+/// Opaque handle for a mount
+pub enum ceph_mount_info {}
+
+/// Opaque handle for an open directory
+pub enum ceph_dir_result {}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ceph_statx {
+    pub stx_mask: u32,
+    pub stx_blksize: u32,
+    pub stx_nlink: u32,
+    pub stx_uid: u32,
+    pub stx_gid: u32,
+    pub stx_mode: u16,
+    pub stx_ino: u64,
+    pub stx_size: u64,
+    pub stx_blocks: u64,
+    pub stx_dev: dev_t,
+    pub stx_rdev: dev_t,
+    pub stx_atime: timespec,
+    pub stx_ctime: timespec,
+    pub stx_mtime: timespec,
+    pub stx_btime: timespec,
+    pub stx_version: u64,
+}
+
+// Which ceph_statx fields to fill in
+pub const CEPH_STATX_BASIC_STATS: c_uint = 0x7ff;
+pub const CEPH_STATX_BTIME: c_uint = 0x800;
+pub const CEPH_STATX_VERSION: c_uint = 0x1000;
+pub const CEPH_STATX_ALL_STATS: c_uint = 0x1fff;
+
+pub const AT_SYMLINK_NOFOLLOW: c_uint = 0x100;
+pub const AT_REMOVEDIR: c_int = 0x200;
+
+// ceph_open takes the usual open(2) flags
+pub use self::flags::*;
+
+// bitflags 0.7 still expands to `try!`
+#[allow(deprecated)]
+mod flags {
+    bitflags! {
+        pub flags OpenFlags: ::libc::c_int {
+            const O_RDONLY = ::libc::O_RDONLY,
+            const O_WRONLY = ::libc::O_WRONLY,
+            const O_RDWR = ::libc::O_RDWR,
+            const O_CREAT = ::libc::O_CREAT,
+            const O_EXCL = ::libc::O_EXCL,
+            const O_TRUNC = ::libc::O_TRUNC,
+            const O_APPEND = ::libc::O_APPEND,
+            const O_DIRECTORY = ::libc::O_DIRECTORY,
+            const O_NOFOLLOW = ::libc::O_NOFOLLOW,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[link(name = "cephfs", kind="dylib")]
+extern "C" {
+    pub fn ceph_create(cmount: *mut *mut ceph_mount_info, id: *const c_char) -> c_int;
+    pub fn ceph_conf_read_file(cmount: *mut ceph_mount_info, path_list: *const c_char) -> c_int;
+    pub fn ceph_select_filesystem(cmount: *mut ceph_mount_info, fs_name: *const c_char) -> c_int;
+    pub fn ceph_mount(cmount: *mut ceph_mount_info, root: *const c_char) -> c_int;
+    pub fn ceph_is_mounted(cmount: *mut ceph_mount_info) -> c_int;
+    pub fn ceph_unmount(cmount: *mut ceph_mount_info) -> c_int;
+    pub fn ceph_release(cmount: *mut ceph_mount_info) -> c_int;
+    pub fn ceph_open(cmount: *mut ceph_mount_info, path: *const c_char, flags: c_int, mode: mode_t) -> c_int;
+    pub fn ceph_close(cmount: *mut ceph_mount_info, fd: c_int) -> c_int;
+    pub fn ceph_lseek(cmount: *mut ceph_mount_info, fd: c_int, offset: i64, whence: c_int) -> i64;
+    pub fn ceph_read(cmount: *mut ceph_mount_info, fd: c_int, buf: *mut c_char, size: i64, offset: i64) -> c_int;
+    pub fn ceph_write(cmount: *mut ceph_mount_info, fd: c_int, buf: *const c_char, size: i64, offset: i64) -> c_int;
+    pub fn ceph_mkdir(cmount: *mut ceph_mount_info, path: *const c_char, mode: mode_t) -> c_int;
+    pub fn ceph_mkdirs(cmount: *mut ceph_mount_info, path: *const c_char, mode: mode_t) -> c_int;
+    pub fn ceph_rmdir(cmount: *mut ceph_mount_info, path: *const c_char) -> c_int;
+    pub fn ceph_unlink(cmount: *mut ceph_mount_info, path: *const c_char) -> c_int;
+    pub fn ceph_rename(cmount: *mut ceph_mount_info, from: *const c_char, to: *const c_char) -> c_int;
+    pub fn ceph_opendir(cmount: *mut ceph_mount_info, name: *const c_char, dirpp: *mut *mut ceph_dir_result)
+                        -> c_int;
+    pub fn ceph_closedir(cmount: *mut ceph_mount_info, dirp: *mut ceph_dir_result) -> c_int;
+    pub fn ceph_readdir_r(cmount: *mut ceph_mount_info, dirp: *mut ceph_dir_result, de: *mut dirent) -> c_int;
+    pub fn ceph_statx(cmount: *mut ceph_mount_info, path: *const c_char, stx: *mut ceph_statx, want: c_uint,
+                      flags: c_uint)
+                      -> c_int;
+    pub fn ceph_statfs(cmount: *mut ceph_mount_info, path: *const c_char, stbuf: *mut statvfs) -> c_int;
+    pub fn ceph_getxattr(cmount: *mut ceph_mount_info, path: *const c_char, name: *const c_char, value: *mut c_void,
+                         size: size_t)
+                         -> c_int;
+    pub fn ceph_setxattr(cmount: *mut ceph_mount_info, path: *const c_char, name: *const c_char,
+                         value: *const c_void, size: size_t, flags: c_int)
+                         -> c_int;
+    pub fn ceph_listxattr(cmount: *mut ceph_mount_info, path: *const c_char, list: *mut c_char, size: size_t)
+                          -> c_int;
+    pub fn ceph_removexattr(cmount: *mut ceph_mount_info, path: *const c_char, name: *const c_char) -> c_int;
+    pub fn ceph_fgetxattr(cmount: *mut ceph_mount_info, fd: c_int, name: *const c_char, value: *mut c_void,
+                          size: size_t)
+                          -> c_int;
+    pub fn ceph_fsetxattr(cmount: *mut ceph_mount_info, fd: c_int, name: *const c_char, value: *const c_void,
+                          size: size_t, flags: c_int)
+                          -> c_int;
+    pub fn ceph_flistxattr(cmount: *mut ceph_mount_info, fd: c_int, list: *mut c_char, size: size_t) -> c_int;
+    pub fn ceph_fremovexattr(cmount: *mut ceph_mount_info, fd: c_int, name: *const c_char) -> c_int;
+    pub fn ceph_fsync(cmount: *mut ceph_mount_info, fd: c_int, syncdataonly: c_int) -> c_int;
+}
+
+// The directory relative calls only exist from Reef (v18) on
+#[cfg(all(target_os = "linux", feature = "cephfs-at"))]
+#[link(name = "cephfs", kind="dylib")]
+extern "C" {
+    pub fn ceph_dirfd(cmount: *mut ceph_mount_info, dirp: *mut ceph_dir_result) -> c_int;
+    pub fn ceph_openat(cmount: *mut ceph_mount_info, dirfd: c_int, relpath: *const c_char, flags: c_int,
+                       mode: mode_t)
+                       -> c_int;
+    pub fn ceph_mkdirat(cmount: *mut ceph_mount_info, dirfd: c_int, relpath: *const c_char, mode: mode_t) -> c_int;
+    pub fn ceph_unlinkat(cmount: *mut ceph_mount_info, dirfd: c_int, relpath: *const c_char, flags: c_int) -> c_int;
+    pub fn ceph_statxat(cmount: *mut ceph_mount_info, dirfd: c_int, relpath: *const c_char, stx: *mut ceph_statx,
+                        want: c_uint, flags: c_uint)
+                        -> c_int;
+}
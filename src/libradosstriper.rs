@@ -0,0 +1,49 @@
+// Copyright 2017 LambdaStack All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bindings for libradosstriper, which spreads one logical object over
+//! many RADOS objects.  The declarations follow
+//! include/radosstriper/libradosstriper.h.
+
+#![allow(non_camel_case_types)]
+
+extern crate libc;
+
+use self::libc::{c_char, c_int, c_uint, size_t, time_t};
+use rados::rados_ioctx_t;
+
+pub type rados_striper_t = *mut ::libc::c_void;
+
+#[cfg(target_os = "linux")]
+#[link(name = "radosstriper", kind="dylib")]
+extern "C" {
+    pub fn rados_striper_create(ioctx: rados_ioctx_t, striper: *mut rados_striper_t) -> c_int;
+    pub fn rados_striper_destroy(striper: rados_striper_t);
+    pub fn rados_striper_set_object_layout_stripe_unit(striper: rados_striper_t, stripe_unit: c_uint) -> c_int;
+    pub fn rados_striper_set_object_layout_stripe_count(striper: rados_striper_t, stripe_count: c_uint) -> c_int;
+    pub fn rados_striper_set_object_layout_object_size(striper: rados_striper_t, object_size: c_uint) -> c_int;
+    pub fn rados_striper_write(striper: rados_striper_t, soid: *const c_char, buf: *const c_char, len: size_t,
+                               off: u64)
+                               -> c_int;
+    pub fn rados_striper_write_full(striper: rados_striper_t, soid: *const c_char, buf: *const c_char, len: size_t)
+                                    -> c_int;
+    pub fn rados_striper_append(striper: rados_striper_t, soid: *const c_char, buf: *const c_char, len: size_t)
+                                -> c_int;
+    pub fn rados_striper_read(striper: rados_striper_t, soid: *const c_char, buf: *mut c_char, len: size_t, off: u64)
+                              -> c_int;
+    pub fn rados_striper_remove(striper: rados_striper_t, soid: *const c_char) -> c_int;
+    pub fn rados_striper_trunc(striper: rados_striper_t, soid: *const c_char, size: u64) -> c_int;
+    pub fn rados_striper_stat(striper: rados_striper_t, soid: *const c_char, psize: *mut u64, pmtime: *mut time_t)
+                              -> c_int;
+}
@@ -0,0 +1,176 @@
+// Copyright 2017 LambdaStack All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bindings for the parts of librbd that the `rbd` module wraps.  The
+//! declarations follow include/rbd/librbd.h.
+
+#![allow(non_camel_case_types)]
+
+extern crate libc;
+
+use self::libc::{c_char, c_int, size_t, ssize_t, time_t};
+use rados::rados_ioctx_t;
+
+pub type rbd_image_t = *mut ::libc::c_void;
+pub type rbd_completion_t = *mut ::libc::c_void;
+pub type rbd_callback_t = ::std::option::Option<extern "C" fn(cb: rbd_completion_t, arg: *mut ::libc::c_void)>;
+
+pub const RBD_MAX_BLOCK_NAME_SIZE: usize = 24;
+pub const RBD_MAX_IMAGE_NAME_SIZE: usize = 96;
+
+pub use self::flags::*;
+
+// bitflags 0.7 still expands to `try!`
+#[allow(deprecated)]
+mod flags {
+    bitflags! {
+        pub flags RbdFlags: u64 {
+            const RBD_FLAG_OBJECT_MAP_INVALID = 1,
+            const RBD_FLAG_FAST_DIFF_INVALID = 2,
+        }
+    }
+
+    bitflags! {
+        pub flags RbdFeatures: u64 {
+            const RBD_FEATURE_LAYERING = 1,
+            const RBD_FEATURE_STRIPINGV2 = 2,
+            const RBD_FEATURE_EXCLUSIVE_LOCK = 4,
+            const RBD_FEATURE_OBJECT_MAP = 8,
+            const RBD_FEATURE_FAST_DIFF = 16,
+            const RBD_FEATURE_DEEP_FLATTEN = 32,
+            const RBD_FEATURE_JOURNALING = 64,
+            const RBD_FEATURE_DATA_POOL = 128,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct rbd_image_info_t {
+    pub size: u64,
+    pub obj_size: u64,
+    pub num_objs: u64,
+    pub order: c_int,
+    pub block_name_prefix: [c_char; RBD_MAX_BLOCK_NAME_SIZE],
+    pub parent_pool: i64,
+    pub parent_name: [c_char; RBD_MAX_IMAGE_NAME_SIZE],
+}
+
+pub type rbd_mirror_mode_t = c_int;
+pub const RBD_MIRROR_MODE_DISABLED: rbd_mirror_mode_t = 0;
+pub const RBD_MIRROR_MODE_IMAGE: rbd_mirror_mode_t = 1;
+pub const RBD_MIRROR_MODE_POOL: rbd_mirror_mode_t = 2;
+
+pub type rbd_mirror_image_state_t = c_int;
+pub const RBD_MIRROR_IMAGE_DISABLING: rbd_mirror_image_state_t = 0;
+pub const RBD_MIRROR_IMAGE_ENABLED: rbd_mirror_image_state_t = 1;
+pub const RBD_MIRROR_IMAGE_DISABLED: rbd_mirror_image_state_t = 2;
+
+pub type rbd_mirror_image_status_state_t = c_int;
+pub const MIRROR_IMAGE_STATUS_STATE_UNKNOWN: rbd_mirror_image_status_state_t = 0;
+pub const MIRROR_IMAGE_STATUS_STATE_ERROR: rbd_mirror_image_status_state_t = 1;
+pub const MIRROR_IMAGE_STATUS_STATE_SYNCING: rbd_mirror_image_status_state_t = 2;
+pub const MIRROR_IMAGE_STATUS_STATE_STARTING_REPLAY: rbd_mirror_image_status_state_t = 3;
+pub const MIRROR_IMAGE_STATUS_STATE_REPLAYING: rbd_mirror_image_status_state_t = 4;
+pub const MIRROR_IMAGE_STATUS_STATE_STOPPING_REPLAY: rbd_mirror_image_status_state_t = 5;
+pub const MIRROR_IMAGE_STATUS_STATE_STOPPED: rbd_mirror_image_status_state_t = 6;
+
+pub type rbd_lock_mode_t = c_int;
+pub const RBD_LOCK_MODE_EXCLUSIVE: rbd_lock_mode_t = 0;
+pub const RBD_LOCK_MODE_SHARED: rbd_lock_mode_t = 1;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct rbd_mirror_image_info_t {
+    pub global_id: *mut c_char,
+    pub state: rbd_mirror_image_state_t,
+    pub primary: bool,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct rbd_mirror_image_status_t {
+    pub name: *mut c_char,
+    pub info: rbd_mirror_image_info_t,
+    pub state: rbd_mirror_image_status_state_t,
+    pub description: *mut c_char,
+    pub last_update: time_t,
+    pub up: bool,
+}
+
+#[cfg(target_os = "linux")]
+#[link(name = "rbd", kind="dylib")]
+extern "C" {
+    pub fn rbd_list(io: rados_ioctx_t, names: *mut c_char, size: *mut size_t) -> c_int;
+    pub fn rbd_create2(io: rados_ioctx_t, name: *const c_char, size: u64, features: u64,
+                       order: *mut c_int)
+                       -> c_int;
+    pub fn rbd_remove(io: rados_ioctx_t, name: *const c_char) -> c_int;
+    pub fn rbd_open(io: rados_ioctx_t, name: *const c_char, image: *mut rbd_image_t, snap_name: *const c_char)
+                    -> c_int;
+    pub fn rbd_close(image: rbd_image_t) -> c_int;
+    pub fn rbd_stat(image: rbd_image_t, info: *mut rbd_image_info_t, infosize: size_t) -> c_int;
+    pub fn rbd_get_features(image: rbd_image_t, features: *mut u64) -> c_int;
+    pub fn rbd_resize(image: rbd_image_t, size: u64) -> c_int;
+    pub fn rbd_read(image: rbd_image_t, ofs: u64, len: size_t, buf: *mut c_char) -> ssize_t;
+    pub fn rbd_write(image: rbd_image_t, ofs: u64, len: size_t, buf: *const c_char) -> ssize_t;
+    pub fn rbd_discard(image: rbd_image_t, ofs: u64, len: u64) -> c_int;
+    pub fn rbd_flush(image: rbd_image_t) -> c_int;
+    pub fn rbd_get_flags(image: rbd_image_t, flags: *mut u64) -> c_int;
+    pub fn rbd_update_features(image: rbd_image_t, features: u64, enabled: u8) -> c_int;
+    pub fn rbd_is_exclusive_lock_owner(image: rbd_image_t, is_owner: *mut c_int) -> c_int;
+    pub fn rbd_metadata_get(image: rbd_image_t, key: *const c_char, value: *mut c_char, val_len: *mut size_t)
+                            -> c_int;
+    pub fn rbd_metadata_set(image: rbd_image_t, key: *const c_char, value: *const c_char) -> c_int;
+    pub fn rbd_metadata_remove(image: rbd_image_t, key: *const c_char) -> c_int;
+    pub fn rbd_metadata_list(image: rbd_image_t, start: *const c_char, max: u64, keys: *mut c_char,
+                             key_len: *mut size_t, values: *mut c_char, vals_len: *mut size_t)
+                             -> c_int;
+    pub fn rbd_mirror_mode_get(io: rados_ioctx_t, mirror_mode: *mut rbd_mirror_mode_t) -> c_int;
+    pub fn rbd_mirror_mode_set(io: rados_ioctx_t, mirror_mode: rbd_mirror_mode_t) -> c_int;
+    pub fn rbd_mirror_image_enable(image: rbd_image_t) -> c_int;
+    pub fn rbd_mirror_image_disable(image: rbd_image_t, force: bool) -> c_int;
+    pub fn rbd_mirror_image_promote(image: rbd_image_t, force: bool) -> c_int;
+    pub fn rbd_mirror_image_demote(image: rbd_image_t) -> c_int;
+    pub fn rbd_mirror_image_resync(image: rbd_image_t) -> c_int;
+    pub fn rbd_mirror_image_get_status(image: rbd_image_t, mirror_image_status: *mut rbd_mirror_image_status_t,
+                                       status_size: size_t)
+                                       -> c_int;
+    pub fn rbd_aio_create_completion(cb_arg: *mut ::libc::c_void, complete_cb: rbd_callback_t,
+                                     c: *mut rbd_completion_t)
+                                     -> c_int;
+    pub fn rbd_aio_is_complete(c: rbd_completion_t) -> c_int;
+    pub fn rbd_aio_wait_for_complete(c: rbd_completion_t) -> c_int;
+    pub fn rbd_aio_get_return_value(c: rbd_completion_t) -> ssize_t;
+    pub fn rbd_aio_release(c: rbd_completion_t);
+    pub fn rbd_aio_read(image: rbd_image_t, off: u64, len: size_t, buf: *mut c_char, c: rbd_completion_t) -> c_int;
+    pub fn rbd_aio_write(image: rbd_image_t, off: u64, len: size_t, buf: *const c_char, c: rbd_completion_t)
+                         -> c_int;
+    pub fn rbd_aio_discard(image: rbd_image_t, off: u64, len: u64, c: rbd_completion_t) -> c_int;
+    pub fn rbd_aio_flush(image: rbd_image_t, c: rbd_completion_t) -> c_int;
+    pub fn rbd_diff_iterate2(image: rbd_image_t, fromsnapname: *const c_char, ofs: u64, len: u64, include_parent: u8,
+                             whole_object: u8,
+                             cb: Option<extern "C" fn(ofs: u64, len: size_t, exists: c_int, arg: *mut ::libc::c_void)
+                                                      -> c_int>,
+                             arg: *mut ::libc::c_void)
+                             -> c_int;
+    pub fn rbd_lock_acquire(image: rbd_image_t, lock_mode: rbd_lock_mode_t) -> c_int;
+    pub fn rbd_lock_release(image: rbd_image_t) -> c_int;
+    pub fn rbd_lock_get_owners(image: rbd_image_t, lock_mode: *mut rbd_lock_mode_t, lock_owners: *mut *mut c_char,
+                               max_lock_owners: *mut size_t)
+                               -> c_int;
+    pub fn rbd_lock_get_owners_cleanup(lock_owners: *mut *mut c_char, lock_owner_count: size_t);
+    pub fn rbd_lock_break(image: rbd_image_t, lock_mode: rbd_lock_mode_t, lock_owner: *const c_char) -> c_int;
+    pub fn rbd_mirror_image_status_cleanup(mirror_image_status: *mut rbd_mirror_image_status_t);
+}
@@ -0,0 +1,173 @@
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libc::{c_char, c_void, EBUSY};
+
+use ceph::get_error;
+use cluster::Rados;
+use cmd::LogLevel;
+use error::{RadosError, RadosResult};
+use rados::rados_monitor_log2;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_log_levels() {
+        assert_eq!(parse_level("[WRN]"), Some(LogLevel::Warn));
+        assert_eq!(parse_level("[ERR]"), Some(LogLevel::Error));
+        assert_eq!(parse_level("info"), Some(LogLevel::Info));
+        assert_eq!(parse_level("[SEC]"), Some(LogLevel::Sec));
+        assert_eq!(parse_level("[???]"), None);
+    }
+
+    #[test]
+    fn it_orders_log_levels() {
+        assert!(LogLevel::Debug < LogLevel::Info);
+        assert!(LogLevel::Warn < LogLevel::Error);
+        assert_eq!(LogLevel::Warn.to_string(), "warn");
+    }
+}
+
+// The monitors send "[WRN]" and friends with each entry but take the long
+// names when subscribing
+fn parse_level(level: &str) -> Option<LogLevel> {
+    let level = level.trim_start_matches('[').trim_end_matches(']');
+    match level.to_lowercase().as_str() {
+        "dbg" | "debug" => Some(LogLevel::Debug),
+        "inf" | "info" => Some(LogLevel::Info),
+        "sec" => Some(LogLevel::Sec),
+        "wrn" | "warn" => Some(LogLevel::Warn),
+        "err" | "error" => Some(LogLevel::Error),
+        _ => None,
+    }
+}
+
+/// One entry of the cluster log, as shown by `ceph -w`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClusterLogEntry {
+    /// Usually "cluster" or "audit"
+    pub channel: String,
+    /// The daemon that logged the entry, e.g. "mon.a"
+    pub name: String,
+    /// The entity and address of that daemon
+    pub who: String,
+    /// None for a level this crate doesn't know about
+    pub level: Option<LogLevel>,
+    pub message: String,
+    pub timestamp: SystemTime,
+    pub seq: u64,
+}
+
+/// A subscription to the cluster log from `Rados::watch_log`.  Entries
+/// arrive on the channel as the monitors send them; iterating blocks
+/// waiting for the next one.  Dropping this unsubscribes.  librados keeps
+/// one subscription per handle, so only one of these can be alive for a
+/// `Rados` at a time.
+pub struct LogWatch<'a> {
+    rados: &'a Rados,
+    entries: Receiver<ClusterLogEntry>,
+    sender: *mut Mutex<Sender<ClusterLogEntry>>,
+}
+
+impl<'a> fmt::Debug for LogWatch<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LogWatch").field("cluster", &self.rados.as_raw()).finish()
+    }
+}
+
+impl<'a> Drop for LogWatch<'a> {
+    fn drop(&mut self) {
+        // A null callback unsubscribes.  librados waits for a callback that
+        // is running to finish so the sender can be freed afterwards.
+        let level = CString::new("info").unwrap_or_default();
+        unsafe {
+            let ret_code = rados_monitor_log2(self.rados.as_raw(), level.as_ptr(), None, ptr::null_mut());
+            if ret_code < 0 {
+                // Leaking the sender beats freeing it under a live callback.
+                // The handle stays marked as watched since the callback is.
+                error!("Failed to unsubscribe from the cluster log: {}", ret_code);
+                return;
+            }
+            drop(Box::from_raw(self.sender));
+        }
+        self.rados.set_log_watched(false);
+    }
+}
+
+impl<'a> LogWatch<'a> {
+    pub(crate) fn new(rados: &'a Rados, level: LogLevel) -> RadosResult<LogWatch<'a>> {
+        // A second subscription would replace the callback of the first
+        if rados.log_watched() {
+            return Err(RadosError::new(get_error(-EBUSY)?));
+        }
+        let level_str = CString::new(level.to_string())?;
+        let (sender, entries) = channel();
+        let sender = Box::into_raw(Box::new(Mutex::new(sender)));
+        unsafe {
+            let ret_code =
+                rados_monitor_log2(rados.as_raw(), level_str.as_ptr(), Some(log_callback), sender as *mut c_void);
+            if ret_code < 0 {
+                drop(Box::from_raw(sender));
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        rados.set_log_watched(true);
+        Ok(LogWatch {
+            rados: rados,
+            entries: entries,
+            sender: sender,
+        })
+    }
+
+    /// The channel the entries arrive on, for `try_recv` or
+    /// `recv_timeout`
+    pub fn receiver(&self) -> &Receiver<ClusterLogEntry> {
+        &self.entries
+    }
+}
+
+impl<'a> Iterator for LogWatch<'a> {
+    type Item = ClusterLogEntry;
+    fn next(&mut self) -> Option<ClusterLogEntry> {
+        self.entries.recv().ok()
+    }
+}
+
+// Copy a string librados owns, which may be null
+unsafe fn c_string(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+extern "C" fn log_callback(arg: *mut c_void, _line: *const c_char, channel: *const c_char, who: *const c_char,
+                           name: *const c_char, sec: u64, nsec: u64, seq: u64, level: *const c_char,
+                           msg: *const c_char) {
+    // Unwinding into librados isn't allowed
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let sender = unsafe { &*(arg as *const Mutex<Sender<ClusterLogEntry>>) };
+        let entry = unsafe {
+            ClusterLogEntry {
+                channel: c_string(channel),
+                name: c_string(name),
+                who: c_string(who),
+                level: parse_level(&c_string(level)),
+                message: c_string(msg),
+                timestamp: UNIX_EPOCH + Duration::new(sec, nsec as u32),
+                seq: seq,
+            }
+        };
+        if let Ok(sender) = sender.lock() {
+            // Nobody is listening once the receiver is gone
+            let _ = sender.send(entry);
+        }
+    }));
+}
@@ -0,0 +1,313 @@
+//! Turn perf counters and cluster stats into Prometheus metrics.  Feed the
+//! output of `perf dump` on an admin socket, `osd perf` or the pg map of
+//! `ceph status` through the `*_families` functions and serve `render` of
+//! the result as /metrics.
+//!
+//! ```rust,no_run
+//! # extern crate ceph;
+//! # extern crate serde_json;
+//! # use ceph::admin_sockets::admin_socket_command;
+//! # use ceph::metrics;
+//! # use ceph::error::RadosError;
+//! # fn main() {
+//! #   let _ = run();
+//! # }
+//! # fn run() -> Result<(), RadosError> {
+//! let dump = admin_socket_command("perf dump", "/var/run/ceph/ceph-osd.0.asok")?;
+//! let families = metrics::perf_dump_families("osd.0", &serde_json::from_str(&dump)?);
+//! println!("{}", metrics::render(&families));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::fmt::Write;
+
+use serde_json::Value;
+
+use cmd::{OsdPerf, StatusPgMap};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_flattens_perf_dumps() {
+        let dump = json!({
+            "osd": {
+                "op_w": 12,
+                "op_r_latency": {"avgcount": 4, "sum": 0.5, "avgtime": 0.125},
+                "stat_bytes": 1024,
+            },
+            "throttle-msgr_dispatch_throttler-client": {"val": 0},
+        });
+        let text = render(&perf_dump_families("osd.3", &dump));
+        assert!(text.contains("# TYPE ceph_osd_op_w untyped\nceph_osd_op_w{ceph_daemon=\"osd.3\"} 12\n"));
+        assert!(text.contains("ceph_osd_op_r_latency_sum{ceph_daemon=\"osd.3\"} 0.5\n"));
+        assert!(text.contains("ceph_osd_op_r_latency_count{ceph_daemon=\"osd.3\"} 4\n"));
+        assert!(!text.contains("avgtime"));
+        assert!(text.contains("ceph_throttle_msgr_dispatch_throttler_client_val{ceph_daemon=\"osd.3\"} 0\n"));
+    }
+
+    #[test]
+    fn it_groups_families_by_name() {
+        let mut families = perf_dump_families("osd.0", &json!({"osd": {"op_w": 1}}));
+        families.extend(perf_dump_families("osd.1", &json!({"osd": {"op_w": 2}})));
+        let text = render(&families);
+        assert_eq!(text.matches("# TYPE ceph_osd_op_w").count(), 1);
+        assert!(text.contains("ceph_osd_op_w{ceph_daemon=\"osd.0\"} 1\nceph_osd_op_w{ceph_daemon=\"osd.1\"} 2\n"));
+    }
+
+    #[test]
+    fn it_escapes_label_values() {
+        let family = MetricFamily {
+            name: "ceph_test".to_string(),
+            help: "A \"test\"\nmetric".to_string(),
+            metric_type: MetricType::Gauge,
+            metrics: vec![Metric::new(vec![("path", "C:\\ \"x\"".to_string())], 1.0)],
+        };
+        assert_eq!(
+            render(&[family]),
+            "# HELP ceph_test A \"test\"\\nmetric\n# TYPE ceph_test gauge\nceph_test{path=\"C:\\\\ \\\"x\\\"\"} 1\n"
+        );
+    }
+
+    #[test]
+    fn it_formats_non_finite_values() {
+        let family = MetricFamily {
+            name: "ceph_test".to_string(),
+            help: "test".to_string(),
+            metric_type: MetricType::Gauge,
+            metrics: vec![
+                Metric::new(vec![("v", "a".to_string())], f64::INFINITY),
+                Metric::new(vec![("v", "b".to_string())], f64::NEG_INFINITY),
+                Metric::new(vec![("v", "c".to_string())], f64::NAN),
+                Metric::new(vec![("v", "d".to_string())], 0.5),
+            ],
+        };
+        assert!(render(&[family]).ends_with(
+            "ceph_test{v=\"a\"} +Inf\nceph_test{v=\"b\"} -Inf\nceph_test{v=\"c\"} NaN\nceph_test{v=\"d\"} 0.5\n"
+        ));
+    }
+
+    #[test]
+    fn it_converts_osd_perf() {
+        let perf: OsdPerf = serde_json::from_value(json!({
+            "osd_perf_infos": [{"id": 2, "perf_stats": {"commit_latency_ms": 5, "apply_latency_ms": 7}}],
+        })).unwrap();
+        let text = render(&osd_perf_families(&perf));
+        assert!(text.contains("ceph_osd_commit_latency_ms{ceph_daemon=\"osd.2\"} 5\n"));
+        assert!(text.contains("ceph_osd_apply_latency_ms{ceph_daemon=\"osd.2\"} 7\n"));
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    /// For values whose kind isn't known, like most perf dump counters
+    Untyped,
+}
+
+impl fmt::Display for MetricType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MetricType::Counter => write!(f, "counter"),
+            MetricType::Gauge => write!(f, "gauge"),
+            MetricType::Untyped => write!(f, "untyped"),
+        }
+    }
+}
+
+/// One sample of a family
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metric {
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+impl Metric {
+    pub fn new(labels: Vec<(&str, String)>, value: f64) -> Metric {
+        Metric {
+            labels: labels.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+            value: value,
+        }
+    }
+}
+
+/// The samples that share a metric name
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricFamily {
+    pub name: String,
+    pub help: String,
+    pub metric_type: MetricType,
+    pub metrics: Vec<Metric>,
+}
+
+impl MetricFamily {
+    fn single(name: &str, help: &str, metric_type: MetricType, metric: Metric) -> MetricFamily {
+        MetricFamily {
+            name: name.to_string(),
+            help: help.to_string(),
+            metric_type: metric_type,
+            metrics: vec![metric],
+        }
+    }
+}
+
+/// Render families in the Prometheus text exposition format.  Families
+/// with the same name, e.g. the same counter from several daemons, are
+/// written out as one, with the help and type of the first.
+pub fn render(families: &[MetricFamily]) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for family in families {
+        if !names.contains(&family.name.as_str()) {
+            names.push(&family.name);
+        }
+    }
+    let mut out = String::new();
+    for name in names {
+        let mut same_name = families.iter().filter(|f| f.name == name);
+        let first = match same_name.next() {
+            Some(family) => family,
+            None => continue,
+        };
+        // Writing to a String can't fail
+        let _ = writeln!(out, "# HELP {} {}", name, first.help.replace('\\', "\\\\").replace('\n', "\\n"));
+        let _ = writeln!(out, "# TYPE {} {}", name, first.metric_type);
+        for family in Some(first).into_iter().chain(same_name) {
+            for metric in &family.metrics {
+                out.push_str(name);
+                if !metric.labels.is_empty() {
+                    let labels: Vec<String> = metric
+                        .labels
+                        .iter()
+                        .map(|&(ref label, ref value)| format!("{}=\"{}\"", label, escape_label(value)))
+                        .collect();
+                    let _ = write!(out, "{{{}}}", labels.join(","));
+                }
+                let _ = writeln!(out, " {}", format_value(metric.value));
+            }
+        }
+    }
+    out
+}
+
+/// The counters of an admin socket `perf dump`, labelled with the daemon
+/// they came from, e.g. "osd.0".  A counter named `op_w` in the `osd`
+/// section becomes `ceph_osd_op_w`.  Averages become a `_sum` and a
+/// `_count`, like a Prometheus summary.
+pub fn perf_dump_families(daemon: &str, dump: &Value) -> Vec<MetricFamily> {
+    let mut families = Vec::new();
+    let sections = match dump.as_object() {
+        Some(sections) => sections,
+        None => return families,
+    };
+    for (section, counters) in sections {
+        let counters = match counters.as_object() {
+            Some(counters) => counters,
+            None => continue,
+        };
+        for (counter, value) in counters {
+            let name = metric_name(&format!("ceph_{}_{}", section, counter));
+            let help = format!("{} {}", section, counter);
+            let labels = vec![("ceph_daemon", daemon.to_string())];
+            if let Some(value) = value.as_f64() {
+                families.push(MetricFamily::single(&name, &help, MetricType::Untyped, Metric::new(labels, value)));
+            } else if let (Some(sum), Some(count)) = (
+                value.get("sum").and_then(Value::as_f64),
+                value.get("avgcount").and_then(Value::as_f64),
+            ) {
+                families.push(MetricFamily::single(
+                    &format!("{}_sum", name),
+                    &help,
+                    MetricType::Counter,
+                    Metric::new(labels.clone(), sum),
+                ));
+                families.push(MetricFamily::single(
+                    &format!("{}_count", name),
+                    &help,
+                    MetricType::Counter,
+                    Metric::new(labels, count),
+                ));
+            }
+        }
+    }
+    families
+}
+
+/// The commit and apply latencies from `osd perf`
+pub fn osd_perf_families(perf: &OsdPerf) -> Vec<MetricFamily> {
+    let mut commit = Vec::new();
+    let mut apply = Vec::new();
+    for info in &perf.osd_perf_infos {
+        let labels = vec![("ceph_daemon", format!("osd.{}", info.id))];
+        commit.push(Metric::new(labels.clone(), info.perf_stats.commit_latency_ms as f64));
+        apply.push(Metric::new(labels, info.perf_stats.apply_latency_ms as f64));
+    }
+    vec![
+        MetricFamily {
+            name: "ceph_osd_commit_latency_ms".to_string(),
+            help: "OSD commit latency in milliseconds".to_string(),
+            metric_type: MetricType::Gauge,
+            metrics: commit,
+        },
+        MetricFamily {
+            name: "ceph_osd_apply_latency_ms".to_string(),
+            help: "OSD apply latency in milliseconds".to_string(),
+            metric_type: MetricType::Gauge,
+            metrics: apply,
+        },
+    ]
+}
+
+/// The pg counts by state and the usage totals from the pg map of
+/// `ceph status`
+pub fn pg_stat_families(pgmap: &StatusPgMap) -> Vec<MetricFamily> {
+    let by_state = pgmap
+        .pgs_by_state
+        .iter()
+        .map(|state| Metric::new(vec![("state", state.state_name.clone())], state.count as f64))
+        .collect();
+    let gauge = |name: &str, help: &str, value: u64| {
+        MetricFamily::single(name, help, MetricType::Gauge, Metric::new(Vec::new(), value as f64))
+    };
+    vec![
+        MetricFamily {
+            name: "ceph_pg_state".to_string(),
+            help: "Number of placement groups in each combination of states".to_string(),
+            metric_type: MetricType::Gauge,
+            metrics: by_state,
+        },
+        gauge("ceph_pg_total", "Number of placement groups", pgmap.num_pgs),
+        gauge("ceph_pg_data_bytes", "Bytes of data stored", pgmap.data_bytes),
+        gauge("ceph_cluster_used_bytes", "Raw bytes used", pgmap.bytes_used),
+        gauge("ceph_cluster_available_bytes", "Raw bytes available", pgmap.bytes_avail),
+        gauge("ceph_cluster_total_bytes", "Raw capacity in bytes", pgmap.bytes_total),
+    ]
+}
+
+// Prometheus names only allow [a-zA-Z0-9_:], perf counter sections use
+// dashes and dots too
+fn metric_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == ':' { c } else { '_' })
+        .collect()
+}
+
+// Rust writes these as inf and NaN, Prometheus wants +Inf, -Inf and NaN
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() && value > 0.0 {
+        "+Inf".to_string()
+    } else if value.is_infinite() {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -1,15 +1,14 @@
-use std::collections::HashMap;
-
-use serde_json;
+use serde::Serialize;
+use serde_json::{self, Map, Value};
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::{BTreeMap, HashMap};
 
     #[test]
     fn it_builds_a_mon_command() {
-        let command = MonCommand::new().
-            with_prefix("osd set").
+        let command = MonCommand::new("osd set").
             with("key", "osdout");
 
         let actual: HashMap<String, String> = serde_json::from_str(&command.as_json()).unwrap();
@@ -17,40 +16,113 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn it_keeps_argument_types() {
+        let command = MonCommand::new("osd pool create")
+            .arg("pool", "rbd")
+            .arg("pg_num", 128)
+            .arg("ids", vec!["1", "2"]);
+
+        assert_eq!(
+            command.as_value(),
+            json!({"prefix": "osd pool create", "format": "json", "pool": "rbd", "pg_num": 128, "ids": ["1", "2"]})
+        );
+        assert!(command.error().is_none());
+    }
+
+    #[test]
+    fn it_keeps_argument_errors() {
+        let mut weights = BTreeMap::new();
+        weights.insert((1, 2), 0.5);
+        let command = MonCommand::new("osd reweightn").arg("weights", weights).arg("pool", "rbd");
+
+        assert!(command.error().unwrap().contains("weights"));
+        assert_eq!(command.as_value()["pool"], json!("rbd"));
+    }
 }
 
-pub struct MonCommand<'a> {
-    map: HashMap<&'a str, &'a str>,
+/// Builds the json for any mon command.  This covers the commands that
+/// don't have a wrapper yet, the argument names are the ones listed in
+/// https://github.com/ceph/ceph/blob/master/src/mon/MonCommands.h
+///
+/// ```rust,no_run
+/// # use ceph::{CephClient, MonCommand};
+/// # use ceph::error::RadosError;
+/// # fn main() {
+/// #   let _ = run();
+/// # }
+/// # fn run() -> Result<(), RadosError> {
+/// let client = CephClient::new("admin", "/etc/ceph/ceph.conf")?;
+/// let cmd = MonCommand::new("osd pool set")
+///     .arg("pool", "rbd")
+///     .arg("var", "size")
+///     .arg("val", "3");
+/// client.run_command(cmd)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct MonCommand {
+    map: Map<String, Value>,
+    // Why the first argument that couldn't be turned into json was left out
+    error: Option<String>,
 }
 
-impl<'a> MonCommand<'a> {
-    pub fn new() -> MonCommand<'a> {
+impl MonCommand {
+    pub fn new(prefix: &str) -> MonCommand {
         MonCommand {
-            map: {
-                let mut map = HashMap::new();
-                map.insert("format", "json");
-                map
-            }
+            map: Map::new(),
+            error: None,
         }
+            .with_prefix(prefix)
+            .with_format("json")
     }
 
-    pub fn with_format(self, format: &'a str) -> MonCommand<'a> {
+    pub fn with_format(self, format: &str) -> MonCommand {
         self.with("format", format)
     }
 
-    pub fn with_name(self, name: &'a str) -> MonCommand<'a> {
+    pub fn with_name(self, name: &str) -> MonCommand {
         self.with("name", name)
     }
 
-    pub fn with_prefix(self, prefix: &'a str) -> MonCommand<'a> {
+    pub fn with_prefix(self, prefix: &str) -> MonCommand {
         self.with("prefix", prefix)
     }
 
-    pub fn with(mut self, name: &'a str, value: &'a str) -> MonCommand<'a> {
-        self.map.insert(name, value);
+    pub fn with(self, name: &str, value: &str) -> MonCommand {
+        self.arg(name, value)
+    }
+
+    /// Add an argument of any type.  Use this for the integer, bool and
+    /// list arguments that the mon won't accept as strings.  A value that
+    /// can't be turned into json, like a map with non-string keys, is left
+    /// out and `CephClient::run_command` fails instead of sending the
+    /// command.
+    pub fn arg<T: Serialize>(mut self, name: &str, value: T) -> MonCommand {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.map.insert(name.to_string(), value);
+            },
+            Err(e) => {
+                if self.error.is_none() {
+                    self.error = Some(format!("Mon command argument {} is not valid json: {}", name, e));
+                }
+            },
+        }
         self
     }
 
+    /// Why an argument was left out, if one was
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn as_value(&self) -> Value {
+        Value::Object(self.map.clone())
+    }
+
     pub fn as_json(&self) -> String {
         serde_json::to_string(&self.map).unwrap()
     }
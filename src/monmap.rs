@@ -0,0 +1,410 @@
+//! Decode the binary monmap that `mon getmap` returns or `monmaptool`
+//! writes, for tooling that bootstraps from map files.
+//!
+//! ```rust,no_run
+//! # use ceph::Rados;
+//! # use ceph::cmd::mon_getmap;
+//! # use ceph::monmap::MonMap;
+//! # use ceph::error::RadosError;
+//! # fn main() {
+//! #   let _ = run();
+//! # }
+//! # fn run() -> Result<(), RadosError> {
+//! let rados = Rados::connect("admin", "/etc/ceph/ceph.conf")?;
+//! let monmap = MonMap::decode(&mon_getmap(&rados, None)?)?;
+//! for mon in &monmap.mons {
+//!     let addrs: Vec<String> = mon.addrs.iter().map(|a| a.to_string()).collect();
+//!     println!("mon.{} [{}]", mon.name, addrs.join(","));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::time::SystemTime;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use uuid::Uuid;
+
+use decode::Decoder;
+use error::{RadosError, RadosResult};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use decode::encode::{addr_v4, end_struct, start_struct, string};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn nautilus_monmap() -> Vec<u8> {
+        let mut buf = Vec::new();
+        let map = start_struct(&mut buf, 9, 6);
+        buf.extend_from_slice(&[0x11; 16]);
+        buf.write_u32::<LittleEndian>(3).unwrap();
+        // last_changed and created
+        buf.write_u32::<LittleEndian>(1600000000).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(1500000000).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        for features in &[0x3f, 0] {
+            let start = start_struct(&mut buf, 1, 1);
+            buf.write_u64::<LittleEndian>(*features).unwrap();
+            end_struct(&mut buf, start);
+        }
+        // mon_info
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        for &(name, ip) in &[("a", [10, 0, 0, 1]), ("b", [10, 0, 0, 2])] {
+            string(&mut buf, name);
+            let info = start_struct(&mut buf, 5, 1);
+            string(&mut buf, name);
+            buf.push(2);
+            buf.write_u32::<LittleEndian>(2).unwrap();
+            addr_v4(&mut buf, 2, ip, 3300);
+            addr_v4(&mut buf, 1, ip, 6789);
+            buf.write_u16::<LittleEndian>(0).unwrap();
+            buf.write_u16::<LittleEndian>(0).unwrap();
+            buf.write_u32::<LittleEndian>(0).unwrap();
+            end_struct(&mut buf, info);
+        }
+        // ranks
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        string(&mut buf, "b");
+        string(&mut buf, "a");
+        buf.push(15);
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.push(1);
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.push(0);
+        string(&mut buf, "");
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        end_struct(&mut buf, map);
+        buf
+    }
+
+    #[test]
+    fn it_decodes_a_monmap() {
+        let monmap = MonMap::decode(&nautilus_monmap()).unwrap();
+        assert_eq!(monmap.fsid.to_string(), "11111111-1111-1111-1111-111111111111");
+        assert_eq!(monmap.epoch, 3);
+        assert_eq!(monmap.last_changed, UNIX_EPOCH + Duration::from_secs(1600000000));
+        assert_eq!(monmap.persistent_features, 0x3f);
+        assert_eq!(monmap.min_mon_release, 15);
+        let names: Vec<&str> = monmap.mons.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "a"]);
+        assert_eq!(monmap.mons[1].rank, 1);
+        assert_eq!(monmap.mons[1].addrs[0].to_string(), "v2:10.0.0.1:3300/0");
+        assert_eq!(monmap.mons[1].addrs[1].to_string(), "v1:10.0.0.1:6789/0");
+        assert_eq!(monmap.mons[1].public_addr(AddrType::Legacy), Some("10.0.0.1:6789".parse().unwrap()));
+    }
+
+    #[test]
+    fn it_decodes_legacy_addrs() {
+        let mut buf = vec![0, 0, 0, 0];
+        buf.write_u32::<LittleEndian>(7).unwrap();
+        let mut ss = [0u8; 128];
+        BigEndian::write_u16(&mut ss[0..2], 2);
+        BigEndian::write_u16(&mut ss[2..4], 6789);
+        ss[4..8].copy_from_slice(&[192, 168, 0, 1]);
+        buf.extend_from_slice(&ss);
+        let addrs = decode_addrvec(&mut Decoder::new(&buf)).unwrap();
+        assert_eq!(addrs.len(), 1);
+        assert_eq!(addrs[0].to_string(), "v1:192.168.0.1:6789/7");
+    }
+
+    #[test]
+    fn it_rejects_truncated_maps() {
+        let map = nautilus_monmap();
+        assert!(MonMap::decode(&map[..map.len() - 10]).is_err());
+    }
+}
+
+const AF_INET: u16 = 2;
+const AF_INET6: u16 = 10;
+
+/// Which messenger protocol an address speaks
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AddrType {
+    None,
+    /// The v1 protocol, usually port 6789 on the monitors
+    Legacy,
+    /// The v2 protocol added in nautilus, usually port 3300 on the
+    /// monitors
+    Msgr2,
+    Any,
+    Other(u32),
+}
+
+impl AddrType {
+    fn from_u32(addr_type: u32) -> AddrType {
+        match addr_type {
+            0 => AddrType::None,
+            1 => AddrType::Legacy,
+            2 => AddrType::Msgr2,
+            3 => AddrType::Any,
+            other => AddrType::Other(other),
+        }
+    }
+}
+
+/// The address of a daemon, an `entity_addr_t` in Ceph
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EntityAddr {
+    pub addr_type: AddrType,
+    /// Tells apart instances of a daemon that reuse an address
+    pub nonce: u32,
+    /// None for an unset address
+    pub addr: Option<SocketAddr>,
+}
+
+/// Formats the way Ceph prints addresses, e.g. "v2:10.0.0.1:3300/0"
+impl fmt::Display for EntityAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.addr_type {
+            AddrType::None => write!(f, "-")?,
+            AddrType::Legacy => write!(f, "v1:")?,
+            AddrType::Msgr2 => write!(f, "v2:")?,
+            AddrType::Any => write!(f, "any:")?,
+            AddrType::Other(other) => write!(f, "{}:", other)?,
+        }
+        match self.addr {
+            Some(addr) => write!(f, "{}/{}", addr, self.nonce),
+            None => write!(f, "/{}", self.nonce),
+        }
+    }
+}
+
+/// One monitor of the map
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MonInfo {
+    pub name: String,
+    pub rank: usize,
+    /// The v2 and v1 addresses since nautilus, a single v1 one before
+    pub addrs: Vec<EntityAddr>,
+    pub priority: u16,
+    pub weight: u16,
+    /// Used by stretch clusters
+    pub crush_location: BTreeMap<String, String>,
+}
+
+impl MonInfo {
+    /// The socket address for one protocol, if the monitor listens on it
+    pub fn public_addr(&self, addr_type: AddrType) -> Option<SocketAddr> {
+        self.addrs.iter().find(|a| a.addr_type == addr_type).and_then(|a| a.addr)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MonMap {
+    pub fsid: Uuid,
+    pub epoch: u32,
+    pub last_changed: SystemTime,
+    pub created: SystemTime,
+    pub persistent_features: u64,
+    pub optional_features: u64,
+    /// In rank order
+    pub mons: Vec<MonInfo>,
+    /// The release number, e.g. 14 for nautilus.  Zero when the map
+    /// predates it.
+    pub min_mon_release: u8,
+    pub removed_ranks: Vec<i32>,
+    /// 1 classic, 2 disallow, 3 connectivity
+    pub election_strategy: u8,
+    pub disallowed_leaders: Vec<String>,
+    pub stretch_mode_enabled: bool,
+    pub tiebreaker_mon: String,
+    pub stretch_marked_down_mons: Vec<String>,
+}
+
+impl MonMap {
+    pub fn decode(buf: &[u8]) -> RadosResult<MonMap> {
+        let mut d = Decoder::new(buf);
+        let header = d.start("monmap", 9)?;
+        if header.version < 3 {
+            return Err(RadosError::new(format!("monmap version {} is too old to decode", header.version)));
+        }
+        let fsid = d.uuid()?;
+        let epoch = d.u32()?;
+        // Before mimic the addresses came in a map of their own
+        let mut legacy_addrs = BTreeMap::new();
+        if header.version < 6 {
+            legacy_addrs = d.map(Decoder::string, decode_addrvec)?;
+        }
+        let last_changed = d.utime()?;
+        let created = d.utime()?;
+        let mut persistent_features = 0;
+        let mut optional_features = 0;
+        if header.version >= 4 {
+            persistent_features = decode_mon_features(&mut d)?;
+            optional_features = decode_mon_features(&mut d)?;
+        }
+        let mut mon_info = BTreeMap::new();
+        if header.version >= 5 {
+            mon_info = d.map(Decoder::string, decode_mon_info)?;
+        }
+        for (name, addrs) in legacy_addrs {
+            mon_info.entry(name.clone()).or_insert(MonInfo {
+                name: name,
+                rank: 0,
+                addrs: addrs,
+                priority: 0,
+                weight: 0,
+                crush_location: BTreeMap::new(),
+            });
+        }
+        let ranks = if header.version >= 6 {
+            d.list(Decoder::string)?
+        } else {
+            legacy_ranks(&mon_info)
+        };
+        let mut monmap = MonMap {
+            fsid: fsid,
+            epoch: epoch,
+            last_changed: last_changed,
+            created: created,
+            persistent_features: persistent_features,
+            optional_features: optional_features,
+            mons: Vec::new(),
+            min_mon_release: 0,
+            removed_ranks: Vec::new(),
+            election_strategy: 1,
+            disallowed_leaders: Vec::new(),
+            stretch_mode_enabled: false,
+            tiebreaker_mon: String::new(),
+            stretch_marked_down_mons: Vec::new(),
+        };
+        for (rank, name) in ranks.iter().enumerate() {
+            let mut mon = mon_info
+                .remove(name)
+                .ok_or_else(|| RadosError::new(format!("monmap ranks list unknown mon {}", name)))?;
+            mon.rank = rank;
+            monmap.mons.push(mon);
+        }
+        if header.version >= 7 {
+            monmap.min_mon_release = d.u8()?;
+        }
+        if header.version >= 8 {
+            monmap.removed_ranks = d.list(Decoder::i32)?;
+            monmap.election_strategy = d.u8()?;
+            monmap.disallowed_leaders = d.list(Decoder::string)?;
+        }
+        if header.version >= 9 {
+            monmap.stretch_mode_enabled = d.bool()?;
+            monmap.tiebreaker_mon = d.string()?;
+            monmap.stretch_marked_down_mons = d.list(Decoder::string)?;
+        }
+        d.finish(header)?;
+        Ok(monmap)
+    }
+}
+
+// Old maps don't list the ranks, they follow the order of the addresses
+fn legacy_ranks(mon_info: &BTreeMap<String, MonInfo>) -> Vec<String> {
+    let mut by_addr: Vec<(&String, Option<&EntityAddr>)> =
+        mon_info.iter().map(|(name, info)| (name, info.addrs.first())).collect();
+    by_addr.sort_by_key(|&(_, addr)| addr.map(|a| (a.nonce, a.addr.map(|s| s.port()), a.addr.map(|s| s.ip()))));
+    by_addr.into_iter().map(|(name, _)| name.clone()).collect()
+}
+
+fn decode_mon_features(d: &mut Decoder) -> RadosResult<u64> {
+    let header = d.start("mon_feature_t", 1)?;
+    let features = d.u64()?;
+    d.finish(header)?;
+    Ok(features)
+}
+
+fn decode_mon_info(d: &mut Decoder) -> RadosResult<MonInfo> {
+    let header = d.start("mon_info_t", 5)?;
+    let mut info = MonInfo {
+        name: d.string()?,
+        rank: 0,
+        addrs: decode_addrvec(d)?,
+        priority: 0,
+        weight: 0,
+        crush_location: BTreeMap::new(),
+    };
+    if header.version >= 2 {
+        info.priority = d.u16()?;
+    }
+    if header.version >= 4 {
+        info.weight = d.u16()?;
+    }
+    if header.version >= 5 {
+        info.crush_location = d.map(Decoder::string, Decoder::string)?;
+    }
+    d.finish(header)?;
+    Ok(info)
+}
+
+/// An `entity_addrvec_t`, which may also hold a single old style address
+pub(crate) fn decode_addrvec(d: &mut Decoder) -> RadosResult<Vec<EntityAddr>> {
+    match d.u8()? {
+        2 => d.list(decode_addr),
+        marker => Ok(vec![decode_addr_after_marker(d, marker)?]),
+    }
+}
+
+/// An `entity_addr_t`
+pub(crate) fn decode_addr(d: &mut Decoder) -> RadosResult<EntityAddr> {
+    let marker = d.u8()?;
+    decode_addr_after_marker(d, marker)
+}
+
+fn decode_addr_after_marker(d: &mut Decoder, marker: u8) -> RadosResult<EntityAddr> {
+    match marker {
+        0 => {
+            // The pre-nautilus encoding: the rest of a zero u32, the nonce
+            // and a whole sockaddr_storage with the family in network order
+            d.bytes(3)?;
+            let nonce = d.u32()?;
+            let storage = d.bytes(128)?;
+            let addr = sockaddr(BigEndian::read_u16(&storage[0..2]), &storage[2..])?;
+            Ok(EntityAddr {
+                addr_type: if addr.is_some() { AddrType::Legacy } else { AddrType::None },
+                nonce: nonce,
+                addr: addr,
+            })
+        },
+        1 => {
+            let header = d.start("entity_addr_t", 1)?;
+            let addr_type = AddrType::from_u32(d.u32()?);
+            let nonce = d.u32()?;
+            let len = d.u32()? as usize;
+            let mut addr = None;
+            if len >= 2 {
+                let family = d.u16()?;
+                addr = sockaddr(family, d.bytes(len - 2)?)?;
+            }
+            d.finish(header)?;
+            Ok(EntityAddr {
+                addr_type: addr_type,
+                nonce: nonce,
+                addr: addr,
+            })
+        },
+        other => Err(RadosError::new(format!("Unknown address encoding {}", other))),
+    }
+}
+
+// The part of a sockaddr_in or sockaddr_in6 after the family.  The port and
+// address are in network order.
+fn sockaddr(family: u16, data: &[u8]) -> RadosResult<Option<SocketAddr>> {
+    match family {
+        AF_INET if data.len() >= 6 => {
+            let port = BigEndian::read_u16(&data[0..2]);
+            let ip = Ipv4Addr::new(data[2], data[3], data[4], data[5]);
+            Ok(Some(SocketAddr::V4(SocketAddrV4::new(ip, port))))
+        },
+        AF_INET6 if data.len() >= 26 => {
+            let port = BigEndian::read_u16(&data[0..2]);
+            let flowinfo = BigEndian::read_u32(&data[2..6]);
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&data[6..22]);
+            let scope_id = LittleEndian::read_u32(&data[22..26]);
+            Ok(Some(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(octets), port, flowinfo, scope_id))))
+        },
+        0 => Ok(None),
+        _ => Err(RadosError::new(format!("Unsupported address family {} of length {}", family, data.len()))),
+    }
+}
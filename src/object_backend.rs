@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::time::SystemTime;
+
+use libc::{c_char, c_int, size_t, ERANGE};
+
+use cancel::CancellationToken;
+use ceph::{self, get_error, IoCtx};
+use error::{RadosError, RadosResult};
+use rados::*;
+
+/// The object calls of an io context.  `IoCtx` sends them to the cluster
+/// while `fake::FakeIoCtx`, built with the `fake` feature, keeps a pool in
+/// memory so code written against this trait can be tested without one.
+pub trait ObjectBackend {
+    /// Read up to `len` bytes starting at `offset`.  Less comes back when
+    /// the object ends first.
+    fn read(&self, object: &str, len: usize, offset: u64) -> RadosResult<Vec<u8>>;
+
+    /// Write at an offset, creating the object if needed
+    fn write(&self, object: &str, data: &[u8], offset: u64) -> RadosResult<()>;
+
+    /// Replace the whole object
+    fn write_full(&self, object: &str, data: &[u8]) -> RadosResult<()>;
+
+    fn append(&self, object: &str, data: &[u8]) -> RadosResult<()>;
+
+    /// Grow the object with zeros or cut it down to `size`
+    fn trunc(&self, object: &str, size: u64) -> RadosResult<()>;
+
+    fn remove(&self, object: &str) -> RadosResult<()>;
+
+    /// The size and modification time of an object
+    fn stat(&self, object: &str) -> RadosResult<(u64, SystemTime)>;
+
+    /// The names of the objects in the pool, in no particular order
+    fn object_names(&self) -> RadosResult<Vec<String>>;
+
+    fn getxattr(&self, object: &str, name: &str) -> RadosResult<Vec<u8>>;
+
+    fn setxattr(&self, object: &str, name: &str, value: &[u8]) -> RadosResult<()>;
+
+    fn rmxattr(&self, object: &str, name: &str) -> RadosResult<()>;
+
+    fn xattrs(&self, object: &str) -> RadosResult<BTreeMap<String, Vec<u8>>>;
+
+    /// Set omap keys, leaving the other keys alone
+    fn omap_set(&self, object: &str, entries: &BTreeMap<String, Vec<u8>>) -> RadosResult<()>;
+
+    /// Up to `max` omap entries with keys after `start_after`, or from the
+    /// first key when it is empty
+    fn omap_get(&self, object: &str, start_after: &str, max: u64) -> RadosResult<BTreeMap<String, Vec<u8>>>;
+
+    fn omap_rm_keys(&self, object: &str, keys: &[&str]) -> RadosResult<()>;
+
+    fn omap_clear(&self, object: &str) -> RadosResult<()>;
+
+    /// Take a snapshot of the whole pool
+    fn snap_create(&self, name: &str) -> RadosResult<()>;
+
+    fn snap_remove(&self, name: &str) -> RadosResult<()>;
+
+    /// The id of a pool snapshot
+    fn snap_lookup(&self, name: &str) -> RadosResult<u64>;
+
+    /// Put an object back the way it was in a pool snapshot
+    fn snap_rollback(&self, object: &str, snap: &str) -> RadosResult<()>;
+
+    /// Read from a snapshot from now on, or from the current data again
+    /// with `LIBRADOS_SNAP_HEAD`.  Writes fail while reading a snapshot.
+    fn snap_set_read(&self, snap_id: u64) -> RadosResult<()>;
+}
+
+impl<'a> ObjectBackend for IoCtx<'a> {
+    fn read(&self, object: &str, len: usize, offset: u64) -> RadosResult<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(len);
+        if len > 0 {
+            ceph::rados_object_read(self.as_raw(), object, &mut buffer, offset)?;
+        }
+        Ok(buffer)
+    }
+
+    fn write(&self, object: &str, data: &[u8], offset: u64) -> RadosResult<()> {
+        ceph::rados_object_write(self.as_raw(), object, data, offset)
+    }
+
+    fn write_full(&self, object: &str, data: &[u8]) -> RadosResult<()> {
+        ceph::rados_object_write_full(self.as_raw(), object, data)
+    }
+
+    fn append(&self, object: &str, data: &[u8]) -> RadosResult<()> {
+        ceph::rados_object_append(self.as_raw(), object, data)
+    }
+
+    fn trunc(&self, object: &str, size: u64) -> RadosResult<()> {
+        ceph::rados_object_trunc(self.as_raw(), object, size)
+    }
+
+    fn remove(&self, object: &str) -> RadosResult<()> {
+        ceph::rados_object_remove(self.as_raw(), object)
+    }
+
+    fn stat(&self, object: &str) -> RadosResult<(u64, SystemTime)> {
+        ceph::rados_object_stat(self.as_raw(), object)
+    }
+
+    fn object_names(&self) -> RadosResult<Vec<String>> {
+        self.objects(&CancellationToken::new())?.map(|o| o.map(|o| o.name)).collect()
+    }
+
+    fn getxattr(&self, object: &str, name: &str) -> RadosResult<Vec<u8>> {
+        let object_str = CString::new(object)?;
+        let name_str = CString::new(name)?;
+        // Grow the buffer until the value fits
+        let mut buffer = vec![0u8; 4096];
+        loop {
+            let ret_code = unsafe {
+                rados_getxattr(
+                    self.as_raw(),
+                    object_str.as_ptr(),
+                    name_str.as_ptr(),
+                    buffer.as_mut_ptr() as *mut c_char,
+                    buffer.len(),
+                )
+            };
+            if ret_code == -ERANGE {
+                let len = buffer.len() * 2;
+                buffer.resize(len, 0);
+                continue;
+            }
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            buffer.truncate(ret_code as usize);
+            return Ok(buffer);
+        }
+    }
+
+    fn setxattr(&self, object: &str, name: &str, value: &[u8]) -> RadosResult<()> {
+        let object_str = CString::new(object)?;
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code = rados_setxattr(
+                self.as_raw(),
+                object_str.as_ptr(),
+                name_str.as_ptr(),
+                value.as_ptr() as *const c_char,
+                value.len(),
+            );
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    fn rmxattr(&self, object: &str, name: &str) -> RadosResult<()> {
+        ceph::rados_object_rmxattr(self.as_raw(), object, name)
+    }
+
+    fn xattrs(&self, object: &str) -> RadosResult<BTreeMap<String, Vec<u8>>> {
+        let object_str = CString::new(object)?;
+        let mut iter: rados_xattrs_iter_t = ptr::null_mut();
+        let mut xattrs = BTreeMap::new();
+        unsafe {
+            let ret_code = rados_getxattrs(self.as_raw(), object_str.as_ptr(), &mut iter);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            loop {
+                let mut name: *const c_char = ptr::null();
+                let mut value: *const c_char = ptr::null();
+                let mut len: size_t = 0;
+                let ret_code = rados_getxattrs_next(iter, &mut name, &mut value, &mut len);
+                if ret_code < 0 {
+                    rados_getxattrs_end(iter);
+                    return Err(RadosError::new(get_error(ret_code)?));
+                }
+                // A null name marks the end
+                if name.is_null() {
+                    break;
+                }
+                xattrs.insert(CStr::from_ptr(name).to_string_lossy().into_owned(), copy_buffer(value, len));
+            }
+            rados_getxattrs_end(iter);
+        }
+        Ok(xattrs)
+    }
+
+    fn omap_set(&self, object: &str, entries: &BTreeMap<String, Vec<u8>>) -> RadosResult<()> {
+        let keys = entries.keys().map(|k| CString::new(k.as_str())).collect::<Result<Vec<_>, _>>()?;
+        let key_ptrs: Vec<*const c_char> = keys.iter().map(|k| k.as_ptr()).collect();
+        let value_ptrs: Vec<*const c_char> = entries.values().map(|v| v.as_ptr() as *const c_char).collect();
+        let lens: Vec<size_t> = entries.values().map(|v| v.len()).collect();
+        self.write_op(object, |op| unsafe {
+            rados_write_op_omap_set(op, key_ptrs.as_ptr(), value_ptrs.as_ptr(), lens.as_ptr(), key_ptrs.len());
+        })
+    }
+
+    fn omap_get(&self, object: &str, start_after: &str, max: u64) -> RadosResult<BTreeMap<String, Vec<u8>>> {
+        let object_str = CString::new(object)?;
+        let start_after_str = CString::new(start_after)?;
+        let mut entries = BTreeMap::new();
+        unsafe {
+            let op = rados_create_read_op();
+            let mut iter: rados_omap_iter_t = ptr::null_mut();
+            let mut prval: c_int = 0;
+            rados_read_op_omap_get_vals(op, start_after_str.as_ptr(), ptr::null(), max, &mut iter, &mut prval);
+            let mut ret_code = rados_read_op_operate(op, self.as_raw(), object_str.as_ptr(), 0);
+            if ret_code >= 0 {
+                ret_code = prval;
+            }
+            if ret_code >= 0 {
+                loop {
+                    let mut key: *mut c_char = ptr::null_mut();
+                    let mut value: *mut c_char = ptr::null_mut();
+                    let mut len: size_t = 0;
+                    ret_code = rados_omap_get_next(iter, &mut key, &mut value, &mut len);
+                    if ret_code < 0 || key.is_null() {
+                        break;
+                    }
+                    entries.insert(CStr::from_ptr(key).to_string_lossy().into_owned(), copy_buffer(value, len));
+                }
+                rados_omap_get_end(iter);
+            }
+            rados_release_read_op(op);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn omap_rm_keys(&self, object: &str, keys: &[&str]) -> RadosResult<()> {
+        let keys = keys.iter().map(|k| CString::new(*k)).collect::<Result<Vec<_>, _>>()?;
+        let key_ptrs: Vec<*const c_char> = keys.iter().map(|k| k.as_ptr()).collect();
+        self.write_op(object, |op| unsafe {
+            rados_write_op_omap_rm_keys(op, key_ptrs.as_ptr(), key_ptrs.len());
+        })
+    }
+
+    fn omap_clear(&self, object: &str) -> RadosResult<()> {
+        self.write_op(object, |op| unsafe {
+            rados_write_op_omap_clear(op);
+        })
+    }
+
+    fn snap_create(&self, name: &str) -> RadosResult<()> {
+        ceph::rados_snap_create(self.as_raw(), name)
+    }
+
+    fn snap_remove(&self, name: &str) -> RadosResult<()> {
+        ceph::rados_snap_remove(self.as_raw(), name)
+    }
+
+    fn snap_lookup(&self, name: &str) -> RadosResult<u64> {
+        ceph::rados_snap_lookup(self.as_raw(), name)
+    }
+
+    fn snap_rollback(&self, object: &str, snap: &str) -> RadosResult<()> {
+        ceph::rados_snap_rollback(self.as_raw(), object, snap)
+    }
+
+    fn snap_set_read(&self, snap_id: u64) -> RadosResult<()> {
+        ceph::rados_snap_set_read(self.as_raw(), snap_id)
+    }
+}
+
+impl<'a> IoCtx<'a> {
+    // Run a write op holding whatever `build` adds to it
+    fn write_op<F: FnOnce(rados_write_op_t)>(&self, object: &str, build: F) -> RadosResult<()> {
+        let object_str = CString::new(object)?;
+        unsafe {
+            let op = rados_create_write_op();
+            build(op);
+            let ret_code = rados_write_op_operate(op, self.as_raw(), object_str.as_ptr(), ptr::null_mut(), 0);
+            rados_release_write_op(op);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Copy a value librados owns before the iterator it came from is ended
+unsafe fn copy_buffer(ptr: *const c_char, len: size_t) -> Vec<u8> {
+    if ptr.is_null() {
+        return Vec::new();
+    }
+    ::std::slice::from_raw_parts(ptr as *const u8, len).to_vec()
+}
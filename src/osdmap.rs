@@ -0,0 +1,667 @@
+//! A read-only decoder for the binary OSDMap that `osd getmap` returns,
+//! enough to simulate placement offline: pools, osd states, weights and
+//! addresses, the pg_temp and upmap overrides and the embedded CRUSH map.
+//! Maps from before jewel use an older encoding that isn't supported.
+//!
+//! ```rust,no_run
+//! # use ceph::Rados;
+//! # use ceph::cmd::osd_getmap;
+//! # use ceph::osdmap::OsdMap;
+//! # use ceph::error::RadosError;
+//! # fn main() {
+//! #   let _ = run();
+//! # }
+//! # fn run() -> Result<(), RadosError> {
+//! let rados = Rados::connect("admin", "/etc/ceph/ceph.conf")?;
+//! let map = OsdMap::decode(&osd_getmap(&rados, None)?)?;
+//! for osd in map.osds.iter().filter(|o| o.exists() && !o.is_up()) {
+//!     println!("osd.{} is down", osd.id);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+use crush::CrushMap;
+use decode::Decoder;
+use error::{RadosError, RadosResult};
+use monmap::{decode_addr, decode_addrvec, EntityAddr};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use decode::encode::{addr_v4, end_struct, legacy_addr_v4, start_struct, string};
+
+    fn pgid(buf: &mut Vec<u8>, pool: u64, seed: u32) {
+        buf.push(1);
+        buf.write_u64::<LittleEndian>(pool).unwrap();
+        buf.write_u32::<LittleEndian>(seed).unwrap();
+        buf.write_i32::<LittleEndian>(-1).unwrap();
+    }
+
+    // A replicated pool as a nautilus cluster encodes it
+    fn pool(buf: &mut Vec<u8>) {
+        let start = start_struct(buf, 29, 5);
+        buf.extend_from_slice(&[1, 3, 0, 2]);
+        for value in &[32, 32, 0, 0, 40] {
+            buf.write_u32::<LittleEndian>(*value).unwrap();
+        }
+        // snap_seq, snap_epoch, one snap, no removed snaps, auid, flags
+        buf.write_u64::<LittleEndian>(1).unwrap();
+        buf.write_u32::<LittleEndian>(38).unwrap();
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        buf.write_u64::<LittleEndian>(1).unwrap();
+        let snap = start_struct(buf, 2, 2);
+        buf.write_u64::<LittleEndian>(1).unwrap();
+        buf.write_u64::<LittleEndian>(0).unwrap();
+        string(buf, "before-upgrade");
+        end_struct(buf, snap);
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u64::<LittleEndian>(0).unwrap();
+        buf.write_u64::<LittleEndian>(1).unwrap();
+        // crash_replay_interval, min_size, quotas
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.push(2);
+        buf.write_u64::<LittleEndian>(1 << 30).unwrap();
+        buf.write_u64::<LittleEndian>(0).unwrap();
+        // tiers, tier_of, cache_mode, read_tier, write_tier, properties
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_i64::<LittleEndian>(-1).unwrap();
+        buf.push(0);
+        buf.write_i64::<LittleEndian>(-1).unwrap();
+        buf.write_i64::<LittleEndian>(-1).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        // hit_set_params, period and count, stripe_width, targets
+        let hit_set = start_struct(buf, 1, 1);
+        buf.push(0);
+        end_struct(buf, hit_set);
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u64::<LittleEndian>(0).unwrap();
+        buf.write_u64::<LittleEndian>(0).unwrap();
+        // cache ratios and ages
+        for _ in 0..4 {
+            buf.write_u32::<LittleEndian>(0).unwrap();
+        }
+        string(buf, "");
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_i32::<LittleEndian>(0).unwrap();
+        buf.write_u64::<LittleEndian>(0).unwrap();
+        // v19 to v23
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.push(1);
+        buf.push(0);
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        // opts, last_force_op_resend_prenautilus
+        let opts = start_struct(buf, 2, 1);
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        end_struct(buf, opts);
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        // application_metadata
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        string(buf, "rbd");
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        // create_time
+        buf.write_u32::<LittleEndian>(1500000000).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        // pg_num_target, pgp_num_target, pg_num_pending, two retired
+        // epochs, last_force_op_resend, pg_autoscale_mode
+        for value in &[64, 64, 32, 0, 0, 0] {
+            buf.write_u32::<LittleEndian>(*value).unwrap();
+        }
+        buf.push(1);
+        // last_pg_merge_meta, which isn't decoded
+        let merge = start_struct(buf, 1, 1);
+        buf.extend_from_slice(&[0; 20]);
+        end_struct(buf, merge);
+        end_struct(buf, start);
+    }
+
+    fn nautilus_osdmap() -> Vec<u8> {
+        osdmap(9)
+    }
+
+    // Before nautilus (osd data v8) the cluster addresses were single
+    // legacy addresses rather than address vectors
+    fn osdmap(osd_version: u8) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let wrapper = start_struct(&mut buf, 8, 7);
+        let client = start_struct(&mut buf, 9, 1);
+        buf.extend_from_slice(&[0x22; 16]);
+        buf.write_u32::<LittleEndian>(41).unwrap();
+        for _ in 0..2 {
+            buf.write_u32::<LittleEndian>(1500000000).unwrap();
+            buf.write_u32::<LittleEndian>(0).unwrap();
+        }
+        // pools, pool names, pool_max, flags, max_osd
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        buf.write_i64::<LittleEndian>(1).unwrap();
+        pool(&mut buf);
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        buf.write_i64::<LittleEndian>(1).unwrap();
+        string(&mut buf, "rbd");
+        buf.write_i32::<LittleEndian>(1).unwrap();
+        buf.write_u32::<LittleEndian>((OSDMAP_NOOUT | OSDMAP_SORTBITWISE).bits()).unwrap();
+        buf.write_i32::<LittleEndian>(2).unwrap();
+        // osd_state, osd_weight, client addrs
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        buf.write_u32::<LittleEndian>(OSD_EXISTS | OSD_UP).unwrap();
+        buf.write_u32::<LittleEndian>(OSD_EXISTS).unwrap();
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        buf.write_u32::<LittleEndian>(0x10000).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        buf.push(2);
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        addr_v4(&mut buf, 2, [10, 0, 0, 5], 6800);
+        addr_v4(&mut buf, 1, [10, 0, 0, 5], 6801);
+        buf.push(2);
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        // pg_temp, primary_temp, primary affinity
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        pgid(&mut buf, 1, 7);
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        buf.write_i32::<LittleEndian>(1).unwrap();
+        buf.write_i32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        // crush
+        let crush = CrushMap::default().encode().unwrap();
+        buf.write_u32::<LittleEndian>(crush.len() as u32).unwrap();
+        buf.extend_from_slice(&crush);
+        // erasure code profiles
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        string(&mut buf, "default");
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        string(&mut buf, "k");
+        string(&mut buf, "2");
+        // pg_upmap, pg_upmap_items
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        pgid(&mut buf, 1, 0x1a);
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        buf.write_i32::<LittleEndian>(0).unwrap();
+        buf.write_i32::<LittleEndian>(1).unwrap();
+        // crush_version, removed and purged snaps, last up and in change
+        buf.write_i32::<LittleEndian>(5).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        for _ in 0..2 {
+            buf.write_u32::<LittleEndian>(1600000000).unwrap();
+            buf.write_u32::<LittleEndian>(0).unwrap();
+        }
+        end_struct(&mut buf, client);
+
+        let osd = start_struct(&mut buf, osd_version, 1);
+        // hb_back_addrs, osd_info, blocklist
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        for _ in 0..2 {
+            buf.push(1);
+            buf.extend_from_slice(&[0; 24]);
+        }
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        // cluster addrs
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        if osd_version >= 8 {
+            addr_v4(&mut buf, 2, [192, 168, 0, 5], 6802);
+            addr_v4(&mut buf, 0, [0, 0, 0, 0], 0);
+        } else {
+            legacy_addr_v4(&mut buf, [192, 168, 0, 5], 6802);
+            legacy_addr_v4(&mut buf, [0, 0, 0, 0], 0);
+        }
+        // cluster snapshot, osd uuids
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        string(&mut buf, "");
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        buf.extend_from_slice(&[0x33; 16]);
+        buf.extend_from_slice(&[0; 16]);
+        // the rest of the osd data isn't decoded
+        buf.extend_from_slice(&[0xff; 10]);
+        end_struct(&mut buf, osd);
+        // crc
+        buf.extend_from_slice(&[0; 8]);
+        end_struct(&mut buf, wrapper);
+        buf
+    }
+
+    #[test]
+    fn it_decodes_an_osdmap() {
+        let map = OsdMap::decode(&nautilus_osdmap()).unwrap();
+        assert_eq!(map.epoch, 41);
+        assert_eq!(map.flags, OSDMAP_NOOUT | OSDMAP_SORTBITWISE);
+        assert_eq!(map.crush_version, 5);
+
+        let pool = &map.pools[&1];
+        assert_eq!(pool.name, "rbd");
+        assert_eq!(pool.pool_type, PoolType::Replicated);
+        assert_eq!((pool.size, pool.min_size, pool.pg_num), (3, 2, 32));
+        assert_eq!(pool.snaps[&1], "before-upgrade");
+        assert_eq!(pool.quota_max_bytes, 1 << 30);
+        assert!(pool.application_metadata.contains_key("rbd"));
+        assert_eq!(pool.pg_num_target, Some(64));
+
+        assert!(map.osds[0].is_up() && map.osds[0].is_in());
+        assert!(map.osds[1].exists() && !map.osds[1].is_up() && !map.osds[1].is_in());
+        assert_eq!(map.osds[0].public_addrs[0].to_string(), "v2:10.0.0.5:6800/0");
+        assert_eq!(map.osds[0].cluster_addrs[0].to_string(), "v2:192.168.0.5:6802/0");
+        assert_eq!(map.osds[0].uuid.unwrap().to_string(), "33333333-3333-3333-3333-333333333333");
+        assert_eq!(map.osds[1].uuid, None);
+
+        let pg = PgId { pool: 1, seed: 0x1a };
+        assert_eq!(pg.to_string(), "1.1a");
+        assert_eq!(map.pg_upmap_items[&pg], vec![(0, 1)]);
+        assert_eq!(map.pg_temp[&PgId { pool: 1, seed: 7 }], vec![1, 0]);
+        assert_eq!(map.erasure_code_profiles["default"]["k"], "2");
+    }
+
+    #[test]
+    fn it_decodes_pre_nautilus_osd_data() {
+        let map = OsdMap::decode(&osdmap(6)).unwrap();
+        assert_eq!(map.osds[0].cluster_addrs[0].to_string(), "v1:192.168.0.5:6802/0");
+        assert_eq!(map.osds[0].uuid.unwrap().to_string(), "33333333-3333-3333-3333-333333333333");
+        assert_eq!(map.osds[1].uuid, None);
+    }
+
+    #[test]
+    fn it_rejects_classic_osdmaps() {
+        assert!(OsdMap::decode(b"\x06\x00").is_err());
+    }
+}
+
+pub const OSD_EXISTS: u32 = 1;
+pub const OSD_UP: u32 = 2;
+pub const OSD_AUTOOUT: u32 = 4;
+pub const OSD_NEW: u32 = 8;
+pub const OSD_DESTROYED: u32 = 128;
+
+/// The weight of an osd that is fully in
+pub const OSD_IN_WEIGHT: u32 = 0x10000;
+
+pub use self::flags::*;
+
+// bitflags 0.7 still expands to `try!`
+#[allow(deprecated)]
+mod flags {
+    bitflags! {
+        /// The cluster wide flags, e.g. the ones `ceph osd set` sets
+        pub flags OsdMapFlags: u32 {
+            const OSDMAP_NEARFULL = 1,
+            const OSDMAP_FULL = 1 << 1,
+            const OSDMAP_PAUSERD = 1 << 2,
+            const OSDMAP_PAUSEWR = 1 << 3,
+            const OSDMAP_PAUSEREC = 1 << 4,
+            const OSDMAP_NOUP = 1 << 5,
+            const OSDMAP_NODOWN = 1 << 6,
+            const OSDMAP_NOOUT = 1 << 7,
+            const OSDMAP_NOIN = 1 << 8,
+            const OSDMAP_NOBACKFILL = 1 << 9,
+            const OSDMAP_NORECOVER = 1 << 10,
+            const OSDMAP_NOSCRUB = 1 << 11,
+            const OSDMAP_NODEEP_SCRUB = 1 << 12,
+            const OSDMAP_NOTIERAGENT = 1 << 13,
+            const OSDMAP_NOREBALANCE = 1 << 14,
+            const OSDMAP_SORTBITWISE = 1 << 15,
+            const OSDMAP_REQUIRE_JEWEL = 1 << 16,
+            const OSDMAP_REQUIRE_KRAKEN = 1 << 17,
+            const OSDMAP_REQUIRE_LUMINOUS = 1 << 18,
+            const OSDMAP_RECOVERY_DELETES = 1 << 19,
+            const OSDMAP_PURGED_SNAPDIRS = 1 << 20,
+            const OSDMAP_NOSNAPTRIM = 1 << 21,
+            const OSDMAP_PGLOG_HARDLIMIT = 1 << 22,
+            const OSDMAP_NOAUTOSCALE = 1 << 23,
+        }
+    }
+}
+
+/// A placement group, printed the way Ceph does as pool.seed in hex
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PgId {
+    pub pool: u64,
+    pub seed: u32,
+}
+
+impl fmt::Display for PgId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:x}", self.pool, self.seed)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PoolType {
+    Replicated,
+    Erasure,
+    Other(u8),
+}
+
+impl PoolType {
+    fn from_u8(pool_type: u8) -> PoolType {
+        match pool_type {
+            1 => PoolType::Replicated,
+            3 => PoolType::Erasure,
+            other => PoolType::Other(other),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pool {
+    pub id: i64,
+    pub name: String,
+    pub pool_type: PoolType,
+    pub size: u8,
+    pub min_size: u8,
+    pub crush_rule: u8,
+    pub object_hash: u8,
+    pub pg_num: u32,
+    pub pgp_num: u32,
+    /// The epoch the pool last changed in
+    pub last_change: u32,
+    pub snap_seq: u64,
+    /// Pool snapshot ids to names
+    pub snaps: BTreeMap<u64, String>,
+    pub flags: u64,
+    pub quota_max_bytes: u64,
+    pub quota_max_objects: u64,
+    pub tiers: Vec<u64>,
+    /// The base pool of a cache tier, -1 otherwise
+    pub tier_of: i64,
+    pub read_tier: i64,
+    pub write_tier: i64,
+    pub properties: BTreeMap<String, String>,
+    pub stripe_width: u32,
+    pub erasure_code_profile: String,
+    pub application_metadata: BTreeMap<String, BTreeMap<String, String>>,
+    /// Where pg_num is heading while pgs split or merge, since nautilus
+    pub pg_num_target: Option<u32>,
+    pub pgp_num_target: Option<u32>,
+    /// 0 off, 1 warn, 2 on
+    pub pg_autoscale_mode: Option<u8>,
+}
+
+/// The state of one osd id
+#[derive(Clone, Debug, PartialEq)]
+pub struct OsdInfo {
+    pub id: i32,
+    /// `OSD_*` bits
+    pub state: u32,
+    /// 0 when out, `OSD_IN_WEIGHT` when fully in
+    pub weight: u32,
+    /// `OSD_IN_WEIGHT` scaled, when set
+    pub primary_affinity: Option<u32>,
+    pub public_addrs: Vec<EntityAddr>,
+    pub cluster_addrs: Vec<EntityAddr>,
+    pub uuid: Option<Uuid>,
+}
+
+impl OsdInfo {
+    pub fn exists(&self) -> bool {
+        self.state & OSD_EXISTS != 0
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.exists() && self.state & OSD_UP != 0
+    }
+
+    pub fn is_in(&self) -> bool {
+        self.exists() && self.weight > 0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OsdMap {
+    pub fsid: Uuid,
+    pub epoch: u32,
+    pub created: SystemTime,
+    pub modified: SystemTime,
+    pub pools: BTreeMap<i64, Pool>,
+    /// The highest pool id ever used
+    pub pool_max: i32,
+    pub flags: OsdMapFlags,
+    /// Indexed by osd id, up to max_osd
+    pub osds: Vec<OsdInfo>,
+    pub pg_temp: BTreeMap<PgId, Vec<i32>>,
+    pub primary_temp: BTreeMap<PgId, i32>,
+    pub crush: CrushMap,
+    pub erasure_code_profiles: BTreeMap<String, BTreeMap<String, String>>,
+    /// Explicit mappings of a pg to a set of osds
+    pub pg_upmap: BTreeMap<PgId, Vec<i32>>,
+    /// (from, to) osd replacements for a pg
+    pub pg_upmap_items: BTreeMap<PgId, Vec<(i32, i32)>>,
+    pub crush_version: i32,
+    pub pg_upmap_primaries: BTreeMap<PgId, i32>,
+}
+
+impl OsdMap {
+    pub fn decode(buf: &[u8]) -> RadosResult<OsdMap> {
+        // The classic encoding starts with a u16 version below 7
+        if buf.first().map_or(true, |v| *v < 7) {
+            return Err(RadosError::new("Only osdmaps from jewel or newer can be decoded".to_string()));
+        }
+        let mut d = Decoder::new(buf);
+        let wrapper = d.start("osdmap", 8)?;
+        let client = d.start("osdmap client data", 10)?;
+        let fsid = d.uuid()?;
+        let epoch = d.u32()?;
+        let created = d.utime()?;
+        let modified = d.utime()?;
+        let mut pools = d.map(Decoder::i64, decode_pool)?;
+        let pool_names = d.map(Decoder::i64, Decoder::string)?;
+        for (id, name) in pool_names {
+            if let Some(pool) = pools.get_mut(&id) {
+                pool.id = id;
+                pool.name = name;
+            }
+        }
+        let pool_max = d.i32()?;
+        let flags = OsdMapFlags::from_bits_truncate(d.u32()?);
+        let max_osd = d.i32()?;
+        let states = if client.version >= 5 {
+            d.list(Decoder::u32)?
+        } else {
+            d.list(|d| d.u8().map(|s| s as u32))?
+        };
+        let weights = d.list(Decoder::u32)?;
+        let public_addrs = d.list(decode_addrvec)?;
+        let pg_temp = d.map(decode_pgid, |d| d.list(Decoder::i32))?;
+        let primary_temp = d.map(decode_pgid, Decoder::i32)?;
+        let primary_affinity = if client.version >= 2 { d.list(Decoder::u32)? } else { Vec::new() };
+        let crush_len = d.u32()? as usize;
+        let crush = CrushMap::decode(d.bytes(crush_len)?)?;
+
+        let mut map = OsdMap {
+            fsid: fsid,
+            epoch: epoch,
+            created: created,
+            modified: modified,
+            pools: pools,
+            pool_max: pool_max,
+            flags: flags,
+            osds: Vec::new(),
+            pg_temp: pg_temp,
+            primary_temp: primary_temp,
+            crush: crush,
+            erasure_code_profiles: BTreeMap::new(),
+            pg_upmap: BTreeMap::new(),
+            pg_upmap_items: BTreeMap::new(),
+            crush_version: 0,
+            pg_upmap_primaries: BTreeMap::new(),
+        };
+        if client.version >= 3 {
+            map.erasure_code_profiles = d.map(Decoder::string, |d| d.map(Decoder::string, Decoder::string))?;
+        }
+        if client.version >= 4 {
+            map.pg_upmap = d.map(decode_pgid, |d| d.list(Decoder::i32))?;
+            map.pg_upmap_items = d.map(decode_pgid, |d| d.list(|d| Ok((d.i32()?, d.i32()?))))?;
+        }
+        if client.version >= 6 {
+            map.crush_version = d.i32()?;
+        }
+        if client.version >= 10 {
+            // Skip the snap intervals and up/in change times to get here
+            d.map(Decoder::i64, |d| d.map(Decoder::u64, Decoder::u64))?;
+            d.map(Decoder::i64, |d| d.map(Decoder::u64, Decoder::u64))?;
+            d.utime()?;
+            d.utime()?;
+            map.pg_upmap_primaries = d.map(decode_pgid, Decoder::i32)?;
+        }
+        d.finish(client)?;
+
+        let osd = d.start("osdmap osd data", 10)?;
+        // hb_back_addrs, osd_info and the blocklist come first
+        d.list(decode_addrvec)?;
+        d.list(|d| d.bytes(25).map(|_| ()))?;
+        d.map(decode_addr_key, Decoder::utime)?;
+        let cluster_addrs = d.list(decode_addrvec)?;
+        // The cluster snapshot epoch and name
+        d.u32()?;
+        d.string()?;
+        let uuids = d.list(Decoder::uuid)?;
+        d.finish(osd)?;
+        d.finish(wrapper)?;
+
+        for id in 0..max_osd.max(0) as usize {
+            map.osds.push(OsdInfo {
+                id: id as i32,
+                state: states.get(id).cloned().unwrap_or(0),
+                weight: weights.get(id).cloned().unwrap_or(0),
+                primary_affinity: primary_affinity.get(id).cloned(),
+                public_addrs: public_addrs.get(id).cloned().unwrap_or_default(),
+                cluster_addrs: cluster_addrs.get(id).cloned().unwrap_or_default(),
+                // A nil uuid means none was set
+                uuid: uuids.get(id).cloned().filter(|u| !u.is_nil()),
+            });
+        }
+        Ok(map)
+    }
+}
+
+// Blocklist entries are keyed by a single address
+fn decode_addr_key(d: &mut Decoder) -> RadosResult<String> {
+    Ok(decode_addr(d)?.to_string())
+}
+
+fn decode_pgid(d: &mut Decoder) -> RadosResult<PgId> {
+    let version = d.u8()?;
+    if version != 1 {
+        return Err(RadosError::new(format!("Unknown pg_t version {}", version)));
+    }
+    let pool = d.u64()?;
+    let seed = d.u32()?;
+    // Once the preferred osd, always -1
+    d.i32()?;
+    Ok(PgId { pool: pool, seed: seed })
+}
+
+fn decode_pool(d: &mut Decoder) -> RadosResult<Pool> {
+    let header = d.start("pg_pool_t", 31)?;
+    if header.version < 23 {
+        return Err(RadosError::new(format!("pg_pool_t version {} is too old to decode", header.version)));
+    }
+    let mut pool = Pool {
+        id: 0,
+        name: String::new(),
+        pool_type: PoolType::from_u8(d.u8()?),
+        size: d.u8()?,
+        crush_rule: d.u8()?,
+        object_hash: d.u8()?,
+        pg_num: d.u32()?,
+        pgp_num: d.u32()?,
+        min_size: 0,
+        last_change: 0,
+        snap_seq: 0,
+        snaps: BTreeMap::new(),
+        flags: 0,
+        quota_max_bytes: 0,
+        quota_max_objects: 0,
+        tiers: Vec::new(),
+        tier_of: -1,
+        read_tier: -1,
+        write_tier: -1,
+        properties: BTreeMap::new(),
+        stripe_width: 0,
+        erasure_code_profile: String::new(),
+        application_metadata: BTreeMap::new(),
+        pg_num_target: None,
+        pgp_num_target: None,
+        pg_autoscale_mode: None,
+    };
+    // lpg_num and lpgp_num are always zero
+    d.u32()?;
+    d.u32()?;
+    pool.last_change = d.u32()?;
+    pool.snap_seq = d.u64()?;
+    // snap_epoch
+    d.u32()?;
+    pool.snaps = d.map(Decoder::u64, |d| {
+        let snap = d.start("pool_snap_info_t", 2)?;
+        d.u64()?;
+        d.utime()?;
+        let name = d.string()?;
+        d.finish(snap)?;
+        Ok(name)
+    })?;
+    // removed_snaps and auid
+    d.map(Decoder::u64, Decoder::u64)?;
+    d.u64()?;
+    pool.flags = d.u64()?;
+    // crash_replay_interval
+    d.u32()?;
+    pool.min_size = d.u8()?;
+    pool.quota_max_bytes = d.u64()?;
+    pool.quota_max_objects = d.u64()?;
+    pool.tiers = d.list(Decoder::u64)?;
+    pool.tier_of = d.i64()?;
+    // cache_mode
+    d.u8()?;
+    pool.read_tier = d.i64()?;
+    pool.write_tier = d.i64()?;
+    pool.properties = d.map(Decoder::string, Decoder::string)?;
+    let hit_set = d.start("HitSet::Params", 1)?;
+    d.finish(hit_set)?;
+    // hit_set_period and hit_set_count
+    d.u32()?;
+    d.u32()?;
+    pool.stripe_width = d.u32()?;
+    // target_max_bytes, target_max_objects, the cache ratios and ages
+    d.bytes(8 + 8 + 4 * 4)?;
+    pool.erasure_code_profile = d.string()?;
+    // last_force_op_resend_preluminous, min_read_recency_for_promote,
+    // expected_num_objects, cache_target_dirty_high_ratio_micro,
+    // min_write_recency_for_promote, use_gmt_hitset, fast_read,
+    // hit_set_grade_decay_rate and hit_set_search_last_n
+    d.bytes(4 + 4 + 8 + 4 + 4 + 1 + 1 + 4 + 4)?;
+    if header.version >= 24 {
+        let opts = d.start("pool_opts_t", 2)?;
+        d.finish(opts)?;
+    }
+    if header.version >= 25 {
+        // last_force_op_resend_prenautilus
+        d.u32()?;
+    }
+    if header.version >= 26 {
+        pool.application_metadata = d.map(Decoder::string, |d| d.map(Decoder::string, Decoder::string))?;
+    }
+    if header.version >= 27 {
+        // create_time
+        d.utime()?;
+    }
+    if header.version >= 28 {
+        pool.pg_num_target = Some(d.u32()?);
+        pool.pgp_num_target = Some(d.u32()?);
+        // pg_num_pending, two retired epochs and last_force_op_resend
+        d.bytes(4 * 4)?;
+        pool.pg_autoscale_mode = Some(d.u8()?);
+    }
+    d.finish(header)?;
+    Ok(pool)
+}
@@ -103,6 +103,9 @@ pub type rados_config_t = *mut ::libc::c_void;
 pub type rados_ioctx_t = *mut ::libc::c_void;
 pub type rados_list_ctx_t = *mut ::libc::c_void;
 pub type rados_snap_t = uint64_t;
+/// The snap id of the head of an object, to go back to reading current
+/// data after `rados_ioctx_snap_set_read`
+pub const LIBRADOS_SNAP_HEAD: rados_snap_t = !1;
 pub type rados_xattrs_iter_t = *mut ::libc::c_void;
 pub type rados_omap_iter_t = *mut ::libc::c_void;
 
@@ -193,8 +196,22 @@ pub type rados_log_callback_t = ::std::option::Option<extern "C" fn(arg: *mut ::
                                                                       msg: *const ::libc::c_char)
                                                                       -> ()>;
 
+pub type rados_log_callback2_t = ::std::option::Option<extern "C" fn(arg: *mut ::libc::c_void,
+                                                                       line: *const ::libc::c_char,
+                                                                       channel: *const ::libc::c_char,
+                                                                       who: *const ::libc::c_char,
+                                                                       name: *const ::libc::c_char,
+                                                                       sec: u64,
+                                                                       nsec: u64,
+                                                                       seq: u64,
+                                                                       level: *const ::libc::c_char,
+                                                                       msg: *const ::libc::c_char)
+                                                                       -> ()>;
+
 #[cfg(target_os = "linux")]
-#[link(name = "rados", kind="dylib")]
+// Test builds that never call into librados can skip it with
+// `--cfg ceph_without_librados`, see the fake module
+#[cfg_attr(not(ceph_without_librados), link(name = "rados", kind = "dylib"))]
 extern "C" {
     pub fn rados_version(major: *mut ::libc::c_int, minor: *mut ::libc::c_int, extra: *mut ::libc::c_int) -> ();
 
@@ -246,6 +263,9 @@ extern "C" {
 
     pub fn rados_get_instance_id(cluster: rados_t) -> uint64_t;
 
+    /// Nautilus and later.  The string is malloc'd and freed with free().
+    pub fn rados_getaddrs(cluster: rados_t, addrs: *mut *mut ::libc::c_char) -> ::libc::c_int;
+
     pub fn rados_ioctx_create(cluster: rados_t, pool_name: *const ::libc::c_char, ioctx: *mut rados_ioctx_t)
                               -> ::libc::c_int;
 
@@ -456,6 +476,8 @@ extern "C" {
     pub fn rados_aio_stat(io: rados_ioctx_t, o: *const ::libc::c_char, completion: rados_completion_t,
                           psize: *mut uint64_t, pmtime: *mut time_t)
                           -> ::libc::c_int;
+    // Only librados from Luminous on has this
+    #[cfg(feature = "luminous")]
     pub fn rados_aio_cancel(io: rados_ioctx_t, completion: rados_completion_t) -> ::libc::c_int;
     pub fn rados_watch(io: rados_ioctx_t, o: *const ::libc::c_char, ver: uint64_t, cookie: *mut uint64_t,
                        watchcb: rados_watchcb_t, arg: *mut ::libc::c_void)
@@ -602,6 +624,10 @@ extern "C" {
                                     outbuf: *mut *mut ::libc::c_char, outbuflen: *mut size_t,
                                     outs: *mut *mut ::libc::c_char, outslen: *mut size_t)
                                     -> ::libc::c_int;
+    pub fn rados_mgr_command(cluster: rados_t, cmd: *mut *const ::libc::c_char, cmdlen: size_t,
+                             inbuf: *const ::libc::c_char, inbuflen: size_t, outbuf: *mut *mut ::libc::c_char,
+                             outbuflen: *mut size_t, outs: *mut *mut ::libc::c_char, outslen: *mut size_t)
+                             -> ::libc::c_int;
     pub fn rados_buffer_free(buf: *mut ::libc::c_char) -> ();
     pub fn rados_osd_command(cluster: rados_t, osdid: ::libc::c_int, cmd: *mut *const ::libc::c_char, cmdlen: size_t,
                              inbuf: *const ::libc::c_char, inbuflen: size_t, outbuf: *mut *mut ::libc::c_char,
@@ -615,4 +641,29 @@ extern "C" {
     pub fn rados_monitor_log(cluster: rados_t, level: *const ::libc::c_char, cb: rados_log_callback_t,
                              arg: *mut ::libc::c_void)
                              -> ::libc::c_int;
+    pub fn rados_service_register(cluster: rados_t, service: *const ::libc::c_char, daemon: *const ::libc::c_char,
+                                  metadata_dict: *const ::libc::c_char)
+                                  -> ::libc::c_int;
+    pub fn rados_service_update_status(cluster: rados_t, status_dict: *const ::libc::c_char) -> ::libc::c_int;
+    pub fn rados_application_enable(io: rados_ioctx_t, app_name: *const ::libc::c_char, force: ::libc::c_int)
+                                    -> ::libc::c_int;
+    pub fn rados_application_list(io: rados_ioctx_t, values: *mut ::libc::c_char, values_len: *mut size_t)
+                                  -> ::libc::c_int;
+    pub fn rados_application_metadata_get(io: rados_ioctx_t, app_name: *const ::libc::c_char,
+                                          key: *const ::libc::c_char, value: *mut ::libc::c_char,
+                                          value_len: *mut size_t)
+                                          -> ::libc::c_int;
+    pub fn rados_application_metadata_set(io: rados_ioctx_t, app_name: *const ::libc::c_char,
+                                          key: *const ::libc::c_char, value: *const ::libc::c_char)
+                                          -> ::libc::c_int;
+    pub fn rados_application_metadata_remove(io: rados_ioctx_t, app_name: *const ::libc::c_char,
+                                             key: *const ::libc::c_char)
+                                             -> ::libc::c_int;
+    pub fn rados_application_metadata_list(io: rados_ioctx_t, app_name: *const ::libc::c_char,
+                                           keys: *mut ::libc::c_char, key_len: *mut size_t,
+                                           values: *mut ::libc::c_char, vals_len: *mut size_t)
+                                           -> ::libc::c_int;
+    pub fn rados_monitor_log2(cluster: rados_t, level: *const ::libc::c_char, cb: rados_log_callback2_t,
+                              arg: *mut ::libc::c_void)
+                              -> ::libc::c_int;
 }
@@ -0,0 +1,1083 @@
+// Copyright 2017 LambdaStack All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Basic RBD image management through librbd.  Only built with the `rbd`
+//! feature, which links against librbd.
+//!
+//! ```rust,no_run
+//! # use ceph::Rados;
+//! # use ceph::librbd::RBD_FEATURE_LAYERING;
+//! # use ceph::rbd::RbdImage;
+//! # use ceph::error::RadosError;
+//! # fn main() {
+//! #   let _ = run();
+//! # }
+//! # fn run() -> Result<(), RadosError> {
+//! let rados = Rados::connect("admin", "/etc/ceph/ceph.conf")?;
+//! let ioctx = rados.ioctx("rbd")?;
+//! RbdImage::create(&ioctx, "disk1", 10 * 1024 * 1024 * 1024, RBD_FEATURE_LAYERING)?;
+//! let image = RbdImage::open(&ioctx, "disk1")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::future::Future;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::{mem, ptr};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use aio::{self, aio_complete, AioState};
+use ceph::{get_error, IoCtx};
+use error::{RadosError, RadosResult};
+use librbd::*;
+use libc::{c_char, c_int, c_void, size_t, ECANCELED, ENOENT, ERANGE};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_splits_image_names() {
+        assert_eq!(split_names(b"disk1\0disk2\0"), vec!["disk1", "disk2"]);
+        assert!(split_names(b"").is_empty());
+    }
+
+    #[test]
+    fn it_pairs_metadata() {
+        let metadata = pair_metadata(b"conf_rbd_cache\0hypervisor\0", b"false\0\0");
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata["conf_rbd_cache"], "false");
+        assert_eq!(metadata["hypervisor"], "");
+    }
+
+    #[test]
+    fn it_writes_export_diff_records() {
+        let mut out = Vec::new();
+        {
+            let mut diff = DiffWriter::new(&mut out, Some("snap1"), 8192).unwrap();
+            diff.data(0, b"ab").unwrap();
+            diff.zero(4096, 4096).unwrap();
+            diff.end().unwrap();
+        }
+        let mut expected = b"rbd diff v1\n".to_vec();
+        expected.extend_from_slice(b"f\x05\0\0\0snap1");
+        expected.extend_from_slice(b"s\0\x20\0\0\0\0\0\0");
+        expected.extend_from_slice(b"w\0\0\0\0\0\0\0\0\x02\0\0\0\0\0\0\0ab");
+        expected.extend_from_slice(b"z\0\x10\0\0\0\0\0\0\0\x10\0\0\0\0\0\0");
+        expected.extend_from_slice(b"e");
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn it_maps_mirror_modes() {
+        for mode in &[MirrorMode::Disabled, MirrorMode::Image, MirrorMode::Pool] {
+            assert_eq!(&MirrorMode::from_raw(mode.as_raw()).unwrap(), mode);
+        }
+        assert!(MirrorMode::from_raw(7).is_err());
+        assert_eq!(MirrorStatusState::from_raw(4), MirrorStatusState::Replaying);
+        assert_eq!(MirrorStatusState::from_raw(42), MirrorStatusState::Unknown);
+    }
+}
+
+/// The size and layout of an image
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ImageStat {
+    pub size: u64,
+    pub obj_size: u64,
+    pub num_objs: u64,
+    /// Objects are 2^order bytes
+    pub order: i32,
+    pub features: RbdFeatures,
+}
+
+/// The features of an image and the state of the ones that hosts need to
+/// know about before using it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ImageInfo {
+    pub features: RbdFeatures,
+    /// The object map needs rebuilding before it can be trusted
+    pub object_map_invalid: bool,
+    /// The fast-diff data needs rebuilding before it can be trusted
+    pub fast_diff_invalid: bool,
+    /// Whether this client holds the exclusive lock
+    pub exclusive_lock_owner: bool,
+}
+
+/// Which images of a pool rbd-mirror replicates
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MirrorMode {
+    Disabled,
+    /// Only images that have mirroring enabled on them
+    Image,
+    /// Every image with the journaling feature
+    Pool,
+}
+
+impl MirrorMode {
+    fn from_raw(mode: rbd_mirror_mode_t) -> RadosResult<MirrorMode> {
+        match mode {
+            RBD_MIRROR_MODE_DISABLED => Ok(MirrorMode::Disabled),
+            RBD_MIRROR_MODE_IMAGE => Ok(MirrorMode::Image),
+            RBD_MIRROR_MODE_POOL => Ok(MirrorMode::Pool),
+            _ => Err(RadosError::new(format!("Unknown rbd mirror mode {}", mode))),
+        }
+    }
+
+    fn as_raw(&self) -> rbd_mirror_mode_t {
+        match *self {
+            MirrorMode::Disabled => RBD_MIRROR_MODE_DISABLED,
+            MirrorMode::Image => RBD_MIRROR_MODE_IMAGE,
+            MirrorMode::Pool => RBD_MIRROR_MODE_POOL,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MirrorImageState {
+    Disabling,
+    Enabled,
+    Disabled,
+}
+
+impl MirrorImageState {
+    fn from_raw(state: rbd_mirror_image_state_t) -> MirrorImageState {
+        match state {
+            RBD_MIRROR_IMAGE_DISABLING => MirrorImageState::Disabling,
+            RBD_MIRROR_IMAGE_ENABLED => MirrorImageState::Enabled,
+            _ => MirrorImageState::Disabled,
+        }
+    }
+}
+
+/// What rbd-mirror on the peer cluster is doing with an image
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MirrorStatusState {
+    Unknown,
+    Error,
+    Syncing,
+    StartingReplay,
+    Replaying,
+    StoppingReplay,
+    Stopped,
+}
+
+impl MirrorStatusState {
+    fn from_raw(state: rbd_mirror_image_status_state_t) -> MirrorStatusState {
+        match state {
+            MIRROR_IMAGE_STATUS_STATE_ERROR => MirrorStatusState::Error,
+            MIRROR_IMAGE_STATUS_STATE_SYNCING => MirrorStatusState::Syncing,
+            MIRROR_IMAGE_STATUS_STATE_STARTING_REPLAY => MirrorStatusState::StartingReplay,
+            MIRROR_IMAGE_STATUS_STATE_REPLAYING => MirrorStatusState::Replaying,
+            MIRROR_IMAGE_STATUS_STATE_STOPPING_REPLAY => MirrorStatusState::StoppingReplay,
+            MIRROR_IMAGE_STATUS_STATE_STOPPED => MirrorStatusState::Stopped,
+            _ => MirrorStatusState::Unknown,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MirrorImageStatus {
+    pub name: String,
+    /// The id the image has on every cluster it's mirrored to
+    pub global_id: String,
+    pub state: MirrorImageState,
+    /// Whether this cluster holds the primary copy
+    pub primary: bool,
+    pub status: MirrorStatusState,
+    pub description: String,
+    /// Seconds since the epoch
+    pub last_update: i64,
+    /// Whether an rbd-mirror daemon is reporting on the image
+    pub up: bool,
+}
+
+/// The mirror mode of the pool
+pub fn mirror_mode_get(ioctx: &IoCtx) -> RadosResult<MirrorMode> {
+    let mut mode: rbd_mirror_mode_t = RBD_MIRROR_MODE_DISABLED;
+    unsafe {
+        let ret_code = rbd_mirror_mode_get(ioctx.as_raw(), &mut mode);
+        if ret_code < 0 {
+            return Err(RadosError::new(get_error(ret_code)?));
+        }
+    }
+    MirrorMode::from_raw(mode)
+}
+
+/// Enable or disable mirroring for the pool.  Peers are still added with
+/// `rbd mirror pool peer add`.
+pub fn mirror_mode_set(ioctx: &IoCtx, mode: MirrorMode) -> RadosResult<()> {
+    unsafe {
+        let ret_code = rbd_mirror_mode_set(ioctx.as_raw(), mode.as_raw());
+        if ret_code < 0 {
+            return Err(RadosError::new(get_error(ret_code)?));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LockMode {
+    Exclusive,
+    Shared,
+}
+
+impl LockMode {
+    fn from_raw(mode: rbd_lock_mode_t) -> LockMode {
+        match mode {
+            RBD_LOCK_MODE_SHARED => LockMode::Shared,
+            _ => LockMode::Exclusive,
+        }
+    }
+
+    fn as_raw(&self) -> rbd_lock_mode_t {
+        match *self {
+            LockMode::Exclusive => RBD_LOCK_MODE_EXCLUSIVE,
+            LockMode::Shared => RBD_LOCK_MODE_SHARED,
+        }
+    }
+}
+
+/// The clients holding the lock on an image
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LockOwners {
+    pub mode: LockMode,
+    /// Client addresses, as passed to `break_lock`
+    pub owners: Vec<String>,
+}
+
+// Owns an rbd completion.  Dropping it waits for the operation so that
+// librbd is done with any buffer before it is freed.  librbd has no way to
+// cancel an operation, so that wait can't be cut short.
+#[derive(Debug)]
+struct Completion {
+    completion: rbd_completion_t,
+    // Boxed so the callback's pointer to it stays put when the owner moves
+    state: Box<Mutex<AioState>>,
+}
+
+impl Completion {
+    fn new() -> RadosResult<Completion> {
+        let state = Box::new(Mutex::new(AioState::default()));
+        let mut completion: rbd_completion_t = ptr::null_mut();
+        unsafe {
+            let ret_code = rbd_aio_create_completion(
+                &*state as *const Mutex<AioState> as *mut c_void,
+                Some(aio_complete),
+                &mut completion,
+            );
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(Completion {
+            completion: completion,
+            state: state,
+        })
+    }
+
+    fn is_complete(&self) -> bool {
+        unsafe { rbd_aio_is_complete(self.completion) != 0 }
+    }
+
+    // Block until the operation finishes and return its result
+    fn wait(&self) -> RadosResult<usize> {
+        unsafe {
+            rbd_aio_wait_for_complete(self.completion);
+        }
+        self.result()
+    }
+
+    // The result once the operation finished, otherwise wake the task
+    // behind `cx` when it does
+    fn poll_result(&self, cx: &mut Context) -> Poll<RadosResult<usize>> {
+        {
+            let mut state = aio::lock(&self.state);
+            if !state.complete {
+                state.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(self.result())
+    }
+
+    fn result(&self) -> RadosResult<usize> {
+        let ret_code = unsafe { rbd_aio_get_return_value(self.completion) };
+        if ret_code < 0 {
+            return Err(RadosError::new(get_error(ret_code as i32)?));
+        }
+        Ok(ret_code as usize)
+    }
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        unsafe {
+            // The callback has run by the time this returns
+            rbd_aio_wait_for_complete(self.completion);
+            rbd_aio_release(self.completion);
+        }
+    }
+}
+
+/// A read started with `RbdImage::aio_read`.  Start as many as needed and
+/// then `wait` on each, check `is_complete` from an event loop or `.await`
+/// them on any executor.  Dropping an unfinished read waits for it.
+#[derive(Debug)]
+pub struct AioRead<'a> {
+    // Declared before the buffer so it is dropped, and waited on, first
+    completion: Completion,
+    // Taken when the read resolves
+    buffer: Option<Vec<u8>>,
+    image: PhantomData<&'a RbdImage<'a>>,
+}
+
+impl<'a> AioRead<'a> {
+    pub fn is_complete(&self) -> bool {
+        self.completion.is_complete()
+    }
+
+    /// Wait for the read and return the data.  It is short when the read
+    /// ran past the end of the image.
+    pub fn wait(mut self) -> RadosResult<Vec<u8>> {
+        let read = self.completion.wait()?;
+        Ok(self.finish(read))
+    }
+
+    fn finish(&mut self, read: usize) -> Vec<u8> {
+        let mut buffer = self.buffer.take().expect("AioRead polled after it resolved");
+        unsafe {
+            buffer.set_len(read);
+        }
+        buffer
+    }
+}
+
+impl<'a> Future for AioRead<'a> {
+    type Output = RadosResult<Vec<u8>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<RadosResult<Vec<u8>>> {
+        let this = self.get_mut();
+        match this.completion.poll_result(cx) {
+            Poll::Ready(Ok(read)) => Poll::Ready(Ok(this.finish(read))),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A write, discard or flush started on an `RbdImage`.  Like `AioRead` it
+/// can be waited on or awaited.
+#[derive(Debug)]
+pub struct AioOp<'a> {
+    completion: Completion,
+    // Data being written, kept alive until librbd is done with it
+    #[allow(dead_code)]
+    buffer: Vec<u8>,
+    image: PhantomData<&'a RbdImage<'a>>,
+}
+
+impl<'a> AioOp<'a> {
+    pub fn is_complete(&self) -> bool {
+        self.completion.is_complete()
+    }
+
+    pub fn wait(self) -> RadosResult<()> {
+        self.completion.wait()?;
+        Ok(())
+    }
+}
+
+impl<'a> Future for AioOp<'a> {
+    type Output = RadosResult<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<RadosResult<()>> {
+        match self.completion.poll_result(cx) {
+            Poll::Ready(result) => Poll::Ready(result.map(|_| ())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A range of an image that changed.  Extents that no longer exist were
+/// discarded and read back as zeros.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DiffExtent {
+    pub offset: u64,
+    pub length: u64,
+    pub exists: bool,
+}
+
+// What rbd_diff_iterate2 hands back to diff_callback
+struct DiffState<'f> {
+    callback: &'f mut dyn FnMut(DiffExtent) -> RadosResult<()>,
+    error: Option<RadosError>,
+}
+
+extern "C" fn diff_callback(offset: u64, length: size_t, exists: c_int, arg: *mut c_void) -> c_int {
+    let state = unsafe { &mut *(arg as *mut DiffState) };
+    let extent = DiffExtent {
+        offset: offset,
+        length: length as u64,
+        exists: exists != 0,
+    };
+    // Unwinding into librbd isn't allowed
+    match panic::catch_unwind(AssertUnwindSafe(|| (state.callback)(extent))) {
+        Ok(Ok(())) => 0,
+        Ok(Err(e)) => {
+            state.error = Some(e);
+            -ECANCELED
+        },
+        Err(_) => {
+            state.error = Some(RadosError::new("rbd diff callback panicked".to_string()));
+            -ECANCELED
+        },
+    }
+}
+
+// Writes the stream `rbd export-diff` produces, which `rbd import-diff`
+// applies.  See doc/dev/rbd-diff.rst in the ceph tree for the format.
+struct DiffWriter<'w, W: Write + 'w> {
+    out: &'w mut W,
+}
+
+impl<'w, W: Write> DiffWriter<'w, W> {
+    fn new(out: &'w mut W, from_snap: Option<&str>, size: u64) -> RadosResult<DiffWriter<'w, W>> {
+        out.write_all(b"rbd diff v1\n")?;
+        if let Some(from_snap) = from_snap {
+            out.write_u8(b'f')?;
+            out.write_u32::<LittleEndian>(from_snap.len() as u32)?;
+            out.write_all(from_snap.as_bytes())?;
+        }
+        out.write_u8(b's')?;
+        out.write_u64::<LittleEndian>(size)?;
+        Ok(DiffWriter { out: out })
+    }
+
+    fn data(&mut self, offset: u64, data: &[u8]) -> RadosResult<()> {
+        self.out.write_u8(b'w')?;
+        self.out.write_u64::<LittleEndian>(offset)?;
+        self.out.write_u64::<LittleEndian>(data.len() as u64)?;
+        self.out.write_all(data)?;
+        Ok(())
+    }
+
+    fn zero(&mut self, offset: u64, length: u64) -> RadosResult<()> {
+        self.out.write_u8(b'z')?;
+        self.out.write_u64::<LittleEndian>(offset)?;
+        self.out.write_u64::<LittleEndian>(length)?;
+        Ok(())
+    }
+
+    fn end(&mut self) -> RadosResult<()> {
+        self.out.write_u8(b'e')?;
+        Ok(())
+    }
+}
+
+/// An open RBD image.  The image is closed when this is dropped.
+#[derive(Debug)]
+pub struct RbdImage<'a> {
+    image: rbd_image_t,
+    name: String,
+    ioctx: PhantomData<&'a IoCtx<'a>>,
+}
+
+impl<'a> Drop for RbdImage<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            rbd_close(self.image);
+        }
+    }
+}
+
+impl<'a> RbdImage<'a> {
+    /// Create an image of `size` bytes with the default object size
+    pub fn create(ioctx: &IoCtx, name: &str, size: u64, features: RbdFeatures) -> RadosResult<()> {
+        let name_str = CString::new(name)?;
+        // Zero lets librbd pick the object size
+        let mut order = 0;
+        unsafe {
+            let ret_code = rbd_create2(ioctx.as_raw(), name_str.as_ptr(), size, features.bits(), &mut order);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Open an image for reading and writing
+    pub fn open(ioctx: &'a IoCtx, name: &str) -> RadosResult<RbdImage<'a>> {
+        let name_str = CString::new(name)?;
+        let mut image: rbd_image_t = ptr::null_mut();
+        unsafe {
+            let ret_code = rbd_open(ioctx.as_raw(), name_str.as_ptr(), &mut image, ptr::null());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(RbdImage {
+            image: image,
+            name: name.to_string(),
+            ioctx: PhantomData,
+        })
+    }
+
+    /// The names of all images in the pool
+    pub fn list(ioctx: &IoCtx) -> RadosResult<Vec<String>> {
+        let mut size: usize = 1024;
+        loop {
+            let mut names: Vec<u8> = Vec::with_capacity(size);
+            unsafe {
+                let ret_code = rbd_list(ioctx.as_raw(), names.as_mut_ptr() as *mut c_char, &mut size);
+                if ret_code == -ERANGE {
+                    // librbd set size to what it needs
+                    continue;
+                } else if ret_code < 0 {
+                    return Err(RadosError::new(get_error(ret_code)?));
+                }
+                names.set_len(ret_code as usize);
+            }
+            return Ok(split_names(&names));
+        }
+    }
+
+    /// Delete an image and all of its data
+    pub fn remove(ioctx: &IoCtx, name: &str) -> RadosResult<()> {
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code = rbd_remove(ioctx.as_raw(), name_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read up to `len` bytes starting at `offset`.  The read is short
+    /// when it runs past the end of the image.
+    pub fn read(&self, offset: u64, len: usize) -> RadosResult<Vec<u8>> {
+        let mut buffer: Vec<u8> = Vec::with_capacity(len);
+        unsafe {
+            let ret_code = rbd_read(self.image, offset, len, buffer.as_mut_ptr() as *mut c_char);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code as i32)?));
+            }
+            buffer.set_len(ret_code as usize);
+        }
+        Ok(buffer)
+    }
+
+    /// Write `data` at `offset`, returning the number of bytes written
+    pub fn write(&self, offset: u64, data: &[u8]) -> RadosResult<usize> {
+        unsafe {
+            let ret_code = rbd_write(self.image, offset, data.len(), data.as_ptr() as *const c_char);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code as i32)?));
+            }
+            Ok(ret_code as usize)
+        }
+    }
+
+    /// Start reading up to `len` bytes at `offset` without waiting for the
+    /// data
+    pub fn aio_read(&self, offset: u64, len: usize) -> RadosResult<AioRead> {
+        let completion = Completion::new()?;
+        let mut buffer: Vec<u8> = Vec::with_capacity(len);
+        unsafe {
+            let ret_code = rbd_aio_read(self.image, offset, len, buffer.as_mut_ptr() as *mut c_char, completion.completion);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(AioRead {
+            completion: completion,
+            buffer: Some(buffer),
+            image: PhantomData,
+        })
+    }
+
+    /// Start writing `data` at `offset`.  The data is held until the write
+    /// finishes.
+    pub fn aio_write(&self, offset: u64, data: Vec<u8>) -> RadosResult<AioOp> {
+        let completion = Completion::new()?;
+        unsafe {
+            let ret_code = rbd_aio_write(self.image, offset, data.len(), data.as_ptr() as *const c_char, completion.completion);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(AioOp {
+            completion: completion,
+            buffer: data,
+            image: PhantomData,
+        })
+    }
+
+    pub fn aio_discard(&self, offset: u64, len: u64) -> RadosResult<AioOp> {
+        let completion = Completion::new()?;
+        unsafe {
+            let ret_code = rbd_aio_discard(self.image, offset, len, completion.completion);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(AioOp {
+            completion: completion,
+            buffer: Vec::new(),
+            image: PhantomData,
+        })
+    }
+
+    /// Start flushing every write issued so far
+    pub fn aio_flush(&self) -> RadosResult<AioOp> {
+        let completion = Completion::new()?;
+        unsafe {
+            let ret_code = rbd_aio_flush(self.image, completion.completion);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(AioOp {
+            completion: completion,
+            buffer: Vec::new(),
+            image: PhantomData,
+        })
+    }
+
+    /// Call `callback` for every extent of the image that changed since
+    /// `from_snap`, or every allocated extent when there's no snapshot.
+    /// An error from the callback stops the iteration and is returned.
+    pub fn diff_iterate<F>(&self, from_snap: Option<&str>, mut callback: F) -> RadosResult<()>
+    where
+        F: FnMut(DiffExtent) -> RadosResult<()>,
+    {
+        let from_snap_str = match from_snap {
+            Some(snap) => Some(CString::new(snap)?),
+            None => None,
+        };
+        let size = self.stat()?.size;
+        let mut state = DiffState {
+            callback: &mut callback,
+            error: None,
+        };
+        unsafe {
+            let ret_code = rbd_diff_iterate2(
+                self.image,
+                from_snap_str.as_ref().map_or(ptr::null(), |snap| snap.as_ptr()),
+                0,
+                size,
+                1,
+                0,
+                Some(diff_callback),
+                &mut state as *mut DiffState as *mut c_void,
+            );
+            if let Some(e) = state.error {
+                return Err(e);
+            }
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the changes since `from_snap` in the format of
+    /// `rbd export-diff`, ready for `rbd import-diff` on another image.
+    pub fn export_diff<W: Write>(&self, from_snap: Option<&str>, out: &mut W) -> RadosResult<()> {
+        let size = self.stat()?.size;
+        let mut diff = DiffWriter::new(out, from_snap, size)?;
+        self.diff_iterate(from_snap, |extent| {
+            if extent.exists {
+                let data = self.read(extent.offset, extent.length as usize)?;
+                diff.data(extent.offset, &data)
+            } else {
+                diff.zero(extent.offset, extent.length)
+            }
+        })?;
+        diff.end()
+    }
+
+    /// Release the space behind a range of the image.  It reads back as
+    /// zeros afterwards.
+    pub fn discard(&self, offset: u64, len: u64) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_discard(self.image, offset, len);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for all writes to the image to be persisted
+    pub fn flush(&self) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_flush(self.image);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Grow or shrink the image to `size` bytes.  Shrinking throws away the
+    /// data past the new end.
+    pub fn resize(&self, size: u64) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_resize(self.image, size);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// The size, object layout and enabled features of the image
+    pub fn stat(&self) -> RadosResult<ImageStat> {
+        let mut features: u64 = 0;
+        unsafe {
+            let mut info: rbd_image_info_t = mem::zeroed();
+            let ret_code = rbd_stat(self.image, &mut info, mem::size_of::<rbd_image_info_t>());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            let ret_code = rbd_get_features(self.image, &mut features);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            Ok(ImageStat {
+                size: info.size,
+                obj_size: info.obj_size,
+                num_objs: info.num_objs,
+                order: info.order,
+                features: RbdFeatures::from_bits_truncate(features),
+            })
+        }
+    }
+
+    /// Turn features on, e.g. the object map and fast-diff together
+    pub fn feature_enable(&self, features: RbdFeatures) -> RadosResult<()> {
+        self.update_features(features, true)
+    }
+
+    pub fn feature_disable(&self, features: RbdFeatures) -> RadosResult<()> {
+        self.update_features(features, false)
+    }
+
+    fn update_features(&self, features: RbdFeatures, enabled: bool) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_update_features(self.image, features.bits(), enabled as u8);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn image_info(&self) -> RadosResult<ImageInfo> {
+        let mut features: u64 = 0;
+        let mut flags: u64 = 0;
+        unsafe {
+            let ret_code = rbd_get_features(self.image, &mut features);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            let ret_code = rbd_get_flags(self.image, &mut flags);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            let features = RbdFeatures::from_bits_truncate(features);
+            let flags = RbdFlags::from_bits_truncate(flags);
+            Ok(ImageInfo {
+                features: features,
+                object_map_invalid: flags.contains(RBD_FLAG_OBJECT_MAP_INVALID),
+                fast_diff_invalid: flags.contains(RBD_FLAG_FAST_DIFF_INVALID),
+                exclusive_lock_owner: features.contains(RBD_FEATURE_EXCLUSIVE_LOCK) && self.is_exclusive_lock_owner()?,
+            })
+        }
+    }
+
+    /// Look up one metadata key.  `None` when the key isn't set.
+    pub fn metadata_get(&self, key: &str) -> RadosResult<Option<String>> {
+        let key_str = CString::new(key)?;
+        let mut size: usize = 256;
+        loop {
+            let mut value: Vec<u8> = Vec::with_capacity(size);
+            unsafe {
+                let ret_code =
+                    rbd_metadata_get(self.image, key_str.as_ptr(), value.as_mut_ptr() as *mut c_char, &mut size);
+                if ret_code == -ERANGE {
+                    // librbd set size to what it needs
+                    continue;
+                } else if ret_code == -ENOENT {
+                    return Ok(None);
+                } else if ret_code < 0 {
+                    return Err(RadosError::new(get_error(ret_code)?));
+                }
+                value.set_len(size);
+            }
+            // The value comes back nul terminated
+            let end = value.iter().position(|b| *b == 0).unwrap_or(value.len());
+            return Ok(Some(String::from_utf8_lossy(&value[..end]).into_owned()));
+        }
+    }
+
+    pub fn metadata_set(&self, key: &str, value: &str) -> RadosResult<()> {
+        let key_str = CString::new(key)?;
+        let value_str = CString::new(value)?;
+        unsafe {
+            let ret_code = rbd_metadata_set(self.image, key_str.as_ptr(), value_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn metadata_remove(&self, key: &str) -> RadosResult<()> {
+        let key_str = CString::new(key)?;
+        unsafe {
+            let ret_code = rbd_metadata_remove(self.image, key_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// All of the image's metadata
+    pub fn metadata_list(&self) -> RadosResult<BTreeMap<String, String>> {
+        let start = CString::new("")?;
+        let mut keys_size: usize = 1024;
+        let mut values_size: usize = 1024;
+        loop {
+            let mut keys: Vec<u8> = Vec::with_capacity(keys_size);
+            let mut values: Vec<u8> = Vec::with_capacity(values_size);
+            unsafe {
+                // A max of zero lists every key
+                let ret_code = rbd_metadata_list(
+                    self.image,
+                    start.as_ptr(),
+                    0,
+                    keys.as_mut_ptr() as *mut c_char,
+                    &mut keys_size,
+                    values.as_mut_ptr() as *mut c_char,
+                    &mut values_size,
+                );
+                if ret_code == -ERANGE {
+                    continue;
+                } else if ret_code < 0 {
+                    return Err(RadosError::new(get_error(ret_code)?));
+                }
+                keys.set_len(keys_size);
+                values.set_len(values_size);
+            }
+            return Ok(pair_metadata(&keys, &values));
+        }
+    }
+
+    /// Start mirroring the image.  The pool must be in `MirrorMode::Image`.
+    pub fn mirror_enable(&self) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_mirror_image_enable(self.image);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop mirroring the image.  `force` allows disabling on a non-primary
+    /// copy.
+    pub fn mirror_disable(&self, force: bool) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_mirror_image_disable(self.image, force);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Make this cluster's copy the primary.  `force` promotes even when the
+    /// peer still claims to be primary, e.g. after losing the other site.
+    pub fn mirror_promote(&self, force: bool) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_mirror_image_promote(self.image, force);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn mirror_demote(&self) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_mirror_image_demote(self.image);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Throw away this non-primary copy and sync it again from the primary
+    pub fn mirror_resync(&self) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_mirror_image_resync(self.image);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn mirror_image_status(&self) -> RadosResult<MirrorImageStatus> {
+        unsafe {
+            let mut status: rbd_mirror_image_status_t = mem::zeroed();
+            let ret_code =
+                rbd_mirror_image_get_status(self.image, &mut status, mem::size_of::<rbd_mirror_image_status_t>());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            let mirror_status = MirrorImageStatus {
+                name: c_string(status.name),
+                global_id: c_string(status.info.global_id),
+                state: MirrorImageState::from_raw(status.info.state),
+                primary: status.info.primary,
+                status: MirrorStatusState::from_raw(status.state),
+                description: c_string(status.description),
+                last_update: status.last_update as i64,
+                up: status.up,
+            };
+            rbd_mirror_image_status_cleanup(&mut status);
+            Ok(mirror_status)
+        }
+    }
+
+    /// Take the image lock.  Needs the exclusive-lock feature.
+    pub fn lock_acquire(&self, mode: LockMode) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_lock_acquire(self.image, mode.as_raw());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lock_release(&self) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rbd_lock_release(self.image);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether this client currently holds the exclusive lock
+    pub fn is_exclusive_lock_owner(&self) -> RadosResult<bool> {
+        let mut is_owner = 0;
+        unsafe {
+            let ret_code = rbd_is_exclusive_lock_owner(self.image, &mut is_owner);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(is_owner != 0)
+    }
+
+    /// Who holds the image lock, `None` when nobody does
+    pub fn lock_get_owners(&self) -> RadosResult<Option<LockOwners>> {
+        let mut mode: rbd_lock_mode_t = RBD_LOCK_MODE_EXCLUSIVE;
+        let mut count: usize = 8;
+        loop {
+            let mut owners: Vec<*mut c_char> = vec![ptr::null_mut(); count];
+            unsafe {
+                let ret_code = rbd_lock_get_owners(self.image, &mut mode, owners.as_mut_ptr(), &mut count);
+                if ret_code == -ERANGE {
+                    // librbd set count to what it needs
+                    continue;
+                } else if ret_code == -ENOENT {
+                    return Ok(None);
+                } else if ret_code < 0 {
+                    return Err(RadosError::new(get_error(ret_code)?));
+                }
+                let names = owners[..count].iter().map(|owner| c_string(*owner)).collect();
+                rbd_lock_get_owners_cleanup(owners.as_mut_ptr(), count);
+                if count == 0 {
+                    return Ok(None);
+                }
+                return Ok(Some(LockOwners {
+                    mode: LockMode::from_raw(mode),
+                    owners: names,
+                }));
+            }
+        }
+    }
+
+    /// Forcibly take the lock away from a client, e.g. one on a host that
+    /// has been fenced.  Blacklist the client first or it may keep writing.
+    pub fn break_lock(&self, mode: LockMode, owner: &str) -> RadosResult<()> {
+        let owner_str = CString::new(owner)?;
+        unsafe {
+            let ret_code = rbd_lock_break(self.image, mode.as_raw(), owner_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The raw handle for use with the rbd_* functions in `librbd`
+    pub fn as_raw(&self) -> rbd_image_t {
+        self.image
+    }
+}
+
+// Copy a string librbd owns, which may be null
+unsafe fn c_string(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+// rbd_list hands back the names as a run of nul terminated strings
+fn split_names(buf: &[u8]) -> Vec<String> {
+    buf.split(|b| *b == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect()
+}
+
+// rbd_metadata_list returns the keys and values as two runs of nul
+// terminated strings in the same order.  Values may be empty.
+fn pair_metadata(keys: &[u8], values: &[u8]) -> BTreeMap<String, String> {
+    let values = values.split(|b| *b == 0).map(|value| String::from_utf8_lossy(value).into_owned());
+    split_names(keys).into_iter().zip(values).collect()
+}
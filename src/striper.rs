@@ -0,0 +1,288 @@
+// Copyright 2017 LambdaStack All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Striped objects through libradosstriper.  Only built with the `striper`
+//! feature, which links against libradosstriper.
+//!
+//! ```rust,no_run
+//! # use ceph::Rados;
+//! # use ceph::striper::{StripeLayout, Striper};
+//! # use ceph::error::RadosError;
+//! # fn main() {
+//! #   let _ = run();
+//! # }
+//! # fn run() -> Result<(), RadosError> {
+//! let rados = Rados::connect("admin", "/etc/ceph/ceph.conf")?;
+//! let ioctx = rados.ioctx("data")?;
+//! let layout = StripeLayout {
+//!     stripe_unit: 512 * 1024,
+//!     stripe_count: 8,
+//!     object_size: 4 * 1024 * 1024,
+//! };
+//! let striper = Striper::with_layout(&ioctx, &layout)?;
+//! striper.write_full("backup.tar", &vec![0; 64 * 1024 * 1024])?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::ptr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ceph::{get_error, IoCtx};
+use error::{RadosError, RadosResult};
+use libc::{c_char, time_t};
+use libradosstriper::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_checks_stripe_layouts() {
+        let layout = StripeLayout {
+            stripe_unit: 65536,
+            stripe_count: 4,
+            object_size: 4194304,
+        };
+        assert!(layout.validate().is_ok());
+
+        let uneven = StripeLayout {
+            stripe_unit: 65536 * 3,
+            ..layout.clone()
+        };
+        assert!(uneven.validate().is_err());
+
+        let no_stripes = StripeLayout {
+            stripe_count: 0,
+            ..layout
+        };
+        assert!(no_stripes.validate().is_err());
+    }
+}
+
+/// How a striped object is cut up.  Data is written `stripe_unit` bytes at
+/// a time round robin across `stripe_count` objects, each of which holds at
+/// most `object_size` bytes before the next set of objects is started.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StripeLayout {
+    pub stripe_unit: u32,
+    pub stripe_count: u32,
+    pub object_size: u32,
+}
+
+impl StripeLayout {
+    /// Check the layout before handing it to libradosstriper, which only
+    /// notices a bad one when the first object is written
+    pub fn validate(&self) -> RadosResult<()> {
+        if self.stripe_unit == 0 || self.stripe_count == 0 || self.object_size == 0 {
+            return Err(RadosError::new(format!("Stripe layout {:?} has a zero field", self)));
+        }
+        if !self.object_size.is_multiple_of(self.stripe_unit) {
+            return Err(RadosError::new(format!(
+                "Object size {} is not a multiple of the stripe unit {}",
+                self.object_size,
+                self.stripe_unit
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Striped access to the objects of a pool.  The layout only applies to
+/// objects this creates; existing ones keep the layout they were written
+/// with.
+#[derive(Debug)]
+pub struct Striper<'a> {
+    striper: rados_striper_t,
+    ioctx: PhantomData<&'a IoCtx<'a>>,
+}
+
+impl<'a> Drop for Striper<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            rados_striper_destroy(self.striper);
+        }
+    }
+}
+
+impl<'a> Striper<'a> {
+    /// A striper for the pool with the default layout of the cluster
+    pub fn new(ioctx: &'a IoCtx) -> RadosResult<Striper<'a>> {
+        let mut striper: rados_striper_t = ptr::null_mut();
+        unsafe {
+            let ret_code = rados_striper_create(ioctx.as_raw(), &mut striper);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(Striper {
+            striper: striper,
+            ioctx: PhantomData,
+        })
+    }
+
+    /// A striper that creates objects with `layout`
+    pub fn with_layout(ioctx: &'a IoCtx, layout: &StripeLayout) -> RadosResult<Striper<'a>> {
+        layout.validate()?;
+        let striper = Striper::new(ioctx)?;
+        striper.set_object_size(layout.object_size)?;
+        striper.set_stripe_unit(layout.stripe_unit)?;
+        striper.set_stripe_count(layout.stripe_count)?;
+        Ok(striper)
+    }
+
+    pub fn set_stripe_unit(&self, stripe_unit: u32) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rados_striper_set_object_layout_stripe_unit(self.striper, stripe_unit);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_stripe_count(&self, stripe_count: u32) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rados_striper_set_object_layout_stripe_count(self.striper, stripe_count);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_object_size(&self, object_size: u32) -> RadosResult<()> {
+        unsafe {
+            let ret_code = rados_striper_set_object_layout_object_size(self.striper, object_size);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Create an empty striped object, replacing any existing one
+    pub fn create(&self, name: &str) -> RadosResult<()> {
+        self.write_full(name, &[])
+    }
+
+    /// Write `data` at `offset`, creating the object if needed
+    pub fn write(&self, name: &str, data: &[u8], offset: u64) -> RadosResult<()> {
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code = rados_striper_write(
+                self.striper,
+                name_str.as_ptr(),
+                data.as_ptr() as *const c_char,
+                data.len(),
+                offset,
+            );
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the whole object with `data`
+    pub fn write_full(&self, name: &str, data: &[u8]) -> RadosResult<()> {
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code =
+                rados_striper_write_full(self.striper, name_str.as_ptr(), data.as_ptr() as *const c_char, data.len());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn append(&self, name: &str, data: &[u8]) -> RadosResult<()> {
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code =
+                rados_striper_append(self.striper, name_str.as_ptr(), data.as_ptr() as *const c_char, data.len());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read up to `len` bytes at `offset`.  The read is short when it runs
+    /// past the end of the object.
+    pub fn read(&self, name: &str, len: usize, offset: u64) -> RadosResult<Vec<u8>> {
+        let name_str = CString::new(name)?;
+        let mut buffer: Vec<u8> = Vec::with_capacity(len);
+        unsafe {
+            let ret_code = rados_striper_read(
+                self.striper,
+                name_str.as_ptr(),
+                buffer.as_mut_ptr() as *mut c_char,
+                len,
+                offset,
+            );
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+            buffer.set_len(ret_code as usize);
+        }
+        Ok(buffer)
+    }
+
+    /// Truncate or extend the object to `size` bytes
+    pub fn trunc(&self, name: &str, size: u64) -> RadosResult<()> {
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code = rados_striper_trunc(self.striper, name_str.as_ptr(), size);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the object and every RADOS object backing it
+    pub fn remove(&self, name: &str) -> RadosResult<()> {
+        let name_str = CString::new(name)?;
+        unsafe {
+            let ret_code = rados_striper_remove(self.striper, name_str.as_ptr());
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok(())
+    }
+
+    /// The logical size and modification time of the object
+    pub fn stat(&self, name: &str) -> RadosResult<(u64, SystemTime)> {
+        let name_str = CString::new(name)?;
+        let mut size: u64 = 0;
+        let mut mtime: time_t = 0;
+        unsafe {
+            let ret_code = rados_striper_stat(self.striper, name_str.as_ptr(), &mut size, &mut mtime);
+            if ret_code < 0 {
+                return Err(RadosError::new(get_error(ret_code)?));
+            }
+        }
+        Ok((size, UNIX_EPOCH + Duration::from_secs(mtime as u64)))
+    }
+
+    /// The raw handle for use with the functions in `libradosstriper`
+    pub fn as_raw(&self) -> rados_striper_t {
+        self.striper
+    }
+}
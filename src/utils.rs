@@ -16,6 +16,21 @@ use std::io::Result;
 
 use std::process::{Command, Output};
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_truncates_long_output() {
+        assert_eq!(log_snippet("HEALTH_OK"), "HEALTH_OK");
+
+        let long = "é".repeat(600);
+        let snippet = log_snippet(&long);
+        assert!(snippet.starts_with(&"é".repeat(512)));
+        assert!(snippet.ends_with("... (176 more bytes)"));
+    }
+}
+
 /// run_cli - pass in a String of a normal command line
 ///
 /// The function will split the options into words to supply to the low_level
@@ -33,3 +48,20 @@ pub fn run_cli(cmd_line: &str) -> Result<(Output)> {
     let output = try!(Command::new("sh").arg("-c").arg(cmd_line).output());
     Ok(output)
 }
+
+// Command output can run to megabytes for things like `pg dump` so only the
+// start of it is logged.
+const LOG_SNIPPET_LEN: usize = 1024;
+
+/// Shorten command output for logging.  Anything past the first
+/// `LOG_SNIPPET_LEN` bytes is replaced by a note of how much was cut.
+pub(crate) fn log_snippet(output: &str) -> String {
+    if output.len() <= LOG_SNIPPET_LEN {
+        return output.to_string();
+    }
+    let mut end = LOG_SNIPPET_LEN;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... ({} more bytes)", &output[..end], output.len() - end)
+}
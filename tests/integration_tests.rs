@@ -3,10 +3,24 @@ extern crate serde;
 extern crate serde_json;
 
 
-use ceph::cmd::{ClusterHealth, CrushTree, MonStatus};
+use ceph::cmd::{AuthDump, ClusterFeatures, ClusterHealth, ClusterStatus, CommandDescription, CrushTree, HealthReport,
+                HealthStatus, LogEntry, MdsStat, MonStatus, ObjectPlacement, OsdFind, OsdMap, OsdMetadata, ServiceMap};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::Read;
 
+#[test]
+fn test_auth_list_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/auth_list-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let auth: AuthDump = serde_json::from_str(&json).unwrap();
+    println!("auth_list: {:#?}", auth);
+}
+
 #[test]
 fn test_ceph_health_jewel() {
     let json = {
@@ -19,6 +33,76 @@ fn test_ceph_health_jewel() {
     println!("cluster_health: {:#?}", status);
 }
 
+#[test]
+fn test_get_command_descriptions_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/get_command_descriptions-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let commands: BTreeMap<String, CommandDescription> = serde_json::from_str(&json).unwrap();
+    let prefixes: Vec<String> = commands.values().map(|desc| desc.prefix()).collect();
+    assert_eq!(prefixes, vec!["pg stat", "osd pool create", "osd pool set"]);
+}
+
+#[test]
+fn test_features_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/features-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let features: ClusterFeatures = serde_json::from_str(&json).unwrap();
+    assert_eq!(features.client.len(), 2);
+    println!("features: {:#?}", features);
+}
+
+#[test]
+fn test_log_last_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/log_last-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let log: Vec<LogEntry> = serde_json::from_str(&json).unwrap();
+    println!("log_last: {:#?}", log);
+}
+
+#[test]
+fn test_mds_stat_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/mds_stat-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let stat: MdsStat = serde_json::from_str(&json).unwrap();
+    println!("mds_stat: {:#?}", stat);
+}
+
+#[test]
+fn test_ceph_health_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/ceph_health-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let health: HealthReport = serde_json::from_str(&json).unwrap();
+    match health {
+        HealthReport::Checks(ref checks) => assert_eq!(checks.checks.len(), 2),
+        HealthReport::Legacy(_) => panic!("expected health checks"),
+    }
+    match health.status() {
+        &HealthStatus::Warn => {},
+        status => panic!("expected HEALTH_WARN, got {:?}", status),
+    }
+    println!("cluster_health: {:#?}", health);
+}
+
 #[test]
 fn test_mon_status_hammer() {
     let json = {
@@ -66,3 +150,77 @@ fn test_osd_tree_jewel() {
     let tree: CrushTree = serde_json::from_str(&json).unwrap();
     println!("osd_tree: {:#?}", tree);
 }
+
+#[test]
+fn test_osd_find_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/osd_find-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let find: OsdFind = serde_json::from_str(&json).unwrap();
+    println!("osd_find: {:#?}", find);
+}
+
+#[test]
+fn test_osd_metadata_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/osd_metadata-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let metadata: OsdMetadata = serde_json::from_str(&json).unwrap();
+    println!("osd_metadata: {:#?}", metadata);
+}
+
+#[test]
+fn test_osd_map_jewel() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/osd_map-jewel").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let placement: ObjectPlacement = serde_json::from_str(&json).unwrap();
+    println!("osd_map: {:#?}", placement);
+}
+
+#[test]
+fn test_osd_dump_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/osd_dump-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let osd_map: OsdMap = serde_json::from_str(&json).unwrap();
+    println!("osd_dump: {:#?}", osd_map);
+}
+
+#[test]
+fn test_service_dump_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/service_dump-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let service_map: ServiceMap = serde_json::from_str(&json).unwrap();
+    assert_eq!(service_map.services["rgw"].daemons.len(), 1);
+    println!("service_dump: {:#?}", service_map);
+}
+
+#[test]
+fn test_status_luminous() {
+    let json = {
+        let mut buff = String::new();
+        let mut f = File::open("tests/status-luminous").unwrap();
+        f.read_to_string(&mut buff).unwrap();
+        buff
+    };
+    let status: ClusterStatus = serde_json::from_str(&json).unwrap();
+    assert_eq!(status.osdmap.num_up_osds, 6);
+    println!("status: {:#?}", status);
+}